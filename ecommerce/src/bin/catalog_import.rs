@@ -0,0 +1,88 @@
+use std::env;
+use std::fs;
+use std::process;
+
+use ecommerce::catalog::Catalog;
+use ecommerce::csv::{self, ImportMode, ImportOptions};
+use ecommerce::error;
+use ecommerce::storage::{FileStorage, Storage};
+
+fn usage() -> ! {
+    eprintln!("Usage: catalog_import <store.jsonl> <products.csv> [--dry-run] [--upsert] [--export-stock] [--out <file>]");
+    eprintln!("Imports into the store's catalog and stock ledger; the store is created if missing.");
+    process::exit(2);
+}
+
+fn fail(message: &str, err: &dyn std::error::Error) -> ! {
+    eprintln!("❌ {}: {}", message, error::report(err));
+    process::exit(1);
+}
+
+fn main() {
+    let mut store_path = None;
+    let mut path = None;
+    let mut options = ImportOptions { mode: ImportMode::InsertOnly, dry_run: false };
+    let mut export_stock = false;
+    let mut out = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => options.dry_run = true,
+            "--upsert" => options.mode = ImportMode::Upsert,
+            "--export-stock" => export_stock = true,
+            "--out" => out = Some(args.next().unwrap_or_else(|| usage())),
+            flag if flag.starts_with("--") => usage(),
+            _ if store_path.is_none() => store_path = Some(arg),
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let store_path = store_path.unwrap_or_else(|| usage());
+    let path = path.unwrap_or_else(|| usage());
+
+    let text = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", path, e);
+        process::exit(1);
+    });
+
+    let mut storage = FileStorage::open(&store_path).unwrap_or_else(|e| fail(&format!("Failed to open {}", store_path), &e));
+    let mut catalog = Catalog::new();
+    for product in storage.products() {
+        catalog.upsert(product);
+    }
+    let mut inventory = storage.load_inventory().unwrap_or_else(|e| fail("Failed to load stock", &e));
+    let report = csv::import_products(&text, &mut catalog, &mut inventory, options).unwrap_or_else(|e| fail("Import failed", &e));
+
+    println!("📥 Importing {} into {}", path, store_path);
+    report.display();
+
+    if !options.dry_run {
+        for id in report.inserted.iter().chain(&report.updated) {
+            let product = catalog.get(*id).expect("Imported products are in the catalog");
+            storage.put_product(product).unwrap_or_else(|e| fail("Failed to save product", &e));
+        }
+        storage.save_inventory(&inventory).unwrap_or_else(|e| fail("Failed to save stock", &e));
+    }
+
+    if !options.dry_run {
+        let exported = if export_stock {
+            csv::export_stock(&inventory)
+        } else {
+            csv::export_products(&catalog, &inventory)
+        };
+        match out {
+            Some(out) => {
+                fs::write(&out, exported).unwrap_or_else(|e| {
+                    eprintln!("Failed to write {}: {}", out, e);
+                    process::exit(1);
+                });
+                println!("📤 Exported to {}", out);
+            }
+            None => print!("\n{}", exported),
+        }
+    }
+    if !report.is_clean() {
+        process::exit(1);
+    }
+}
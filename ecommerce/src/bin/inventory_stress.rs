@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::env;
+use std::process;
+use std::thread;
+
+use ecommerce::inventory::Inventory;
+
+const PRODUCTS: u32 = 5;
+const INITIAL_STOCK: u32 = 20_000;
+
+/// Small xorshift generator so each buyer's run is reproducible from the seed
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u32) -> u32 {
+        (self.next() % bound as u64) as u32
+    }
+}
+
+#[derive(Default)]
+struct BuyerTally {
+    sold: HashMap<u32, u32>,
+    returned: HashMap<u32, u32>,
+    successful_orders: u32,
+    rejected_orders: u32,
+}
+
+fn arg_or(index: usize, default: u64) -> u64 {
+    env::args()
+        .nth(index)
+        .map(|arg| arg.parse().unwrap_or_else(|_| {
+            eprintln!("Invalid argument: {}", arg);
+            process::exit(2);
+        }))
+        .unwrap_or(default)
+}
+
+fn main() {
+    let buyers = arg_or(1, 16) as u32;
+    let orders_per_buyer = arg_or(2, 2_000) as u32;
+    let seed = arg_or(3, 42);
+
+    println!("🧪 Inventory Stress Test");
+    println!("========================");
+    println!("Buyers: {}, orders per buyer: {}, seed: {}\n", buyers, orders_per_buyer, seed);
+
+    let mut inventory = Inventory::new();
+    for product_id in 1..=PRODUCTS {
+        inventory.add_stock(product_id, INITIAL_STOCK).expect("Failed to receive stock");
+    }
+    let shared = inventory.into_shared();
+
+    let handles: Vec<_> = (0..buyers)
+        .map(|buyer| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let mut rng = Rng(seed.wrapping_mul(6364136223846793005).wrapping_add(buyer as u64 + 1) | 1);
+                let mut tally = BuyerTally::default();
+
+                for _ in 0..orders_per_buyer {
+                    let lines: Vec<(u32, u32)> = (0..1 + rng.below(3))
+                        .map(|_| (1 + rng.below(PRODUCTS), 1 + rng.below(4)))
+                        .collect();
+
+                    if shared.remove_stock_all(&lines).is_err() {
+                        tally.rejected_orders += 1;
+                        continue;
+                    }
+                    tally.successful_orders += 1;
+
+                    // Roughly one order in ten is sent back and restocked
+                    let returned = rng.below(10) == 0;
+                    for &(product_id, quantity) in &lines {
+                        *tally.sold.entry(product_id).or_insert(0) += quantity;
+                        if returned {
+                            shared.add_stock(product_id, quantity).expect("Returned units were sold from this level");
+                            *tally.returned.entry(product_id).or_insert(0) += quantity;
+                        }
+                    }
+                }
+                tally
+            })
+        })
+        .collect();
+
+    let mut sold: HashMap<u32, u32> = HashMap::new();
+    let mut returned: HashMap<u32, u32> = HashMap::new();
+    let (mut successful, mut rejected) = (0, 0);
+    for handle in handles {
+        let tally = handle.join().expect("Buyer thread panicked");
+        for (product_id, quantity) in tally.sold {
+            *sold.entry(product_id).or_insert(0) += quantity;
+        }
+        for (product_id, quantity) in tally.returned {
+            *returned.entry(product_id).or_insert(0) += quantity;
+        }
+        successful += tally.successful_orders;
+        rejected += tally.rejected_orders;
+    }
+
+    println!("Orders accepted: {}, rejected: {}\n", successful, rejected);
+
+    let mut conserved = true;
+    for product_id in 1..=PRODUCTS {
+        let remaining = shared.check_stock(product_id);
+        let sold = sold.get(&product_id).copied().unwrap_or(0);
+        let returned = returned.get(&product_id).copied().unwrap_or(0);
+        let expected = INITIAL_STOCK + returned - sold;
+        let ok = remaining == expected;
+        conserved &= ok;
+
+        println!(
+            "{} Product ID: {}, initial: {}, sold: {}, returned: {}, remaining: {} (expected {})",
+            if ok { "✅" } else { "❌" },
+            product_id, INITIAL_STOCK, sold, returned, remaining, expected
+        );
+    }
+
+    if conserved {
+        println!("\n✨ Stock conserved across all buyers");
+    } else {
+        println!("\n❌ Stock was not conserved");
+        process::exit(1);
+    }
+}
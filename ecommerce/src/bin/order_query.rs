@@ -0,0 +1,296 @@
+use std::env;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+
+use ecommerce::order::Order;
+use ecommerce::order_query::{self, OrderQuery};
+use ecommerce::storage::{FileStorage, Storage};
+
+/// Longest request line accepted, including the query string
+const MAX_REQUEST_LINE: u64 = 8 * 1024;
+/// Most header bytes read after the request line
+const MAX_HEADERS: u64 = 16 * 1024;
+/// How long a client may take to send its whole request, however it paces the bytes
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn usage() -> ! {
+    eprintln!("Usage: order_query <store.jsonl> [--user <id>] [--status <s,...>] [--from <date>] [--to <date>]");
+    eprintln!("                   [--min-total <n>] [--max-total <n>] [--product <id>] [--sort [-]id|created|total]");
+    eprintln!("                   [--page <n>] [--per-page <n>] [--json]");
+    eprintln!("       order_query <store.jsonl> --serve <address>");
+    eprintln!("Dates are YYYY-MM-DD, RFC 3339, 'today' or '-7d' for seven days ago.");
+    process::exit(2);
+}
+
+/// Answers `GET /orders?...` requests from a snapshot of the store taken at startup
+fn serve(address: &str, orders: &[Order]) {
+    let listener = TcpListener::bind(address).unwrap_or_else(|e| {
+        eprintln!("Failed to listen on {}: {}", address, e);
+        process::exit(1);
+    });
+    println!("🌐 Serving {} order(s) on http://{}/orders", orders.len(), address);
+    accept(listener, orders, REQUEST_TIMEOUT);
+}
+
+/// Handles each connection on its own thread, so a slow client never holds up the others
+fn accept(listener: TcpListener, orders: &[Order], timeout: Duration) {
+    thread::scope(|scope| {
+        for stream in listener.incoming().flatten() {
+            scope.spawn(move || {
+                let result = DeadlineStream::new(stream, timeout).and_then(|stream| respond(stream, orders));
+                if let Err(e) = result {
+                    eprintln!("Request failed: {}", e);
+                }
+            });
+        }
+    });
+}
+
+/// A connection whose reads all share one deadline, unlike a socket timeout that restarts on every read
+struct DeadlineStream {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl DeadlineStream {
+    fn new(stream: TcpStream, timeout: Duration) -> io::Result<Self> {
+        stream.set_write_timeout(Some(timeout))?;
+        Ok(DeadlineStream { stream, deadline: Instant::now() + timeout })
+    }
+}
+
+impl Read for DeadlineStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "request deadline passed"));
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for DeadlineStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+fn error_body(code: &str, message: &str) -> String {
+    format!("{{\"error\":{{\"code\":\"{}\",\"message\":\"{}\"}}}}", code, message)
+}
+
+/// Reads one request and writes the response; oversized or slow requests are answered without reading the rest
+fn respond<S: Read + Write>(stream: S, orders: &[Order]) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let (status, body) = match read_request(&mut reader) {
+        Ok(Ok(request_line)) => {
+            let mut parts = request_line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some("GET"), Some(target)) => order_query::api_response(target, orders, Utc::now()),
+                _ => (405, error_body("METHOD_NOT_ALLOWED", "Only GET is supported")),
+            }
+        }
+        Ok(Err(rejected)) => rejected,
+        Err(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => {
+            (408, error_body("REQUEST_TIMEOUT", "Request was not received in time"))
+        }
+        Err(e) => return Err(e),
+    };
+    reply(reader.into_inner(), status, &body)
+}
+
+/// Reads the request line and skips the headers, or gives the response for a request over the size limits
+fn read_request(reader: &mut impl BufRead) -> io::Result<Result<String, (u16, String)>> {
+    let mut request_line = String::new();
+    reader.take(MAX_REQUEST_LINE).read_line(&mut request_line)?;
+    if !request_line.ends_with('\n') && request_line.len() as u64 == MAX_REQUEST_LINE {
+        return Ok(Err((414, error_body("URI_TOO_LONG", "Request line is too long"))));
+    }
+
+    // Headers are not needed; read them so the client sees a clean close
+    let mut headers = reader.take(MAX_HEADERS);
+    loop {
+        let mut line = String::new();
+        headers.read_line(&mut line)?;
+        if !line.ends_with('\n') && headers.limit() == 0 {
+            return Ok(Err((431, error_body("HEADERS_TOO_LARGE", "Request headers are too large"))));
+        }
+        if line.trim_end().is_empty() {
+            break;
+        }
+    }
+    Ok(Ok(request_line))
+}
+
+fn reply(mut stream: impl Write, status: u16, body: &str) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        408 => "Request Timeout",
+        414 => "URI Too Long",
+        431 => "Request Header Fields Too Large",
+        _ => "Method Not Allowed",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, reason, body.len(), body
+    )
+}
+
+fn main() {
+    let mut path = None;
+    let mut params = Vec::new();
+    let mut json = false;
+    let mut address = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--serve" => address = Some(args.next().unwrap_or_else(|| usage())),
+            flag if flag.starts_with("--") => {
+                // Flags are the API's query parameters, e.g. --per-page 5 is per_page=5
+                let name = flag.trim_start_matches("--").replace('-', "_");
+                params.push((name, args.next().unwrap_or_else(|| usage())));
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage());
+
+    let storage = FileStorage::open(&path).unwrap_or_else(|e| {
+        eprintln!("Failed to open {}: {}", path, e);
+        process::exit(1);
+    });
+    let orders = storage.orders();
+
+    if let Some(address) = address {
+        serve(&address, &orders);
+        return;
+    }
+
+    let query = OrderQuery::from_params(params, Utc::now()).unwrap_or_else(|e| {
+        eprintln!("❌ {}", e);
+        process::exit(2);
+    });
+    let page = query.run(&orders);
+    if json {
+        println!("{}", page.to_json());
+    } else {
+        page.display();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A connection whose request is fixed up front and whose response is captured
+    struct FakeStream {
+        request: Cursor<Vec<u8>>,
+        response: Vec<u8>,
+    }
+
+    impl Read for FakeStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.request.read(buf)
+        }
+    }
+
+    impl Write for FakeStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.response.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn status_line(request: String) -> String {
+        let mut stream = FakeStream { request: Cursor::new(request.into_bytes()), response: Vec::new() };
+        respond(&mut stream, &[]).unwrap();
+        String::from_utf8(stream.response).unwrap().lines().next().unwrap_or_default().to_string()
+    }
+
+    #[test]
+    fn answers_ordinary_requests() {
+        assert_eq!(status_line("GET /orders?page=2 HTTP/1.1\r\nHost: x\r\n\r\n".into()), "HTTP/1.1 200 OK");
+        assert_eq!(status_line("GET /orders?from=-99999999999d HTTP/1.1\r\n\r\n".into()), "HTTP/1.1 400 Bad Request");
+        assert_eq!(status_line("GET /other HTTP/1.1\r\n\r\n".into()), "HTTP/1.1 404 Not Found");
+        assert_eq!(status_line("POST /orders HTTP/1.1\r\n\r\n".into()), "HTTP/1.1 405 Method Not Allowed");
+    }
+
+    #[test]
+    fn long_request_lines_get_414() {
+        let target = format!("/orders?user={}", "1".repeat(MAX_REQUEST_LINE as usize));
+        assert_eq!(status_line(format!("GET {} HTTP/1.1\r\n\r\n", target)), "HTTP/1.1 414 URI Too Long");
+        // Just under the limit is still read in full
+        let target = format!("/orders?{}", "a".repeat(MAX_REQUEST_LINE as usize - 40));
+        assert_eq!(status_line(format!("GET {} HTTP/1.1\r\n\r\n", target)), "HTTP/1.1 400 Bad Request");
+    }
+
+    #[test]
+    fn oversized_headers_get_431() {
+        let header = format!("X-Padding: {}\r\n", "a".repeat(1024));
+        let many = header.repeat(MAX_HEADERS as usize / header.len() + 1);
+        assert_eq!(
+            status_line(format!("GET /orders HTTP/1.1\r\n{}\r\n", many)),
+            "HTTP/1.1 431 Request Header Fields Too Large"
+        );
+        let few = header.repeat(4);
+        assert_eq!(status_line(format!("GET /orders HTTP/1.1\r\n{}\r\n", few)), "HTTP/1.1 200 OK");
+    }
+
+    #[test]
+    fn trickling_clients_are_cut_off_at_the_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            // Each byte arrives well within the deadline, but the request as a whole does not
+            for byte in b"GET" {
+                stream.write_all(&[*byte]).unwrap();
+                thread::sleep(Duration::from_millis(100));
+            }
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let started = Instant::now();
+        respond(DeadlineStream::new(stream, Duration::from_millis(250)).unwrap(), &[]).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(1_000), "{:?}", started.elapsed());
+        assert!(client.join().unwrap().starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    }
+
+    #[test]
+    fn a_silent_connection_does_not_block_other_clients() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || accept(listener, &[], Duration::from_secs(10)));
+
+        let _silent = TcpStream::connect(address).unwrap();
+        let mut client = TcpStream::connect(address).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        client.write_all(b"GET /orders HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    }
+}
@@ -0,0 +1,63 @@
+use std::env;
+use std::process;
+use std::str::FromStr;
+
+use ecommerce::simulation::{self, Action};
+
+/// Upper bound on `--sessions`; every session's actions are kept in memory for shrinking
+const MAX_SESSIONS: u32 = 1_000_000;
+
+fn usage() -> ! {
+    eprintln!("Usage: shop_sim [--seed <n>] [--sessions <1-{}>]", MAX_SESSIONS);
+    process::exit(2);
+}
+
+fn parse_number<T: FromStr>(value: Option<String>) -> T {
+    value.and_then(|v| v.parse().ok()).unwrap_or_else(|| usage())
+}
+
+fn main() {
+    let mut seed = 42;
+    let mut sessions = 5_000;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => seed = parse_number(args.next()),
+            "--sessions" => sessions = parse_number(args.next()),
+            _ => usage(),
+        }
+    }
+    if !(1..=MAX_SESSIONS).contains(&sessions) {
+        usage();
+    }
+
+    println!("🧪 Shop Simulation");
+    println!("==================");
+    println!("Seed: {}, sessions: {}\n", seed, sessions);
+
+    let actions = simulation::generate(seed, sessions);
+    match simulation::run(&actions) {
+        Ok(summary) => {
+            println!("Steps: {} ({} rejected by the shop)", summary.steps, summary.rejected);
+            println!("Orders by status:");
+            for (status, count) in &summary.orders_by_status {
+                println!("  {:<17} {}", status, count);
+            }
+            println!("Units left on the shelf: {}", summary.stock_left);
+            println!("\n✅ All invariants held");
+        }
+        Err((step, violation)) => {
+            println!("❌ Step {}: {}", step + 1, violation);
+            println!("\nShrinking trace...");
+            let trace: Vec<Action> = simulation::shrink(&actions);
+            println!("Minimal reproduction ({} steps, replay on a freshly seeded shop):", trace.len());
+            for (index, action) in trace.iter().enumerate() {
+                println!("  {:>3}. {}", index + 1, action);
+            }
+            if let Err((_, violation)) = simulation::run(&trace) {
+                println!("  => {}", violation);
+            }
+            process::exit(1);
+        }
+    }
+}
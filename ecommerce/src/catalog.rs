@@ -0,0 +1,49 @@
+use std::collections::BTreeMap;
+
+use crate::product::Product;
+
+/// The products the shop sells, keyed and listed by id
+#[derive(Debug, Default)]
+pub struct Catalog {
+    products: BTreeMap<u32, Product>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces a product, returning the previous version if there was one
+    pub fn upsert(&mut self, product: Product) -> Option<Product> {
+        self.products.insert(product.id, product)
+    }
+
+    pub fn remove(&mut self, product_id: u32) -> Option<Product> {
+        self.products.remove(&product_id)
+    }
+
+    pub fn get(&self, product_id: u32) -> Option<&Product> {
+        self.products.get(&product_id)
+    }
+
+    pub fn contains(&self, product_id: u32) -> bool {
+        self.products.contains_key(&product_id)
+    }
+
+    pub fn products(&self) -> impl Iterator<Item = &Product> {
+        self.products.values()
+    }
+
+    /// Every product, mutably; pass to `PriceHistory::refresh` to bring prices up to date
+    pub fn products_mut(&mut self) -> impl Iterator<Item = &mut Product> {
+        self.products.values_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.products.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.products.is_empty()
+    }
+}
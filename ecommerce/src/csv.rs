@@ -0,0 +1,402 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::catalog::Catalog;
+use crate::inventory::Inventory;
+use crate::product::Product;
+
+/// Column order used for product export; import accepts them in any order
+pub const PRODUCT_COLUMNS: [&str; 6] = ["id", "name", "price", "description", "category", "initial_stock"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Rows whose id is already in the catalog are rejected
+    InsertOnly,
+    /// Rows whose id is already in the catalog replace the existing product
+    Upsert,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImportOptions {
+    pub mode: ImportMode,
+    /// Validate and report without touching the catalog or inventory
+    pub dry_run: bool,
+}
+
+/// A row that could not be imported; `line` is 1-based and counts the header
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub inserted: Vec<u32>,
+    pub updated: Vec<u32>,
+    /// Updated products whose file price differs from the catalog, with that price.
+    /// The catalog price is kept; apply these with `PriceHistory::set_price`.
+    pub price_changes: Vec<(u32, f64)>,
+    pub errors: Vec<RowError>,
+    pub dry_run: bool,
+}
+
+impl ImportReport {
+    pub fn is_clean(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn display(&self) {
+        let verb = if self.dry_run { "would be" } else { "were" };
+        println!("{} product(s) {} inserted, {} {} updated, {} row error(s)",
+            self.inserted.len(), verb, self.updated.len(), verb, self.errors.len());
+        for (product_id, price) in &self.price_changes {
+            println!("  💲 Product {} is priced {:.2} in the file; price kept, change it through the price history", product_id, price);
+        }
+        for error in &self.errors {
+            println!("  ⚠️ {}", error);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CsvError {
+    MissingHeader,
+    MissingColumn(String),
+    UnterminatedQuote { line: usize },
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CsvError::MissingHeader => write!(f, "CSV input has no header row"),
+            CsvError::MissingColumn(column) => write!(f, "CSV header is missing column '{}'", column),
+            CsvError::UnterminatedQuote { line } => write!(f, "Unterminated quoted field starting on line {}", line),
+        }
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+/// Splits CSV text into records, each tagged with the line it starts on.
+///
+/// Handles quoted fields containing commas, newlines and doubled quotes.
+/// Blank lines are skipped.
+pub fn parse_records(text: &str) -> Result<Vec<(usize, Vec<String>)>, CsvError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', true) => in_quotes = false,
+            ('"', false) if field.is_empty() => in_quotes = true,
+            (',', false) => record.push(std::mem::take(&mut field)),
+            ('\r', false) if chars.peek() == Some(&'\n') => {}
+            ('\n', false) => {
+                record.push(std::mem::take(&mut field));
+                if !(record.len() == 1 && record[0].is_empty()) {
+                    records.push((record_line, std::mem::take(&mut record)));
+                }
+                record.clear();
+                line += 1;
+                record_line = line;
+            }
+            ('\n', true) => {
+                field.push(c);
+                line += 1;
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err(CsvError::UnterminatedQuote { line: record_line });
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push((record_line, record));
+    }
+    Ok(records)
+}
+
+/// Quotes a field if it contains a delimiter, quote or line break
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn write_row(out: &mut String, fields: &[String]) {
+    let escaped: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+    out.push_str(&escaped.join(","));
+    out.push('\n');
+}
+
+/// Validates one data row into a product and its initial stock
+fn parse_product(fields: &[String], columns: &[usize; 6]) -> Result<(Product, u32), String> {
+    let field = |index: usize| fields.get(columns[index]).map(|f| f.trim()).unwrap_or("");
+
+    let id: u32 = field(0)
+        .parse()
+        .map_err(|_| format!("invalid id '{}'", field(0)))?;
+    let name = field(1);
+    if name.is_empty() {
+        return Err(String::from("name cannot be empty"));
+    }
+    let price: f64 = field(2)
+        .parse()
+        .ok()
+        .filter(|p: &f64| p.is_finite() && *p >= 0.0)
+        .ok_or_else(|| format!("invalid price '{}'", field(2)))?;
+    let initial_stock: u32 = match field(5) {
+        "" => 0,
+        raw => raw.parse().map_err(|_| format!("invalid initial stock '{}'", raw))?,
+    };
+
+    let product = Product::new(id, name.to_string(), price, field(3).to_string()).with_category(field(4));
+    Ok((product, initial_stock))
+}
+
+/// Imports products from CSV text.
+///
+/// Valid rows are applied even when other rows fail; each failure is
+/// reported with its line number. Initial stock is received into the
+/// inventory for newly inserted products only, so re-importing a file in
+/// upsert mode updates product details without double-counting stock.
+/// Updated products keep their stock policy, bundle components and price;
+/// differing file prices are listed in `price_changes` instead.
+pub fn import_products(
+    text: &str,
+    catalog: &mut Catalog,
+    inventory: &mut Inventory,
+    options: ImportOptions,
+) -> Result<ImportReport, CsvError> {
+    let mut records = parse_records(text)?.into_iter();
+    let (_, header) = records.next().ok_or(CsvError::MissingHeader)?;
+
+    let mut columns = [0usize; 6];
+    for (slot, name) in columns.iter_mut().zip(PRODUCT_COLUMNS) {
+        *slot = header
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(name))
+            .ok_or_else(|| CsvError::MissingColumn(name.to_string()))?;
+    }
+
+    let mut report = ImportReport {
+        dry_run: options.dry_run,
+        ..ImportReport::default()
+    };
+    let mut seen = HashSet::new();
+
+    for (line, fields) in records {
+        let (mut product, initial_stock) = match parse_product(&fields, &columns) {
+            Ok(parsed) => parsed,
+            Err(message) => {
+                report.errors.push(RowError { line, message });
+                continue;
+            }
+        };
+        if !seen.insert(product.id) {
+            report.errors.push(RowError { line, message: format!("duplicate id {} in file", product.id) });
+            continue;
+        }
+
+        let exists = catalog.contains(product.id);
+        if exists && options.mode == ImportMode::InsertOnly {
+            report.errors.push(RowError { line, message: format!("product {} already exists", product.id) });
+            continue;
+        }
+
+        let id = product.id;
+        if let Some(existing) = catalog.get(id) {
+            // The file has no columns for these; keep what the catalog already knows
+            product.stock_policy = existing.stock_policy.clone();
+            product.components = existing.components.clone();
+            // Prices only change through the price history, so the file price is reported rather than applied
+            if product.price() != existing.price() {
+                report.price_changes.push((id, product.price()));
+                product.set_price(existing.price());
+            }
+        }
+        if !options.dry_run {
+            if !exists {
+                if let Err(e) = inventory.add_stock(id, initial_stock) {
+                    report.errors.push(RowError { line, message: e.to_string() });
+                    continue;
+                }
+            }
+            catalog.upsert(product);
+        }
+        if exists {
+            report.updated.push(id);
+        } else {
+            report.inserted.push(id);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Exports the catalog in the import format, using current stock as `initial_stock`
+pub fn export_products(catalog: &Catalog, inventory: &Inventory) -> String {
+    let mut out = String::new();
+    write_row(&mut out, &PRODUCT_COLUMNS.map(String::from));
+    for product in catalog.products() {
+        write_row(&mut out, &[
+            product.id.to_string(),
+            product.name.clone(),
+            format!("{:.2}", product.price()),
+            product.description.clone(),
+            product.category.clone(),
+            inventory.check_stock(product.id).to_string(),
+        ]);
+    }
+    out
+}
+
+/// Exports every stock level, sorted by product id
+pub fn export_stock(inventory: &Inventory) -> String {
+    let mut out = String::from("product_id,quantity\n");
+    for (product_id, quantity) in inventory.levels() {
+        out.push_str(&format!("{},{}\n", product_id, quantity));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::product::StockPolicy;
+
+    const HEADER: &str = "id,name,price,description,category,initial_stock\n";
+
+    fn import(text: &str, catalog: &mut Catalog, inventory: &mut Inventory, mode: ImportMode, dry_run: bool) -> ImportReport {
+        import_products(text, catalog, inventory, ImportOptions { mode, dry_run }).unwrap()
+    }
+
+    #[test]
+    fn parses_quoted_fields_and_tracks_lines() {
+        let records = parse_records("a,b\n\n\"x, \"\"y\"\"\",\"two\nlines\"\r\nlast,1").unwrap();
+        assert_eq!(records, vec![
+            (1, vec!["a".to_string(), "b".to_string()]),
+            (3, vec!["x, \"y\"".to_string(), "two\nlines".to_string()]),
+            (5, vec!["last".to_string(), "1".to_string()]),
+        ]);
+        assert_eq!(parse_records("a\n\"open"), Err(CsvError::UnterminatedQuote { line: 2 }));
+    }
+
+    #[test]
+    fn header_columns_may_come_in_any_order() {
+        let mut catalog = Catalog::new();
+        let mut inventory = Inventory::new();
+        let text = "Price,ID,name,description,category,initial_stock\n2.5,9,Cup,,kitchen,\n";
+        let report = import(text, &mut catalog, &mut inventory, ImportMode::InsertOnly, false);
+        assert_eq!(report.inserted, vec![9]);
+        assert_eq!(catalog.get(9).unwrap().price(), 2.5);
+        assert_eq!(inventory.check_stock(9), 0);
+
+        let missing = import_products("id,name\n", &mut catalog, &mut inventory,
+            ImportOptions { mode: ImportMode::InsertOnly, dry_run: false });
+        assert_eq!(missing.unwrap_err(), CsvError::MissingColumn("price".into()));
+    }
+
+    #[test]
+    fn bad_rows_are_reported_and_good_rows_applied() {
+        let mut catalog = Catalog::new();
+        let mut inventory = Inventory::new();
+        let text = format!("{}1,Lamp,12.5,,home,4\nx,Bad,1,,,\n2,,1,,,\n3,Neg,-1,,,\n1,Again,1,,,\n", HEADER);
+        let report = import(&text, &mut catalog, &mut inventory, ImportMode::InsertOnly, false);
+        assert_eq!(report.inserted, vec![1]);
+        let lines: Vec<usize> = report.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![3, 4, 5, 6]);
+        assert_eq!(inventory.check_stock(1), 4);
+    }
+
+    #[test]
+    fn upsert_updates_details_without_restocking() {
+        let mut catalog = Catalog::new();
+        let mut inventory = Inventory::new();
+        import(&format!("{}1,Lamp,12.5,,home,4\n", HEADER), &mut catalog, &mut inventory, ImportMode::InsertOnly, false);
+        let backorderable = catalog.get(1).unwrap().clone().with_stock_policy(StockPolicy::Backorder);
+        catalog.upsert(backorderable);
+
+        let again = format!("{}1,Lamp Pro,15,,home,99\n", HEADER);
+        let rejected = import(&again, &mut catalog, &mut inventory, ImportMode::InsertOnly, false);
+        assert_eq!(rejected.errors.len(), 1);
+
+        let report = import(&again, &mut catalog, &mut inventory, ImportMode::Upsert, false);
+        assert_eq!(report.updated, vec![1]);
+        assert_eq!(report.price_changes, vec![(1, 15.0)]);
+        let lamp = catalog.get(1).unwrap();
+        assert_eq!((lamp.name.as_str(), lamp.price()), ("Lamp Pro", 12.5));
+        assert_eq!(lamp.stock_policy, StockPolicy::Backorder);
+        assert_eq!(inventory.check_stock(1), 4);
+    }
+
+    #[test]
+    fn file_prices_go_through_the_price_history() {
+        use crate::price_history::PriceHistory;
+        use chrono::{TimeZone, Utc};
+
+        let mut catalog = Catalog::new();
+        let mut inventory = Inventory::new();
+        import(&format!("{}1,Lamp,12.5,,home,4\n2,Desk,80,,home,1\n", HEADER), &mut catalog, &mut inventory, ImportMode::Upsert, false);
+        let now = Utc.with_ymd_and_hms(2026, 4, 1, 0, 0, 0).unwrap();
+        let mut history = PriceHistory::new();
+        for product in catalog.products() {
+            history.track(product, now);
+        }
+
+        let report = import(&format!("{}1,Lamp,14,,home,0\n2,Desk,80.00,,home,0\n", HEADER), &mut catalog, &mut inventory, ImportMode::Upsert, true);
+        assert_eq!(report.price_changes, vec![(1, 14.0)]);
+        assert_eq!(catalog.get(1).unwrap().price(), 12.5);
+
+        let report = import(&format!("{}1,Lamp,14,,home,0\n2,Desk,80.00,,home,0\n", HEADER), &mut catalog, &mut inventory, ImportMode::Upsert, false);
+        for &(product_id, price) in &report.price_changes {
+            history.set_price(product_id, price, now + chrono::Duration::days(1)).unwrap();
+        }
+        assert_eq!(catalog.get(1).unwrap().price(), 12.5);
+        let mut lamp = catalog.get(1).unwrap().clone();
+        assert_eq!(history.refresh([&mut lamp], now + chrono::Duration::days(1)), vec![1]);
+        assert_eq!(lamp.price(), 14.0);
+        assert_eq!(history.history(1).len(), 2);
+    }
+
+    #[test]
+    fn dry_run_changes_nothing() {
+        let mut catalog = Catalog::new();
+        let mut inventory = Inventory::new();
+        let report = import(&format!("{}1,Lamp,12.5,,home,4\n", HEADER), &mut catalog, &mut inventory, ImportMode::Upsert, true);
+        assert_eq!(report.inserted, vec![1]);
+        assert!(catalog.is_empty());
+        assert!(inventory.movements().is_empty());
+    }
+
+    #[test]
+    fn export_round_trips_through_import() {
+        let mut catalog = Catalog::new();
+        let mut inventory = Inventory::new();
+        let text = format!("{}1,\"Lamp, large\",12.50,\"Says \"\"hi\"\"\",home,4\n", HEADER);
+        import(&text, &mut catalog, &mut inventory, ImportMode::InsertOnly, false);
+        let exported = export_products(&catalog, &inventory);
+        assert_eq!(exported, text);
+        assert_eq!(export_stock(&inventory), "product_id,quantity\n1,4\n");
+    }
+}
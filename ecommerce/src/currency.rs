@@ -0,0 +1,351 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::csv;
+
+/// Code of the currency catalog prices are stored in
+pub const BASE_CURRENCY: &str = "USD";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Currency {
+    pub code: String,
+    pub symbol: String,
+    pub decimals: u32,
+}
+
+impl Currency {
+    pub fn new(code: &str, symbol: &str, decimals: u32) -> Self {
+        Currency {
+            code: code.to_uppercase(),
+            symbol: symbol.to_string(),
+            decimals,
+        }
+    }
+
+    /// Looks up a well-known currency; unknown codes print as "XYZ " with two decimals
+    pub fn from_code(code: &str) -> Self {
+        match code.trim().to_uppercase().as_str() {
+            "USD" => Currency::new("USD", "$", 2),
+            "EUR" => Currency::new("EUR", "€", 2),
+            "GBP" => Currency::new("GBP", "£", 2),
+            "JPY" => Currency::new("JPY", "¥", 0),
+            "CHF" => Currency::new("CHF", "CHF ", 2),
+            "BGN" => Currency::new("BGN", "лв ", 2),
+            other => Currency::new(other, &format!("{} ", other), 2),
+        }
+    }
+
+    pub fn base() -> Self {
+        Currency::from_code(BASE_CURRENCY)
+    }
+
+    /// Formats an amount already expressed in this currency
+    pub fn format(&self, amount: f64) -> String {
+        format!("{}{:.*}", self.symbol, self.decimals as usize, amount)
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency::base()
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoundingMode {
+    HalfUp,
+    HalfEven,
+    Up,
+    Down,
+}
+
+/// How converted amounts are rounded
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rounding {
+    pub mode: RoundingMode,
+    /// Smallest cash unit, e.g. 0.05 for Swiss francs; `None` rounds to the currency's decimals
+    pub increment: Option<f64>,
+}
+
+impl Rounding {
+    pub fn new(mode: RoundingMode) -> Self {
+        Rounding { mode, increment: None }
+    }
+
+    /// Rounds to multiples of `increment`, which must be a positive amount
+    pub fn with_increment(mut self, increment: f64) -> Result<Self, CurrencyError> {
+        if !increment.is_finite() || increment <= 0.0 {
+            return Err(CurrencyError::InvalidIncrement);
+        }
+        self.increment = Some(increment);
+        Ok(self)
+    }
+
+    /// Rounds an amount; an unusable increment set directly on the field falls back to the currency's decimals
+    pub fn apply(&self, amount: f64, currency: &Currency) -> f64 {
+        let step = self.increment
+            .filter(|step| step.is_finite() && *step > 0.0)
+            .unwrap_or(10f64.powi(-(currency.decimals as i32)));
+        // Trim float noise first so 2.675 is treated as exactly halfway
+        let units = ((amount / step) * 1e6).round() / 1e6;
+        let rounded = match self.mode {
+            RoundingMode::HalfUp => units.round(),
+            RoundingMode::HalfEven => {
+                let floor = units.floor();
+                match (units - floor).partial_cmp(&0.5) {
+                    Some(std::cmp::Ordering::Less) => floor,
+                    Some(std::cmp::Ordering::Greater) => floor + 1.0,
+                    _ if floor % 2.0 == 0.0 => floor,
+                    _ => floor + 1.0,
+                }
+            }
+            RoundingMode::Up => units.ceil(),
+            RoundingMode::Down => units.floor(),
+        };
+        rounded * step
+    }
+}
+
+impl Default for Rounding {
+    fn default() -> Self {
+        Rounding::new(RoundingMode::HalfUp)
+    }
+}
+
+/// Conversion rate from one currency to another, valid from `effective`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExchangeRate {
+    pub from: String,
+    pub to: String,
+    pub rate: f64,
+    pub effective: DateTime<Utc>,
+}
+
+/// The rate and rounding captured on an order at checkout
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrozenRate {
+    pub rate: ExchangeRate,
+    pub rounding: Rounding,
+    pub frozen_at: DateTime<Utc>,
+}
+
+impl FrozenRate {
+    /// Converts a base-currency amount into the order currency
+    pub fn convert(&self, amount: f64, currency: &Currency) -> f64 {
+        self.rounding.apply(amount * self.rate.rate, currency)
+    }
+}
+
+#[derive(Debug)]
+pub enum CurrencyError {
+    NoRate { from: String, to: String },
+    InvalidRate { line: usize, message: String },
+    Io(io::Error),
+    AlreadyFrozen,
+    InvalidIncrement,
+}
+
+impl fmt::Display for CurrencyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CurrencyError::NoRate { from, to } => write!(f, "No exchange rate from {} to {}", from, to),
+            CurrencyError::InvalidRate { line, message } => write!(f, "Invalid rate on line {}: {}", line, message),
+            CurrencyError::Io(_) => write!(f, "Failed to read rate table"),
+            CurrencyError::AlreadyFrozen => write!(f, "Exchange rate is already frozen on this order"),
+            CurrencyError::InvalidIncrement => write!(f, "Rounding increment must be a positive amount"),
+        }
+    }
+}
+
+impl std::error::Error for CurrencyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CurrencyError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CurrencyError {
+    fn from(err: io::Error) -> Self {
+        CurrencyError::Io(err)
+    }
+}
+
+/// Dated exchange rates; the newest rate effective at a given moment wins
+#[derive(Debug, Default)]
+pub struct RateTable {
+    rates: Vec<ExchangeRate>,
+}
+
+impl RateTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, from: &str, to: &str, rate: f64, effective: DateTime<Utc>) {
+        self.rates.push(ExchangeRate {
+            from: from.to_uppercase(),
+            to: to.to_uppercase(),
+            rate,
+            effective,
+        });
+    }
+
+    /// Parses CSV lines of `effective_date,from,to,rate`, e.g. `2026-10-01,USD,EUR,0.92`
+    pub fn parse(text: &str) -> Result<Self, CurrencyError> {
+        let records = csv::parse_records(text)
+            .map_err(|e| CurrencyError::InvalidRate { line: 0, message: e.to_string() })?;
+
+        let mut table = RateTable::new();
+        for (line, fields) in records {
+            if fields.first().is_some_and(|f| f.trim().eq_ignore_ascii_case("effective_date")) {
+                continue;
+            }
+            let invalid = |message: &str| CurrencyError::InvalidRate { line, message: message.to_string() };
+            if fields.len() != 4 {
+                return Err(invalid("expected effective_date,from,to,rate"));
+            }
+            let date = NaiveDate::parse_from_str(fields[0].trim(), "%Y-%m-%d")
+                .map_err(|_| invalid("date must be YYYY-MM-DD"))?;
+            let rate: f64 = fields[3]
+                .trim()
+                .parse()
+                .ok()
+                .filter(|r: &f64| r.is_finite() && *r > 0.0)
+                .ok_or_else(|| invalid("rate must be a positive number"))?;
+            let effective = date.and_hms_opt(0, 0, 0).expect("Midnight is valid").and_utc();
+            table.add(fields[1].trim(), fields[2].trim(), rate, effective);
+        }
+        Ok(table)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CurrencyError> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text)
+    }
+
+    /// Rate in effect at `at`, using the inverse of a reverse quote if needed
+    pub fn rate(&self, from: &str, to: &str, at: DateTime<Utc>) -> Result<ExchangeRate, CurrencyError> {
+        let (from, to) = (from.to_uppercase(), to.to_uppercase());
+        if from == to {
+            return Ok(ExchangeRate { from, to, rate: 1.0, effective: at });
+        }
+
+        self.rates
+            .iter()
+            .filter(|r| r.effective <= at)
+            .filter_map(|r| {
+                if r.from == from && r.to == to {
+                    Some(r.clone())
+                } else if r.from == to && r.to == from {
+                    Some(ExchangeRate { from: from.clone(), to: to.clone(), rate: 1.0 / r.rate, effective: r.effective })
+                } else {
+                    None
+                }
+            })
+            .max_by_key(|r| r.effective)
+            .ok_or(CurrencyError::NoRate { from, to })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::Order;
+    use crate::payment::{MockGateway, PaymentStatus};
+    use crate::product::Product;
+    use crate::user::User;
+
+    fn day(date: &str) -> DateTime<Utc> {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap().and_hms_opt(0, 0, 0).unwrap().and_utc()
+    }
+
+    #[test]
+    fn rounding_modes() {
+        let usd = Currency::base();
+        assert_eq!(Rounding::new(RoundingMode::HalfUp).apply(2.675, &usd), 2.68);
+        assert_eq!(Rounding::new(RoundingMode::HalfEven).apply(2.665, &usd), 2.66);
+        assert_eq!(Rounding::new(RoundingMode::HalfEven).apply(2.675, &usd), 2.68);
+        assert_eq!(Rounding::new(RoundingMode::Up).apply(2.601, &usd), 2.61);
+        assert_eq!(Rounding::new(RoundingMode::Down).apply(2.609, &usd), 2.6);
+        assert_eq!(Rounding::new(RoundingMode::HalfUp).apply(1234.5, &Currency::from_code("jpy")), 1235.0);
+        let chf = Rounding::new(RoundingMode::HalfUp).with_increment(0.05).unwrap().apply(3.12, &Currency::from_code("CHF"));
+        assert!((chf - 3.10).abs() < 1e-9);
+    }
+
+    #[test]
+    fn increments_must_be_positive_amounts() {
+        for increment in [0.0, -0.05, f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(matches!(
+                Rounding::new(RoundingMode::HalfUp).with_increment(increment),
+                Err(CurrencyError::InvalidIncrement)
+            ));
+        }
+        // A bad increment set on the field directly rounds to the currency's decimals instead
+        let zero = Rounding { mode: RoundingMode::HalfUp, increment: Some(0.0) };
+        assert_eq!(zero.apply(2.675, &Currency::base()), 2.68);
+        let nan = Rounding { mode: RoundingMode::Down, increment: Some(f64::NAN) };
+        assert_eq!(nan.apply(1234.9, &Currency::from_code("JPY")), 1234.0);
+    }
+
+    #[test]
+    fn the_newest_effective_rate_wins_and_reverse_quotes_are_inverted() {
+        let table = RateTable::parse("effective_date,from,to,rate\n2026-01-01,USD,EUR,0.9\n2026-06-01,usd,eur,0.8\n").unwrap();
+        assert_eq!(table.rate("USD", "EUR", day("2026-03-01")).unwrap().rate, 0.9);
+        assert_eq!(table.rate("USD", "EUR", day("2026-07-01")).unwrap().rate, 0.8);
+        assert_eq!(table.rate("EUR", "USD", day("2026-07-01")).unwrap().rate, 1.25);
+        assert_eq!(table.rate("GBP", "GBP", day("2020-01-01")).unwrap().rate, 1.0);
+        assert!(matches!(
+            table.rate("USD", "EUR", day("2025-12-31")),
+            Err(CurrencyError::NoRate { from, to }) if from == "USD" && to == "EUR"
+        ));
+    }
+
+    #[test]
+    fn malformed_rate_lines_are_rejected() {
+        assert!(matches!(RateTable::parse("2026-01-01,USD,EUR\n"), Err(CurrencyError::InvalidRate { line: 1, .. })));
+        assert!(matches!(RateTable::parse("01/01/2026,USD,EUR,0.9\n"), Err(CurrencyError::InvalidRate { line: 1, .. })));
+        assert!(matches!(RateTable::parse("2026-01-01,USD,EUR,-1\n"), Err(CurrencyError::InvalidRate { line: 1, .. })));
+        assert!(matches!(RateTable::parse("2026-01-01,USD,EUR,NaN\n"), Err(CurrencyError::InvalidRate { line: 1, .. })));
+    }
+
+    #[test]
+    fn unreadable_rate_files_keep_the_io_error() {
+        use std::error::Error as _;
+        let err = RateTable::load("/nonexistent/rates.csv").unwrap_err();
+        assert!(matches!(&err, CurrencyError::Io(e) if e.kind() == io::ErrorKind::NotFound));
+        assert_eq!(err.to_string(), "Failed to read rate table");
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn checkout_freezes_the_rate_and_payments_carry_the_currency() {
+        let mut rates = RateTable::new();
+        rates.add("USD", "EUR", 0.5, day("2026-01-01"));
+        let user = User::new_unchecked(1, "Ada".into(), "ada@example.com".into(), "1 Main St".into());
+        let mut order = Order::new(1, user);
+        order.add_product(Product::new(1, "Lamp".into(), 10.0, String::new()), 3).unwrap();
+        order.checkout(Currency::from_code("EUR"), &rates, Rounding::default(), day("2026-02-01")).unwrap();
+        assert!(order.checkout(Currency::base(), &rates, Rounding::default(), day("2026-02-01")).is_err());
+
+        rates.add("USD", "EUR", 2.0, day("2026-01-15"));
+        assert_eq!(order.amount_due(), 15.0);
+        assert_eq!(order.format_amount(10.0), "€5.00");
+
+        order.authorize_payment(&mut MockGateway::new()).unwrap();
+        assert!(matches!(&order.payment, PaymentStatus::Authorized { currency, .. } if currency == "EUR"));
+        assert_eq!(order.payment.to_string(), "Authorized (15.00 EUR)");
+    }
+}
@@ -0,0 +1,353 @@
+use std::fmt;
+
+use crate::csv::CsvError;
+use crate::currency::CurrencyError;
+use crate::gift_card::GiftCardError;
+use crate::inventory::InventoryError;
+use crate::loyalty::LoyaltyError;
+use crate::notification::NotifyError;
+use crate::order::OrderError;
+use crate::order_query::QueryError;
+use crate::payment::PaymentError;
+use crate::pricing::PricingError;
+use crate::purchasing::PurchasingError;
+use crate::review::ReviewError;
+use crate::storage::StorageError;
+use crate::subscription::SubscriptionError;
+use crate::user::UserError;
+use crate::webhook::WebhookError;
+
+/// Any error the shop can produce, for callers that do not care which module failed
+#[derive(Debug)]
+pub enum Error {
+    Order(OrderError),
+    User(UserError),
+    Inventory(InventoryError),
+    Payment(PaymentError),
+    Pricing(PricingError),
+    Currency(CurrencyError),
+    Review(ReviewError),
+    Csv(CsvError),
+    Notify(NotifyError),
+    Storage(StorageError),
+    GiftCard(GiftCardError),
+    Loyalty(LoyaltyError),
+    Subscription(SubscriptionError),
+    Purchasing(PurchasingError),
+    Webhook(WebhookError),
+    Query(QueryError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Stable identifier for API clients; never changes once published, unlike the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Order(err) => match err {
+                OrderError::InvalidQuantity => "ORDER_INVALID_QUANTITY",
+                OrderError::ProductNotFound => "ORDER_PRODUCT_NOT_FOUND",
+                OrderError::InvalidStatus => "ORDER_INVALID_STATUS",
+                OrderError::EmptyOrder => "ORDER_EMPTY",
+                OrderError::PaymentNotCaptured => "ORDER_PAYMENT_NOT_CAPTURED",
+                OrderError::Payment(_) => "ORDER_PAYMENT_FAILED",
+                OrderError::ShipmentNotFound => "ORDER_SHIPMENT_NOT_FOUND",
+                OrderError::ExceedsUnshipped { .. } => "ORDER_EXCEEDS_UNSHIPPED",
+                OrderError::Currency(_) => "ORDER_CURRENCY",
+            },
+            Error::User(err) => match err {
+                UserError::InvalidEmail => "USER_INVALID_EMAIL",
+                UserError::EmptyName => "USER_EMPTY_NAME",
+                UserError::EmptyAddress => "USER_EMPTY_ADDRESS",
+            },
+            Error::Inventory(err) => match err {
+                InventoryError::InvalidQuantity => "INVENTORY_INVALID_QUANTITY",
+                InventoryError::WrongDirection(_) => "INVENTORY_WRONG_DIRECTION",
+                InventoryError::InsufficientStock { .. } => "INVENTORY_INSUFFICIENT_STOCK",
+                InventoryError::StockOverflow { .. } => "INVENTORY_STOCK_OVERFLOW",
+                InventoryError::InvalidLedger { .. } => "INVENTORY_INVALID_LEDGER",
+                InventoryError::BundlePolicy { .. } => "INVENTORY_BUNDLE_POLICY",
+            },
+            Error::Payment(err) => match err {
+                PaymentError::Declined(_) => "PAYMENT_DECLINED",
+                PaymentError::Timeout => "PAYMENT_TIMEOUT",
+                PaymentError::InvalidAmount => "PAYMENT_INVALID_AMOUNT",
+                PaymentError::UnknownTransaction => "PAYMENT_UNKNOWN_TRANSACTION",
+                PaymentError::InvalidState => "PAYMENT_INVALID_STATE",
+            },
+            Error::Pricing(err) => match err {
+                PricingError::InvalidPrice => "PRICING_INVALID_PRICE",
+                PricingError::InvalidQuantity => "PRICING_INVALID_QUANTITY",
+                PricingError::InvalidSaleWindow => "PRICING_INVALID_SALE_WINDOW",
+                PricingError::NoHistory { .. } => "PRICING_NO_HISTORY",
+            },
+            Error::Currency(err) => match err {
+                CurrencyError::NoRate { .. } => "CURRENCY_NO_RATE",
+                CurrencyError::InvalidRate { .. } => "CURRENCY_INVALID_RATE",
+                CurrencyError::Io(_) => "CURRENCY_IO",
+                CurrencyError::AlreadyFrozen => "CURRENCY_ALREADY_FROZEN",
+                CurrencyError::InvalidIncrement => "CURRENCY_INVALID_INCREMENT",
+            },
+            Error::Review(err) => match err {
+                ReviewError::InvalidRating => "REVIEW_INVALID_RATING",
+                ReviewError::EmptyText => "REVIEW_EMPTY_TEXT",
+                ReviewError::NotPurchased => "REVIEW_NOT_PURCHASED",
+                ReviewError::AlreadyReviewed => "REVIEW_ALREADY_REVIEWED",
+                ReviewError::ReviewNotFound => "REVIEW_NOT_FOUND",
+            },
+            Error::Csv(err) => match err {
+                CsvError::MissingHeader => "CSV_MISSING_HEADER",
+                CsvError::MissingColumn(_) => "CSV_MISSING_COLUMN",
+                CsvError::UnterminatedQuote { .. } => "CSV_UNTERMINATED_QUOTE",
+            },
+            Error::Notify(err) => match err {
+                NotifyError::Io(_) => "NOTIFY_IO",
+            },
+            Error::Storage(err) => match err {
+                StorageError::Io(_) => "STORAGE_IO",
+                StorageError::Corrupt { .. } => "STORAGE_CORRUPT",
+                StorageError::NotFound { .. } => "STORAGE_NOT_FOUND",
+                StorageError::Ledger(_) => "STORAGE_INVALID_LEDGER",
+            },
+            Error::GiftCard(err) => match err {
+                GiftCardError::InvalidAmount => "GIFT_CARD_INVALID_AMOUNT",
+                GiftCardError::UnknownCode => "GIFT_CARD_UNKNOWN_CODE",
+                GiftCardError::Expired => "GIFT_CARD_EXPIRED",
+                GiftCardError::EmptyBalance => "GIFT_CARD_EMPTY_BALANCE",
+                GiftCardError::OrderNotPayable => "GIFT_CARD_ORDER_NOT_PAYABLE",
+                GiftCardError::OrderNotCancelled => "GIFT_CARD_ORDER_NOT_CANCELLED",
+                GiftCardError::NoEntropy => "GIFT_CARD_NO_ENTROPY",
+            },
+            Error::Loyalty(err) => match err {
+                LoyaltyError::OrderNotDelivered => "LOYALTY_ORDER_NOT_DELIVERED",
+                LoyaltyError::AlreadyCredited => "LOYALTY_ALREADY_CREDITED",
+                LoyaltyError::InvalidPoints => "LOYALTY_INVALID_POINTS",
+                LoyaltyError::InsufficientPoints { .. } => "LOYALTY_INSUFFICIENT_POINTS",
+                LoyaltyError::OrderNotPayable => "LOYALTY_ORDER_NOT_PAYABLE",
+                LoyaltyError::OrderNotReversible => "LOYALTY_ORDER_NOT_REVERSIBLE",
+            },
+            Error::Subscription(err) => match err {
+                SubscriptionError::EmptySubscription => "SUBSCRIPTION_EMPTY",
+                SubscriptionError::InvalidQuantity => "SUBSCRIPTION_INVALID_QUANTITY",
+                SubscriptionError::InvalidStatus => "SUBSCRIPTION_INVALID_STATUS",
+                SubscriptionError::NotFound => "SUBSCRIPTION_NOT_FOUND",
+                SubscriptionError::DateOutOfRange => "SUBSCRIPTION_DATE_OUT_OF_RANGE",
+            },
+            Error::Purchasing(err) => match err {
+                PurchasingError::UnknownSupplier(_) => "PURCHASING_UNKNOWN_SUPPLIER",
+                PurchasingError::UnknownPurchaseOrder(_) => "PURCHASING_UNKNOWN_ORDER",
+                PurchasingError::NotSupplied { .. } => "PURCHASING_NOT_SUPPLIED",
+                PurchasingError::InvalidQuantity => "PURCHASING_INVALID_QUANTITY",
+                PurchasingError::InvalidStatus(_) => "PURCHASING_INVALID_STATUS",
+                PurchasingError::EmptyOrder => "PURCHASING_EMPTY_ORDER",
+                PurchasingError::OverReceipt { .. } => "PURCHASING_OVER_RECEIPT",
+                PurchasingError::InvalidCost { .. } => "PURCHASING_INVALID_COST",
+                PurchasingError::Inventory(_) => "PURCHASING_INVENTORY_REJECTED",
+            },
+            Error::Webhook(err) => match err {
+                WebhookError::InvalidUrl(_) => "WEBHOOK_INVALID_URL",
+                WebhookError::EmptySecret => "WEBHOOK_EMPTY_SECRET",
+                WebhookError::UnknownSubscription(_) => "WEBHOOK_UNKNOWN_SUBSCRIPTION",
+                WebhookError::UnknownDelivery(_) => "WEBHOOK_UNKNOWN_DELIVERY",
+                WebhookError::InactiveSubscription(_) => "WEBHOOK_INACTIVE_SUBSCRIPTION",
+                WebhookError::Io(_) => "WEBHOOK_IO",
+                WebhookError::Status(_) => "WEBHOOK_BAD_STATUS",
+            },
+            Error::Query(err) => match err {
+                QueryError::UnknownParameter(_) => "QUERY_UNKNOWN_PARAMETER",
+                QueryError::InvalidValue { .. } => "QUERY_INVALID_VALUE",
+            },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Order(err) => write!(f, "{}", err),
+            Error::User(err) => write!(f, "{}", err),
+            Error::Inventory(err) => write!(f, "{}", err),
+            Error::Payment(err) => write!(f, "{}", err),
+            Error::Pricing(err) => write!(f, "{}", err),
+            Error::Currency(err) => write!(f, "{}", err),
+            Error::Review(err) => write!(f, "{}", err),
+            Error::Csv(err) => write!(f, "{}", err),
+            Error::Notify(err) => write!(f, "{}", err),
+            Error::Storage(err) => write!(f, "{}", err),
+            Error::GiftCard(err) => write!(f, "{}", err),
+            Error::Loyalty(err) => write!(f, "{}", err),
+            Error::Subscription(err) => write!(f, "{}", err),
+            Error::Purchasing(err) => write!(f, "{}", err),
+            Error::Webhook(err) => write!(f, "{}", err),
+            Error::Query(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Transparent: the wrapped module error's own source is the next link in the chain
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Order(err) => err.source(),
+            Error::User(err) => err.source(),
+            Error::Inventory(err) => err.source(),
+            Error::Payment(err) => err.source(),
+            Error::Pricing(err) => err.source(),
+            Error::Currency(err) => err.source(),
+            Error::Review(err) => err.source(),
+            Error::Csv(err) => err.source(),
+            Error::Notify(err) => err.source(),
+            Error::Storage(err) => err.source(),
+            Error::GiftCard(err) => err.source(),
+            Error::Loyalty(err) => err.source(),
+            Error::Subscription(err) => err.source(),
+            Error::Purchasing(err) => err.source(),
+            Error::Webhook(err) => err.source(),
+            Error::Query(err) => err.source(),
+        }
+    }
+}
+
+/// Joins an error and all of its sources into one line, outermost first
+pub fn report(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+impl From<OrderError> for Error {
+    fn from(err: OrderError) -> Self {
+        Error::Order(err)
+    }
+}
+
+impl From<UserError> for Error {
+    fn from(err: UserError) -> Self {
+        Error::User(err)
+    }
+}
+
+impl From<InventoryError> for Error {
+    fn from(err: InventoryError) -> Self {
+        Error::Inventory(err)
+    }
+}
+
+impl From<PaymentError> for Error {
+    fn from(err: PaymentError) -> Self {
+        Error::Payment(err)
+    }
+}
+
+impl From<PricingError> for Error {
+    fn from(err: PricingError) -> Self {
+        Error::Pricing(err)
+    }
+}
+
+impl From<CurrencyError> for Error {
+    fn from(err: CurrencyError) -> Self {
+        Error::Currency(err)
+    }
+}
+
+impl From<ReviewError> for Error {
+    fn from(err: ReviewError) -> Self {
+        Error::Review(err)
+    }
+}
+
+impl From<CsvError> for Error {
+    fn from(err: CsvError) -> Self {
+        Error::Csv(err)
+    }
+}
+
+impl From<NotifyError> for Error {
+    fn from(err: NotifyError) -> Self {
+        Error::Notify(err)
+    }
+}
+
+impl From<StorageError> for Error {
+    fn from(err: StorageError) -> Self {
+        Error::Storage(err)
+    }
+}
+
+impl From<GiftCardError> for Error {
+    fn from(err: GiftCardError) -> Self {
+        Error::GiftCard(err)
+    }
+}
+
+impl From<LoyaltyError> for Error {
+    fn from(err: LoyaltyError) -> Self {
+        Error::Loyalty(err)
+    }
+}
+
+impl From<SubscriptionError> for Error {
+    fn from(err: SubscriptionError) -> Self {
+        Error::Subscription(err)
+    }
+}
+
+impl From<PurchasingError> for Error {
+    fn from(err: PurchasingError) -> Self {
+        Error::Purchasing(err)
+    }
+}
+
+impl From<WebhookError> for Error {
+    fn from(err: WebhookError) -> Self {
+        Error::Webhook(err)
+    }
+}
+
+impl From<QueryError> for Error {
+    fn from(err: QueryError) -> Self {
+        Error::Query(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+    use std::io;
+
+    #[test]
+    fn codes_are_stable_per_variant() {
+        assert_eq!(Error::from(OrderError::EmptyOrder).code(), "ORDER_EMPTY");
+        assert_eq!(Error::from(InventoryError::InvalidQuantity).code(), "INVENTORY_INVALID_QUANTITY");
+        assert_eq!(Error::from(PaymentError::Timeout).code(), "PAYMENT_TIMEOUT");
+    }
+
+    #[test]
+    fn order_errors_chain_the_payment_or_currency_failure() {
+        let err = Error::from(OrderError::Payment(PaymentError::Declined("card expired".into())));
+        assert_eq!(err.to_string(), "Payment failed");
+        assert_eq!(err.source().unwrap().to_string(), "Payment declined: card expired");
+        assert_eq!(report(&err), "Payment failed: Payment declined: card expired");
+
+        let err = Error::from(OrderError::Currency(CurrencyError::NoRate { from: "USD".into(), to: "XYZ".into() }));
+        assert_eq!(report(&err), "Currency conversion failed: No exchange rate from USD to XYZ");
+
+        let err = OrderError::Currency(CurrencyError::Io(io::Error::other("disk gone")));
+        assert_eq!(report(&err), "Currency conversion failed: Failed to read rate table: disk gone");
+        assert!(Error::from(OrderError::EmptyOrder).source().is_none());
+    }
+
+    #[test]
+    fn report_follows_the_source_chain() {
+        let err = Error::from(NotifyError::Io(io::Error::other("disk full")));
+        assert_eq!(err.code(), "NOTIFY_IO");
+        assert_eq!(report(&err), "Failed to deliver notification: disk full");
+    }
+}
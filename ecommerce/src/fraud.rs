@@ -0,0 +1,239 @@
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::currency::Currency;
+use crate::order::{Order, OrderError, OrderStatus};
+
+/// A risk signal checked at checkout
+#[derive(Debug, Clone, PartialEq)]
+pub enum FraudRule {
+    /// The order total, in the base currency, is above this amount
+    TotalAbove(f64),
+    /// The customer placed more than `max_orders` other orders in the window before this one
+    Velocity { max_orders: u32, window: Duration },
+    /// Billing and shipping countries are both known and differ
+    CountryMismatch,
+    /// An account younger than `max_age` places an order worth at least `min_total`
+    NewAccountHighValue { max_age: Duration, min_total: f64 },
+}
+
+impl fmt::Display for FraudRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let base = Currency::base();
+        match self {
+            FraudRule::TotalAbove(threshold) => write!(f, "total above {}", base.format(*threshold)),
+            FraudRule::Velocity { max_orders, window } => {
+                write!(f, "more than {} orders in {} hour(s)", max_orders, window.num_hours())
+            }
+            FraudRule::CountryMismatch => write!(f, "billing and shipping countries differ"),
+            FraudRule::NewAccountHighValue { max_age, min_total } => write!(
+                f,
+                "account under {} day(s) old spending {} or more",
+                max_age.num_days(),
+                base.format(*min_total)
+            ),
+        }
+    }
+}
+
+/// A rule that fired and the score it added
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchedRule {
+    pub rule: FraudRule,
+    pub score: u32,
+}
+
+/// Outcome of screening one order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screening {
+    pub order_id: u32,
+    pub score: u32,
+    pub matched: Vec<MatchedRule>,
+    pub needs_review: bool,
+}
+
+impl Screening {
+    pub fn display(&self) {
+        let verdict = if self.needs_review { "🔎 Manual review" } else { "✅ Passed" };
+        println!("Order #{} risk score {} - {}", self.order_id, self.score, verdict);
+        for matched in &self.matched {
+            println!("  +{} {}", matched.score, matched.rule);
+        }
+    }
+}
+
+/// Scores orders against weighted rules and holds risky ones for manual review
+#[derive(Debug, Clone)]
+pub struct FraudScreen {
+    rules: Vec<MatchedRule>, // every configured rule with its score
+    review_threshold: u32,
+}
+
+impl Default for FraudScreen {
+    /// A starting rule set; tune the scores and threshold to your own losses
+    fn default() -> Self {
+        FraudScreen::new(50)
+            .with_rule(FraudRule::TotalAbove(5000.0), 40)
+            .with_rule(FraudRule::Velocity { max_orders: 3, window: Duration::hours(1) }, 30)
+            .with_rule(FraudRule::CountryMismatch, 25)
+            .with_rule(FraudRule::NewAccountHighValue { max_age: Duration::days(7), min_total: 1000.0 }, 35)
+    }
+}
+
+impl FraudScreen {
+    /// Orders scoring at least `review_threshold` go to manual review
+    pub fn new(review_threshold: u32) -> Self {
+        FraudScreen { rules: Vec::new(), review_threshold }
+    }
+
+    pub fn with_rule(mut self, rule: FraudRule, score: u32) -> Self {
+        self.rules.push(MatchedRule { rule, score });
+        self
+    }
+
+    fn matches(rule: &FraudRule, order: &Order, history: &[Order], now: DateTime<Utc>) -> bool {
+        match rule {
+            FraudRule::TotalAbove(threshold) => order.total > *threshold,
+            FraudRule::Velocity { max_orders, window } => {
+                let recent = history
+                    .iter()
+                    .filter(|o| o.id != order.id && o.user.id == order.user.id)
+                    .filter(|o| o.created_at <= order.created_at && order.created_at - o.created_at <= *window)
+                    .count();
+                recent > *max_orders as usize
+            }
+            FraudRule::CountryMismatch => match (&order.billing_country, &order.shipping_country) {
+                (Some(billing), Some(shipping)) => billing != shipping,
+                _ => false,
+            },
+            FraudRule::NewAccountHighValue { max_age, min_total } => {
+                now - order.user.registered_at < *max_age && order.total >= *min_total
+            }
+        }
+    }
+
+    /// Scores an order; `history` is the shop's other orders, used for velocity checks
+    pub fn screen(&self, order: &Order, history: &[Order], now: DateTime<Utc>) -> Screening {
+        let matched: Vec<MatchedRule> = self.rules
+            .iter()
+            .filter(|configured| Self::matches(&configured.rule, order, history, now))
+            .cloned()
+            .collect();
+        let score = matched.iter().map(|m| m.score).sum();
+        Screening { order_id: order.id, score, matched, needs_review: score >= self.review_threshold }
+    }
+
+    /// Screens a pending order and moves it to manual review if it scores too high.
+    ///
+    /// Orders that pass stay pending so checkout can carry on.
+    pub fn check(&self, order: &mut Order, history: &[Order], now: DateTime<Utc>) -> Result<Screening, OrderError> {
+        if order.status != OrderStatus::Pending {
+            return Err(OrderError::InvalidStatus);
+        }
+        let screening = self.screen(order, history, now);
+        if screening.needs_review {
+            order.update_status(OrderStatus::UnderReview)?;
+        }
+        Ok(screening)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    use crate::product::Product;
+    use crate::user::User;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap()
+    }
+
+    fn order(id: u32, total: f64, registered: DateTime<Utc>, created: DateTime<Utc>) -> Order {
+        let user = User::new_unchecked(1, "Ada".into(), "ada@example.com".into(), "1 Main St".into())
+            .with_registered_at(registered);
+        let mut order = Order::new(id, user).with_created_at(created);
+        order.add_product(Product::new(1, "Widget".into(), total, String::new()), 1).unwrap();
+        order.place().unwrap();
+        order
+    }
+
+    fn established(id: u32, total: f64) -> Order {
+        order(id, total, now() - Duration::days(365), now())
+    }
+
+    fn only(rule: FraudRule) -> FraudScreen {
+        FraudScreen::new(10).with_rule(rule, 10)
+    }
+
+    #[test]
+    fn total_threshold_is_exclusive() {
+        let screen = only(FraudRule::TotalAbove(100.0));
+        assert!(!screen.screen(&established(1, 100.0), &[], now()).needs_review);
+        assert!(screen.screen(&established(1, 100.01), &[], now()).needs_review);
+    }
+
+    #[test]
+    fn velocity_counts_the_customers_earlier_orders_in_the_window() {
+        let screen = only(FraudRule::Velocity { max_orders: 1, window: Duration::hours(1) });
+        let earlier = |id, minutes| order(id, 5.0, now() - Duration::days(365), now() - Duration::minutes(minutes));
+        let current = established(9, 5.0);
+
+        assert!(!screen.screen(&current, &[earlier(1, 30), earlier(2, 90)], now()).needs_review);
+        assert!(screen.screen(&current, &[earlier(1, 30), earlier(2, 60)], now()).needs_review);
+        // The order itself and later orders do not count
+        let later = order(3, 5.0, now(), now() + Duration::minutes(5));
+        assert!(!screen.screen(&current, &[current.clone(), earlier(1, 30), later], now()).needs_review);
+    }
+
+    #[test]
+    fn country_mismatch_needs_both_countries() {
+        let screen = only(FraudRule::CountryMismatch);
+        assert!(!screen.screen(&established(1, 5.0), &[], now()).needs_review);
+        assert!(!screen.screen(&established(1, 5.0).with_countries("us", "US "), &[], now()).needs_review);
+        assert!(screen.screen(&established(1, 5.0).with_countries("US", "NG"), &[], now()).needs_review);
+    }
+
+    #[test]
+    fn new_accounts_are_flagged_only_for_large_orders() {
+        let screen = only(FraudRule::NewAccountHighValue { max_age: Duration::days(7), min_total: 1000.0 });
+        let newcomer = |total| order(1, total, now() - Duration::days(2), now());
+        assert!(screen.screen(&newcomer(1000.0), &[], now()).needs_review);
+        assert!(!screen.screen(&newcomer(999.0), &[], now()).needs_review);
+        assert!(!screen.screen(&established(1, 5000.0), &[], now()).needs_review);
+    }
+
+    #[test]
+    fn accounts_with_no_recorded_registration_are_not_new() {
+        let screen = only(FraudRule::NewAccountHighValue { max_age: Duration::days(7), min_total: 1000.0 });
+        let old_record = order(1, 5000.0, DateTime::UNIX_EPOCH, DateTime::UNIX_EPOCH);
+        assert!(!screen.screen(&old_record, &[], now()).needs_review);
+    }
+
+    #[test]
+    fn scores_add_up_to_the_threshold() {
+        let screen = FraudScreen::default();
+        let risky = order(1, 6000.0, now() - Duration::days(1), now()).with_countries("US", "GB");
+        let screening = screen.screen(&risky, &[], now());
+        assert_eq!(screening.score, 40 + 25 + 35);
+        assert_eq!(screening.matched.len(), 3);
+
+        // 40 alone stays under the default threshold of 50
+        assert!(!screen.screen(&established(2, 6000.0), &[], now()).needs_review);
+    }
+
+    #[test]
+    fn check_holds_risky_pending_orders_for_review() {
+        let screen = only(FraudRule::TotalAbove(100.0));
+        let mut safe = established(1, 50.0);
+        assert!(!screen.check(&mut safe, &[], now()).unwrap().needs_review);
+        assert_eq!(safe.status, OrderStatus::Pending);
+
+        let mut risky = established(2, 500.0);
+        assert!(screen.check(&mut risky, &[], now()).unwrap().needs_review);
+        assert_eq!(risky.status, OrderStatus::UnderReview);
+        assert!(matches!(screen.check(&mut risky, &[], now()), Err(OrderError::InvalidStatus)));
+    }
+}
@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::loyalty;
+use crate::order::{Order, OrderStatus};
+use crate::payment::PaymentStatus;
+
+/// Characters used in generated codes; no 0/O or 1/I to misread
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// Whether a balance was bought as a gift card or granted as store credit
+#[derive(Debug, Clone, PartialEq)]
+pub enum CardKind {
+    GiftCard,
+    /// One per customer; refunds top it up and it never expires
+    StoreCredit { user_id: u32 },
+}
+
+/// A stored-value balance, held in the base currency
+#[derive(Debug, Clone, PartialEq)]
+pub struct GiftCard {
+    pub code: String,
+    pub kind: CardKind,
+    pub balance: f64,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl GiftCard {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expiry| now >= expiry)
+    }
+}
+
+/// An amount taken from a card to pay part of an order, in the base currency
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Redemption {
+    pub code: String,
+    pub amount: f64,
+}
+
+impl Redemption {
+    /// How the payment is described on receipts
+    pub fn label(&self) -> String {
+        if self.code == loyalty::POINTS_CODE {
+            String::from("Loyalty points")
+        } else {
+            format!("Gift card {}", self.code)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CardTransactionKind {
+    Issue,
+    Redeem { order_id: u32 },
+    Reversal { order_id: u32 },
+    Refund { order_id: u32 },
+    Expire,
+}
+
+impl fmt::Display for CardTransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CardTransactionKind::Issue => write!(f, "Issued"),
+            CardTransactionKind::Redeem { order_id } => write!(f, "Redeemed on order #{}", order_id),
+            CardTransactionKind::Reversal { order_id } => write!(f, "Reversed from cancelled order #{}", order_id),
+            CardTransactionKind::Refund { order_id } => write!(f, "Refund of order #{}", order_id),
+            CardTransactionKind::Expire => write!(f, "Expired"),
+        }
+    }
+}
+
+/// One change to a card balance; `amount` is positive for credits and negative for debits
+#[derive(Debug, Clone, PartialEq)]
+pub struct CardTransaction {
+    pub id: u64,
+    pub code: String,
+    pub kind: CardTransactionKind,
+    pub amount: f64,
+    pub balance_after: f64,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GiftCardError {
+    InvalidAmount,
+    UnknownCode,
+    Expired,
+    EmptyBalance,
+    /// The order is past the point where its payment can change
+    OrderNotPayable,
+    OrderNotCancelled,
+    /// The operating system could not supply random bytes for a new code
+    NoEntropy,
+}
+
+impl fmt::Display for GiftCardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GiftCardError::InvalidAmount => write!(f, "Gift card amount must be positive"),
+            GiftCardError::UnknownCode => write!(f, "Unknown gift card code"),
+            GiftCardError::Expired => write!(f, "Gift card has expired"),
+            GiftCardError::EmptyBalance => write!(f, "Gift card has no balance left"),
+            GiftCardError::OrderNotPayable => write!(f, "Order can no longer take gift card payments"),
+            GiftCardError::OrderNotCancelled => write!(f, "Only cancelled orders can have gift card payments reversed"),
+            GiftCardError::NoEntropy => write!(f, "No secure random source available to generate a gift card code"),
+        }
+    }
+}
+
+impl std::error::Error for GiftCardError {}
+
+/// Rounds to whole cents of the base currency
+fn cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+/// Every gift card and store-credit balance, with the ledger of changes to them
+#[derive(Debug, Default)]
+pub struct GiftCardBook {
+    cards: HashMap<String, GiftCard>,
+    transactions: Vec<CardTransaction>,
+    store_credit: HashMap<u32, String>, // user_id -> code
+}
+
+impl GiftCardBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes a fresh `XXXX-XXXX-XXXX-XXXX` code not used by any other card.
+    ///
+    /// Each code is 80 bits from the operating system's CSPRNG, so knowing
+    /// other codes says nothing about the next one.
+    fn generate_code(&self) -> Result<String, GiftCardError> {
+        loop {
+            let mut bytes = [0u8; 16];
+            getrandom::fill(&mut bytes[..10]).map_err(|_| GiftCardError::NoEntropy)?;
+            let mut bits = u128::from_le_bytes(bytes);
+
+            let mut code = String::with_capacity(19);
+            for i in 0..16 {
+                if i > 0 && i % 4 == 0 {
+                    code.push('-');
+                }
+                code.push(CODE_ALPHABET[(bits & 31) as usize] as char);
+                bits >>= 5;
+            }
+            if !self.cards.contains_key(&code) {
+                return Ok(code);
+            }
+        }
+    }
+
+    fn post(&mut self, code: &str, kind: CardTransactionKind, amount: f64, at: DateTime<Utc>) {
+        let card = self.cards.get_mut(code).expect("Posting to an existing card");
+        card.balance = cents(card.balance + amount);
+        let id = self.transactions.len() as u64 + 1;
+        self.transactions.push(CardTransaction {
+            id,
+            code: code.to_string(),
+            kind,
+            amount,
+            balance_after: card.balance,
+            at,
+        });
+    }
+
+    /// Issues a gift card with `amount` on it and returns its code
+    pub fn issue(
+        &mut self,
+        amount: f64,
+        expires_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Result<String, GiftCardError> {
+        if !amount.is_finite() || amount <= 0.0 {
+            return Err(GiftCardError::InvalidAmount);
+        }
+        let code = self.generate_code()?;
+        self.cards.insert(code.clone(), GiftCard {
+            code: code.clone(),
+            kind: CardKind::GiftCard,
+            balance: 0.0,
+            issued_at: now,
+            expires_at,
+        });
+        self.post(&code, CardTransactionKind::Issue, cents(amount), now);
+        Ok(code)
+    }
+
+    /// Credits a refund to the customer's store-credit balance, opening one if needed
+    pub fn issue_store_credit(
+        &mut self,
+        user_id: u32,
+        order_id: u32,
+        amount: f64,
+        now: DateTime<Utc>,
+    ) -> Result<String, GiftCardError> {
+        if !amount.is_finite() || amount <= 0.0 {
+            return Err(GiftCardError::InvalidAmount);
+        }
+        let code = match self.store_credit.get(&user_id) {
+            Some(code) => code.clone(),
+            None => {
+                let code = self.generate_code()?;
+                self.cards.insert(code.clone(), GiftCard {
+                    code: code.clone(),
+                    kind: CardKind::StoreCredit { user_id },
+                    balance: 0.0,
+                    issued_at: now,
+                    expires_at: None,
+                });
+                self.store_credit.insert(user_id, code.clone());
+                code
+            }
+        };
+        self.post(&code, CardTransactionKind::Refund { order_id }, cents(amount), now);
+        Ok(code)
+    }
+
+    /// Refunds an order's captured payment as store credit instead of to the card it was paid with
+    pub fn refund_to_store_credit(&mut self, order: &mut Order, now: DateTime<Utc>) -> Result<String, GiftCardError> {
+        let (transaction_id, amount, currency) = match &order.payment {
+            PaymentStatus::Captured { transaction_id, amount, currency } => (transaction_id.clone(), *amount, currency.clone()),
+            _ => return Err(GiftCardError::OrderNotPayable),
+        };
+        // Captured amounts are in the order currency; balances are kept in the base currency
+        let rate = order.frozen_rate.as_ref().map_or(1.0, |frozen| frozen.rate.rate);
+        let code = self.issue_store_credit(order.user.id, order.id, amount / rate, now)?;
+        order.payment = PaymentStatus::Refunded { transaction_id, amount, currency };
+        Ok(code)
+    }
+
+    pub fn card(&self, code: &str) -> Option<&GiftCard> {
+        self.cards.get(&code.trim().to_uppercase())
+    }
+
+    pub fn store_credit_code(&self, user_id: u32) -> Option<&str> {
+        self.store_credit.get(&user_id).map(String::as_str)
+    }
+
+    /// Spendable balance; expired cards have none
+    pub fn balance(&self, code: &str, now: DateTime<Utc>) -> Result<f64, GiftCardError> {
+        let card = self.card(code).ok_or(GiftCardError::UnknownCode)?;
+        if card.is_expired(now) {
+            return Err(GiftCardError::Expired);
+        }
+        Ok(card.balance)
+    }
+
+    /// Pays as much of the order's outstanding balance as the card covers, returning the amount taken.
+    ///
+    /// Redemptions must happen before the card payment is authorized; the
+    /// gateway is then only asked for what is left.
+    pub fn redeem(&mut self, code: &str, order: &mut Order, now: DateTime<Utc>) -> Result<f64, GiftCardError> {
+        if order.status != OrderStatus::Pending || !matches!(order.payment, PaymentStatus::Unpaid | PaymentStatus::Failed { .. }) {
+            return Err(GiftCardError::OrderNotPayable);
+        }
+        let code = self.card(code).ok_or(GiftCardError::UnknownCode)?.code.clone();
+        let balance = self.balance(&code, now)?;
+        if balance <= 0.0 {
+            return Err(GiftCardError::EmptyBalance);
+        }
+
+        let amount = cents(balance.min(order.total - order.redeemed_total()));
+        if amount <= 0.0 {
+            return Ok(0.0);
+        }
+        self.post(&code, CardTransactionKind::Redeem { order_id: order.id }, -amount, now);
+        order.redemptions.push(Redemption { code, amount });
+        Ok(amount)
+    }
+
+    /// Puts gift card and store-credit payments on a cancelled order back on their cards.
+    ///
+    /// Returns the amount restored; running it twice restores nothing more.
+    pub fn reverse(&mut self, order: &Order, now: DateTime<Utc>) -> Result<f64, GiftCardError> {
+        if order.status != OrderStatus::Cancelled {
+            return Err(GiftCardError::OrderNotCancelled);
+        }
+        let reversal = CardTransactionKind::Reversal { order_id: order.id };
+        if self.transactions.iter().any(|t| t.kind == reversal) {
+            return Ok(0.0);
+        }
+
+        let mut restored = 0.0;
+        for redemption in &order.redemptions {
+            if self.cards.contains_key(&redemption.code) {
+                self.post(&redemption.code, reversal.clone(), redemption.amount, now);
+                restored += redemption.amount;
+            }
+        }
+        Ok(cents(restored))
+    }
+
+    /// Zeroes the balance of every gift card past its expiry, recording each in the ledger
+    pub fn expire(&mut self, now: DateTime<Utc>) -> Vec<String> {
+        let mut expired: Vec<String> = self.cards
+            .values()
+            .filter(|card| card.is_expired(now) && card.balance > 0.0)
+            .map(|card| card.code.clone())
+            .collect();
+        expired.sort();
+        for code in &expired {
+            let balance = self.cards[code].balance;
+            self.post(code, CardTransactionKind::Expire, -balance, now);
+        }
+        expired
+    }
+
+    /// A card's ledger, oldest first
+    pub fn transactions_for(&self, code: &str) -> Vec<&CardTransaction> {
+        let code = code.trim().to_uppercase();
+        self.transactions.iter().filter(|t| t.code == code).collect()
+    }
+
+    pub fn display_card(&self, code: &str) {
+        let Some(card) = self.card(code) else {
+            println!("Unknown gift card {}", code);
+            return;
+        };
+        let base = Currency::base();
+        let label = match card.kind {
+            CardKind::GiftCard => String::from("Gift card"),
+            CardKind::StoreCredit { user_id } => format!("Store credit for user {}", user_id),
+        };
+        println!("{} {} - balance {}", label, card.code, base.format(card.balance));
+        for transaction in self.transactions_for(code) {
+            println!("  #{} {:+.2} {} (balance {})",
+                transaction.id, transaction.amount, transaction.kind, base.format(transaction.balance_after));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payment::MockGateway;
+    use crate::product::Product;
+    use crate::user::User;
+    use chrono::{Duration, TimeZone};
+    use std::collections::HashSet;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    fn order(total: f64) -> Order {
+        let user = User::new_unchecked(3, "Ada".into(), "ada@example.com".into(), "1 Main St".into());
+        let mut order = Order::new(9, user);
+        order.add_product(Product::new(1, "Lamp".into(), total, String::new()), 1).unwrap();
+        order
+    }
+
+    #[test]
+    fn codes_are_well_formed_and_distinct() {
+        let mut book = GiftCardBook::new();
+        let codes: HashSet<String> = (0..200).map(|_| book.issue(10.0, None, now()).unwrap()).collect();
+        assert_eq!(codes.len(), 200);
+        for code in &codes {
+            assert_eq!(code.len(), 19);
+            assert!(code.split('-').all(|group| group.len() == 4 && group.bytes().all(|b| CODE_ALPHABET.contains(&b))));
+        }
+        assert!(book.card(&codes.iter().next().unwrap().to_lowercase()).is_some());
+    }
+
+    #[test]
+    fn issuing_rejects_bad_amounts() {
+        let mut book = GiftCardBook::new();
+        for amount in [0.0, -5.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(book.issue(amount, None, now()), Err(GiftCardError::InvalidAmount));
+        }
+    }
+
+    #[test]
+    fn redeeming_takes_at_most_what_the_order_owes() {
+        let mut book = GiftCardBook::new();
+        let code = book.issue(50.0, None, now()).unwrap();
+        let mut small = order(20.0);
+        assert_eq!(book.redeem(&code, &mut small, now()), Ok(20.0));
+        assert_eq!(book.redeem(&code, &mut small, now()), Ok(0.0));
+        assert_eq!(book.balance(&code, now()), Ok(30.0));
+
+        let mut large = order(100.0);
+        assert_eq!(book.redeem(&code, &mut large, now()), Ok(30.0));
+        assert_eq!(book.redeem(&code, &mut large, now()), Err(GiftCardError::EmptyBalance));
+        assert_eq!(book.redeem("NOPE", &mut large, now()), Err(GiftCardError::UnknownCode));
+    }
+
+    #[test]
+    fn expired_cards_cannot_be_spent_and_are_zeroed() {
+        let mut book = GiftCardBook::new();
+        let code = book.issue(10.0, Some(now() + Duration::days(30)), now()).unwrap();
+        let later = now() + Duration::days(31);
+        assert_eq!(book.redeem(&code, &mut order(5.0), later), Err(GiftCardError::Expired));
+        assert_eq!(book.expire(later), vec![code.clone()]);
+        assert!(book.expire(later).is_empty());
+        assert_eq!(book.card(&code).unwrap().balance, 0.0);
+    }
+
+    #[test]
+    fn cancelled_orders_get_their_card_payments_back_once() {
+        let mut book = GiftCardBook::new();
+        let code = book.issue(15.0, None, now()).unwrap();
+        let mut order = order(10.0);
+        book.redeem(&code, &mut order, now()).unwrap();
+        assert_eq!(book.reverse(&order, now()), Err(GiftCardError::OrderNotCancelled));
+
+        order.update_status(OrderStatus::Cancelled).unwrap();
+        assert_eq!(book.reverse(&order, now()), Ok(10.0));
+        assert_eq!(book.reverse(&order, now()), Ok(0.0));
+        assert_eq!(book.balance(&code, now()), Ok(15.0));
+    }
+
+    #[test]
+    fn refunds_to_store_credit_share_one_balance_per_customer() {
+        let mut book = GiftCardBook::new();
+        let mut gateway = MockGateway::new();
+        let mut first = order(12.0);
+        assert_eq!(book.refund_to_store_credit(&mut first, now()), Err(GiftCardError::OrderNotPayable));
+        first.authorize_payment(&mut gateway).unwrap();
+        first.capture_payment(&mut gateway).unwrap();
+        let code = book.refund_to_store_credit(&mut first, now()).unwrap();
+        assert!(matches!(first.payment, PaymentStatus::Refunded { .. }));
+
+        assert_eq!(book.issue_store_credit(3, 10, 8.0, now()).unwrap(), code);
+        assert_eq!(book.store_credit_code(3), Some(code.as_str()));
+        assert_eq!(book.balance(&code, now()), Ok(20.0));
+    }
+}
@@ -1,39 +1,772 @@
-use std::collections::HashMap;
-use crate::product::Product;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::order::Order;
+use crate::product::{Product, StockPolicy};
+use crate::render::{Format, Render};
+use crate::shared_inventory::SharedInventory;
 
+/// Actor recorded for movements posted through `add_stock` / `remove_stock`
+pub const SYSTEM_ACTOR: &str = "system";
+
+/// Actor recorded when incoming stock is allocated to waiting orders
+pub const BACKORDER_ACTOR: &str = "backorder";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MovementReason {
+    Receipt,
+    Sale,
+    Return,
+    Damage,
+    CycleCount,
+    Transfer,
+}
+
+impl MovementReason {
+    /// Whether a movement with this reason may add (`Some(true)`), remove
+    /// (`Some(false)`) or do either (`None`)
+    fn direction(&self) -> Option<bool> {
+        match self {
+            MovementReason::Receipt | MovementReason::Return => Some(true),
+            MovementReason::Sale | MovementReason::Damage => Some(false),
+            MovementReason::CycleCount | MovementReason::Transfer => None,
+        }
+    }
+}
+
+impl fmt::Display for MovementReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovementReason::Receipt => write!(f, "Receipt"),
+            MovementReason::Sale => write!(f, "Sale"),
+            MovementReason::Return => write!(f, "Return"),
+            MovementReason::Damage => write!(f, "Damage"),
+            MovementReason::CycleCount => write!(f, "Cycle count"),
+            MovementReason::Transfer => write!(f, "Transfer"),
+        }
+    }
+}
+
+/// A single change to a product's stock level
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StockMovement {
+    pub id: u64,
+    pub product_id: u32,
+    pub delta: i64, // positive adds stock, negative removes it
+    pub reason: MovementReason,
+    pub actor: String,
+    pub at: DateTime<Utc>,
+}
+
+/// Difference between the book level and a physical count
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variance {
+    pub product_id: u32,
+    pub expected: u32,
+    pub counted: u32,
+    pub delta: i64,
+}
+
+/// An order line waiting for stock to arrive
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Backorder {
+    pub order_id: u32,
+    pub product_id: u32,
+    pub allocated: u32,
+    pub outstanding: u32,
+    pub release: Option<DateTime<Utc>>, // pre-order launch date
+}
+
+/// Everything the inventory knows about waiting orders besides stock levels.
+///
+/// The ledger alone rebuilds levels; this is what storage keeps so waiting
+/// orders are still allocated, and their units returned on cancel, after a restart.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BackorderState {
+    pub backorders: Vec<Backorder>,
+    pub held: BTreeMap<u32, Vec<(u32, u32)>>, // order_id -> (product_id, quantity) already taken
+    pub ready_orders: Vec<u32>,
+    pub expected_receipts: BTreeMap<u32, DateTime<Utc>>,
+}
+
+/// Result of reserving stock for a whole order
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reservation {
+    /// Every line was taken from stock
+    Reserved,
+    /// Some lines are waiting for stock; the order is in the backorder queue
+    Waiting,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum InventoryError {
+    InvalidQuantity,
+    WrongDirection(MovementReason),
+    InsufficientStock { product_id: u32, requested: u32, available: u32 },
+    StockOverflow { product_id: u32 },
+    /// A stored movement would take the level below zero or past `u32::MAX`
+    InvalidLedger { movement_id: u64, product_id: u32 },
+    /// A bundle may not wait for stock: its components' own policies are not known here
+    BundlePolicy { product_id: u32 },
+}
+
+impl fmt::Display for InventoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InventoryError::InvalidQuantity => write!(f, "Stock movement quantity must be between 1 and {}", u32::MAX),
+            InventoryError::WrongDirection(reason) => {
+                write!(f, "{} movements cannot change stock in that direction", reason)
+            }
+            InventoryError::InsufficientStock { product_id, requested, available } => write!(
+                f,
+                "Insufficient stock for product {}: requested {}, available {}",
+                product_id, requested, available
+            ),
+            InventoryError::StockOverflow { product_id } => {
+                write!(f, "Stock level for product {} would overflow", product_id)
+            }
+            InventoryError::InvalidLedger { movement_id, product_id } => write!(
+                f, "Movement #{} takes product {} outside the valid stock range", movement_id, product_id),
+            InventoryError::BundlePolicy { product_id } => {
+                write!(f, "Bundle {} can only be sold from stock on hand", product_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InventoryError {}
+
+#[derive(Debug, Default)]
 pub struct Inventory {
     stock: HashMap<u32, u32>, // product_id -> quantity
+    movements: Vec<StockMovement>,
+    backorders: VecDeque<Backorder>, // FIFO across all products
+    expected_receipts: HashMap<u32, DateTime<Utc>>, // product_id -> next delivery
+    ready_orders: Vec<u32>,
+    held: HashMap<u32, Vec<(u32, u32)>>, // order_id -> units already taken while other lines wait
 }
 
 impl Inventory {
     pub fn new() -> Self {
         Inventory {
             stock: HashMap::new(),
+            movements: Vec::new(),
+            backorders: VecDeque::new(),
+            expected_receipts: HashMap::new(),
+            ready_orders: Vec::new(),
+            held: HashMap::new(),
+        }
+    }
+
+    /// Rebuilds an inventory by replaying a ledger of movements; fails if any level leaves the `u32` range
+    pub fn from_movements(movements: Vec<StockMovement>) -> Result<Self, InventoryError> {
+        let mut inventory = Inventory::new();
+        for movement in &movements {
+            let level = inventory.stock.entry(movement.product_id).or_insert(0);
+            *level = (*level as i64)
+                .checked_add(movement.delta)
+                .and_then(|level| u32::try_from(level).ok())
+                .ok_or(InventoryError::InvalidLedger { movement_id: movement.id, product_id: movement.product_id })?;
+        }
+        inventory.movements = movements;
+        Ok(inventory)
+    }
+
+    /// Copies out the backorder queue and everything held for it
+    pub fn backorder_state(&self) -> BackorderState {
+        BackorderState {
+            backorders: self.backorders.iter().cloned().collect(),
+            held: self.held.iter().map(|(order_id, lines)| (*order_id, lines.clone())).collect(),
+            ready_orders: self.ready_orders.clone(),
+            expected_receipts: self.expected_receipts.iter().map(|(id, at)| (*id, *at)).collect(),
         }
     }
 
-    pub fn add_stock(&mut self, product_id: u32, quantity: u32) {
-        *self.stock.entry(product_id).or_insert(0) += quantity;
+    /// Replaces the backorder queue, e.g. with one saved before a restart
+    pub fn restore_backorders(&mut self, state: BackorderState) {
+        self.backorders = state.backorders.into();
+        self.held = state.held.into_iter().collect();
+        self.ready_orders = state.ready_orders;
+        self.expected_receipts = state.expected_receipts.into_iter().collect();
+    }
+
+    /// Records stock received from a supplier
+    pub fn add_stock(&mut self, product_id: u32, quantity: u32) -> Result<(), InventoryError> {
+        if quantity > 0 {
+            self.adjust(product_id, quantity as i64, MovementReason::Receipt, SYSTEM_ACTOR)?;
+        }
+        Ok(())
+    }
+
+    /// Records a sale; leaves stock untouched if there is not enough
+    pub fn remove_stock(&mut self, product_id: u32, quantity: u32) -> Result<(), InventoryError> {
+        if quantity > 0 {
+            self.adjust(product_id, -(quantity as i64), MovementReason::Sale, SYSTEM_ACTOR)?;
+        }
+        Ok(())
     }
 
-    pub fn remove_stock(&mut self, product_id: u32, quantity: u32) -> bool {
-        if let Some(current_quantity) = self.stock.get_mut(&product_id) {
-            if *current_quantity >= quantity {
-                *current_quantity -= quantity;
-                return true;
+    /// Posts a movement to the ledger and updates the level, returning the movement id.
+    ///
+    /// Stock coming in is offered to waiting backorders first.
+    pub fn adjust(
+        &mut self,
+        product_id: u32,
+        delta: i64,
+        reason: MovementReason,
+        actor: &str,
+    ) -> Result<u64, InventoryError> {
+        self.adjust_at(product_id, delta, reason, actor, Utc::now())
+    }
+
+    /// Like `adjust`, but stamps the movement with `at` rather than the current time
+    pub fn adjust_at(
+        &mut self,
+        product_id: u32,
+        delta: i64,
+        reason: MovementReason,
+        actor: &str,
+        at: DateTime<Utc>,
+    ) -> Result<u64, InventoryError> {
+        if delta == 0 {
+            return Err(InventoryError::InvalidQuantity);
+        }
+        if reason.direction().is_some_and(|adds| adds != (delta > 0)) {
+            return Err(InventoryError::WrongDirection(reason));
+        }
+
+        let quantity = u32::try_from(delta.unsigned_abs()).map_err(|_| InventoryError::InvalidQuantity)?;
+        let available = self.check_stock(product_id);
+        let new_level = if delta > 0 {
+            available.checked_add(quantity).ok_or(InventoryError::StockOverflow { product_id })?
+        } else {
+            available
+                .checked_sub(quantity)
+                .ok_or(InventoryError::InsufficientStock { product_id, requested: quantity, available })?
+        };
+
+        self.stock.insert(product_id, new_level);
+        let id = self.movements.len() as u64 + 1;
+        self.movements.push(StockMovement {
+            id,
+            product_id,
+            delta,
+            reason,
+            actor: actor.to_string(),
+            at,
+        });
+
+        if delta > 0 {
+            self.allocate_backorders(product_id);
+        }
+        Ok(id)
+    }
+
+    /// Hands available stock to waiting lines for the product, oldest first
+    fn allocate_backorders(&mut self, product_id: u32) {
+        let mut completed = Vec::new();
+        for index in 0..self.backorders.len() {
+            let available = self.check_stock(product_id);
+            if available == 0 {
+                break;
+            }
+            let line = &self.backorders[index];
+            if line.product_id != product_id {
+                continue;
             }
+
+            let order_id = line.order_id;
+            let quantity = line.outstanding.min(available);
+            self.adjust(product_id, -(quantity as i64), MovementReason::Sale, BACKORDER_ACTOR)
+                .expect("Allocation never exceeds available stock");
+            let line = &mut self.backorders[index];
+            line.allocated += quantity;
+            line.outstanding -= quantity;
+            self.held.entry(order_id).or_default().push((product_id, quantity));
+            if line.outstanding == 0 {
+                completed.push(order_id);
+            }
+        }
+
+        self.backorders.retain(|line| line.outstanding > 0);
+        for order_id in completed {
+            if !self.is_waiting(order_id) && !self.ready_orders.contains(&order_id) {
+                self.ready_orders.push(order_id);
+            }
+        }
+    }
+
+    /// Takes stock for every line of an order.
+    ///
+    /// Bundles take stock from each of their components and must be fully
+    /// available, as must lines for in-stock-only products, or nothing is
+    /// taken. Backorder and pre-order lines take what is on hand and queue
+    /// the rest behind earlier waiting orders.
+    pub fn reserve_order(&mut self, order: &Order) -> Result<Reservation, InventoryError> {
+        let mut lines: Vec<(u32, u32, &StockPolicy)> = Vec::new();
+        for line in order.lines() {
+            if line.product.is_bundle() && line.product.stock_policy != StockPolicy::InStockOnly {
+                return Err(InventoryError::BundlePolicy { product_id: line.product.id });
+            }
+            for (product_id, quantity) in line.product.stock_lines(line.quantity)? {
+                lines.push((product_id, quantity, &line.product.stock_policy));
+            }
+        }
+
+        let mut requested: HashMap<u32, u32> = HashMap::new();
+        for &(product_id, quantity, _) in &lines {
+            let total = requested.entry(product_id).or_insert(0);
+            *total = total.checked_add(quantity).ok_or(InventoryError::InvalidQuantity)?;
+        }
+        for &(product_id, _, policy) in &lines {
+            let wanted = requested[&product_id];
+            let available = self.check_stock(product_id);
+            if *policy == StockPolicy::InStockOnly && available < wanted {
+                return Err(InventoryError::InsufficientStock { product_id, requested: wanted, available });
+            }
+        }
+
+        let mut reservation = Reservation::Reserved;
+        let mut taken_lines = Vec::new();
+        for &(product_id, quantity, policy) in &lines {
+            let on_hand = if self.is_product_waiting(product_id) { 0 } else { self.check_stock(product_id) };
+            let taken = on_hand.min(quantity);
+            if taken > 0 {
+                self.adjust(product_id, -(taken as i64), MovementReason::Sale, SYSTEM_ACTOR)
+                    .expect("Reservation never exceeds available stock");
+                taken_lines.push((product_id, taken));
+            }
+            if taken < quantity {
+                let release = match policy {
+                    StockPolicy::PreOrder { release } => Some(*release),
+                    _ => None,
+                };
+                self.backorders.push_back(Backorder {
+                    order_id: order.id,
+                    product_id,
+                    allocated: taken,
+                    outstanding: quantity - taken,
+                    release,
+                });
+                reservation = Reservation::Waiting;
+            }
+        }
+        if reservation == Reservation::Waiting {
+            self.held.insert(order.id, taken_lines);
+        }
+        Ok(reservation)
+    }
+
+    /// Puts every unit of a reserved order back on the shelf, e.g. when it is cancelled
+    pub fn return_order(&mut self, order: &Order, actor: &str) -> Result<(), InventoryError> {
+        for line in order.lines() {
+            for (product_id, quantity) in line.product.stock_lines(line.quantity)? {
+                self.adjust(product_id, quantity as i64, MovementReason::Return, actor)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sells `quantity` of a product, taking each component of a bundle; all or nothing
+    pub fn remove_product(&mut self, product: &Product, quantity: u32) -> Result<(), InventoryError> {
+        if quantity == 0 {
+            return Err(InventoryError::InvalidQuantity);
+        }
+        let lines = product.stock_lines(quantity)?;
+        for &(product_id, wanted) in &lines {
+            let available = self.check_stock(product_id);
+            if available < wanted {
+                return Err(InventoryError::InsufficientStock { product_id, requested: wanted, available });
+            }
+        }
+        for (product_id, quantity) in lines {
+            self.adjust(product_id, -(quantity as i64), MovementReason::Sale, SYSTEM_ACTOR)
+                .expect("Availability checked above");
+        }
+        Ok(())
+    }
+
+    /// Units that can be sold now; for a bundle, how many complete sets its components make
+    pub fn available(&self, product: &Product) -> u32 {
+        if !product.is_bundle() {
+            return self.check_stock(product.id);
         }
-        false
+        product.components
+            .iter()
+            .map(|(id, per_bundle)| self.check_stock(*id) / per_bundle)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Drops an order's waiting lines and returns every unit already taken for it,
+    /// including lines that were filled in full and orders not yet taken as ready
+    pub fn cancel_backorder(&mut self, order_id: u32) -> bool {
+        let (cancelled, kept): (Vec<Backorder>, Vec<Backorder>) =
+            self.backorders.drain(..).partition(|line| line.order_id == order_id);
+        self.backorders = kept.into();
+        let was_ready = self.ready_orders.contains(&order_id);
+        self.ready_orders.retain(|id| *id != order_id);
+        for (product_id, quantity) in self.held.remove(&order_id).unwrap_or_default() {
+            self.adjust(product_id, quantity as i64, MovementReason::Return, BACKORDER_ACTOR)
+                .expect("Returns always add stock");
+        }
+        !cancelled.is_empty() || was_ready
+    }
+
+    fn is_waiting(&self, order_id: u32) -> bool {
+        self.backorders.iter().any(|line| line.order_id == order_id)
+    }
+
+    fn is_product_waiting(&self, product_id: u32) -> bool {
+        self.backorders.iter().any(|line| line.product_id == product_id)
+    }
+
+    /// Lines still waiting for stock, oldest first
+    pub fn backorders(&self) -> impl Iterator<Item = &Backorder> {
+        self.backorders.iter()
+    }
+
+    /// Ids of backordered orders that have since been fully allocated; clears the list
+    pub fn take_ready_orders(&mut self) -> Vec<u32> {
+        let ready = std::mem::take(&mut self.ready_orders);
+        for order_id in &ready {
+            self.held.remove(order_id);
+        }
+        ready
+    }
+
+    /// Records when the next delivery of a product is due
+    pub fn expect_receipt(&mut self, product_id: u32, at: DateTime<Utc>) {
+        self.expected_receipts.insert(product_id, at);
+    }
+
+    /// When a waiting order should have all its stock; `None` if it is not waiting or a line has no known date
+    pub fn estimated_availability(&self, order_id: u32) -> Option<DateTime<Utc>> {
+        let mut latest = None;
+        for line in self.backorders.iter().filter(|line| line.order_id == order_id) {
+            let estimate = match (line.release, self.expected_receipts.get(&line.product_id)) {
+                (Some(release), Some(receipt)) => release.max(*receipt),
+                (Some(release), None) => release,
+                (None, Some(receipt)) => *receipt,
+                (None, None) => return None,
+            };
+            latest = latest.max(Some(estimate));
+        }
+        latest
+    }
+
+    /// Compares physical counts to the book levels and posts a cycle-count movement for every variance
+    pub fn reconcile(&mut self, counts: &[(u32, u32)], actor: &str) -> Vec<Variance> {
+        let mut variances = Vec::new();
+        for &(product_id, counted) in counts {
+            let expected = self.check_stock(product_id);
+            let delta = counted as i64 - expected as i64;
+            if delta != 0 {
+                self.adjust(product_id, delta, MovementReason::CycleCount, actor)
+                    .expect("Counted level is never negative");
+                variances.push(Variance { product_id, expected, counted, delta });
+            }
+        }
+        variances
     }
 
     pub fn check_stock(&self, product_id: u32) -> u32 {
         *self.stock.get(&product_id).unwrap_or(&0)
     }
 
+    /// Every (product_id, quantity) pair, sorted by product id
+    pub fn levels(&self) -> Vec<(u32, u32)> {
+        let mut levels: Vec<(u32, u32)> = self.stock.iter().map(|(id, qty)| (*id, *qty)).collect();
+        levels.sort_unstable();
+        levels
+    }
+
+    pub fn movements(&self) -> &[StockMovement] {
+        &self.movements
+    }
+
+    pub fn movements_for(&self, product_id: u32) -> Vec<&StockMovement> {
+        self.movements.iter().filter(|m| m.product_id == product_id).collect()
+    }
+
+    /// Sums the ledger for a product; always equals `check_stock`
+    pub fn derived_level(&self, product_id: u32) -> i64 {
+        self.movements_for(product_id).iter().map(|m| m.delta).sum()
+    }
+
+    /// Converts into a handle that can be shared between threads
+    pub fn into_shared(self) -> SharedInventory {
+        SharedInventory::from_levels(self.stock)
+    }
+
     pub fn display_stock(&self) {
-        println!("Current Inventory:");
-        for (product_id, quantity) in &self.stock {
-            println!("Product ID: {}, Quantity: {}", product_id, quantity);
+        print!("{}", self.render_to_string(Format::Plain));
+    }
+
+    pub fn display_movements(&self, product_id: u32) {
+        println!("Stock Movements for Product ID {}:", product_id);
+        for movement in self.movements_for(product_id) {
+            println!("#{} {} {:+} {} by {}",
+                movement.id,
+                movement.at.format("%Y-%m-%d %H:%M:%S"),
+                movement.delta,
+                movement.reason,
+                movement.actor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn movement(id: u64, product_id: u32, delta: i64) -> StockMovement {
+        StockMovement { id, product_id, delta, reason: MovementReason::CycleCount, actor: "test".into(), at: Utc::now() }
+    }
+
+    #[test]
+    fn every_change_is_posted_to_the_ledger() {
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 10).unwrap();
+        inventory.remove_stock(1, 3).unwrap();
+        inventory.adjust(1, -2, MovementReason::Damage, "alice").unwrap();
+
+        assert_eq!(inventory.check_stock(1), 5);
+        assert_eq!(inventory.derived_level(1), 5);
+        let reasons: Vec<_> = inventory.movements_for(1).iter().map(|m| (m.delta, m.reason)).collect();
+        assert_eq!(reasons, vec![(10, MovementReason::Receipt), (-3, MovementReason::Sale), (-2, MovementReason::Damage)]);
+        assert_eq!(inventory.movements()[2].actor, "alice");
+    }
+
+    #[test]
+    fn removing_nothing_is_a_no_op() {
+        let mut inventory = Inventory::new();
+        inventory.remove_stock(1, 0).unwrap();
+        inventory.add_stock(1, 0).unwrap();
+        assert!(inventory.movements().is_empty());
+        assert_eq!(inventory.remove_stock(1, 1), Err(InventoryError::InsufficientStock { product_id: 1, requested: 1, available: 0 }));
+    }
+
+    #[test]
+    fn adjust_rejects_bad_movements_without_posting() {
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 5).unwrap();
+        assert_eq!(inventory.adjust(1, 0, MovementReason::Transfer, "t"), Err(InventoryError::InvalidQuantity));
+        assert_eq!(inventory.adjust(1, 1, MovementReason::Sale, "t"), Err(InventoryError::WrongDirection(MovementReason::Sale)));
+        assert_eq!(inventory.adjust(1, -1, MovementReason::Receipt, "t"), Err(InventoryError::WrongDirection(MovementReason::Receipt)));
+        assert_eq!(
+            inventory.adjust(1, -6, MovementReason::Transfer, "t"),
+            Err(InventoryError::InsufficientStock { product_id: 1, requested: 6, available: 5 })
+        );
+        assert_eq!(inventory.movements().len(), 1);
+    }
+
+    #[test]
+    fn adjust_never_truncates_large_quantities() {
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, u32::MAX - 1).unwrap();
+        assert_eq!(inventory.add_stock(1, 2), Err(InventoryError::StockOverflow { product_id: 1 }));
+        assert_eq!(inventory.adjust(1, -(1i64 << 32), MovementReason::Transfer, "t"), Err(InventoryError::InvalidQuantity));
+        assert_eq!(inventory.adjust(1, i64::MIN, MovementReason::Transfer, "t"), Err(InventoryError::InvalidQuantity));
+        assert_eq!(inventory.check_stock(1), u32::MAX - 1);
+    }
+
+    #[test]
+    fn replaying_the_ledger_rebuilds_levels() {
+        let inventory = Inventory::from_movements(vec![movement(1, 1, 5), movement(2, 2, 3), movement(3, 1, -2)]).unwrap();
+        assert_eq!(inventory.levels(), vec![(1, 3), (2, 3)]);
+        assert_eq!(inventory.movements().len(), 3);
+    }
+
+    #[test]
+    fn replaying_an_impossible_ledger_fails() {
+        let negative = Inventory::from_movements(vec![movement(1, 1, 2), movement(2, 1, -3)]);
+        assert_eq!(negative.unwrap_err(), InventoryError::InvalidLedger { movement_id: 2, product_id: 1 });
+        let overflow = Inventory::from_movements(vec![movement(1, 1, u32::MAX as i64), movement(2, 1, 1)]);
+        assert_eq!(overflow.unwrap_err(), InventoryError::InvalidLedger { movement_id: 2, product_id: 1 });
+    }
+
+    #[test]
+    fn reconcile_posts_only_variances() {
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 10).unwrap();
+        inventory.add_stock(2, 4).unwrap();
+        let variances = inventory.reconcile(&[(1, 8), (2, 4), (3, 1)], "counter");
+        assert_eq!(variances, vec![
+            Variance { product_id: 1, expected: 10, counted: 8, delta: -2 },
+            Variance { product_id: 3, expected: 0, counted: 1, delta: 1 },
+        ]);
+        assert_eq!(inventory.levels(), vec![(1, 8), (2, 4), (3, 1)]);
+        assert_eq!(inventory.movements().last().unwrap().reason, MovementReason::CycleCount);
+    }
+
+    fn waiting_order(id: u32, lines: &[(u32, u32, StockPolicy)]) -> Order {
+        let user = crate::user::User::new_unchecked(1, "Ada".into(), "ada@example.com".into(), "1 Main St".into());
+        let mut order = Order::new(id, user);
+        for (product_id, quantity, policy) in lines {
+            let product = Product::new(*product_id, format!("P{}", product_id), 1.0, String::new()).with_stock_policy(policy.clone());
+            order.add_product(product, *quantity).unwrap();
         }
+        order
+    }
+
+    #[test]
+    fn backorders_are_filled_oldest_first() {
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 2).unwrap();
+        let first = waiting_order(10, &[(1, 5, StockPolicy::Backorder)]);
+        let second = waiting_order(11, &[(1, 2, StockPolicy::Backorder)]);
+        assert_eq!(inventory.reserve_order(&first).unwrap(), Reservation::Waiting);
+        assert_eq!(inventory.reserve_order(&second).unwrap(), Reservation::Waiting);
+        assert_eq!(inventory.check_stock(1), 0);
+
+        inventory.add_stock(1, 4).unwrap();
+        assert_eq!(inventory.check_stock(1), 0);
+        let waiting: Vec<_> = inventory.backorders().map(|b| (b.order_id, b.allocated, b.outstanding)).collect();
+        assert_eq!(waiting, vec![(11, 1, 1)]);
+        assert_eq!(inventory.take_ready_orders(), vec![10]);
+
+        inventory.add_stock(1, 3).unwrap();
+        assert_eq!(inventory.check_stock(1), 2);
+        assert_eq!(inventory.take_ready_orders(), vec![11]);
+        assert!(inventory.take_ready_orders().is_empty());
+    }
+
+    #[test]
+    fn in_stock_only_lines_take_nothing_when_short() {
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 3).unwrap();
+        inventory.add_stock(2, 1).unwrap();
+        let order = waiting_order(10, &[(1, 3, StockPolicy::Backorder), (2, 2, StockPolicy::InStockOnly)]);
+        assert_eq!(
+            inventory.reserve_order(&order),
+            Err(InventoryError::InsufficientStock { product_id: 2, requested: 2, available: 1 })
+        );
+        assert_eq!(inventory.check_stock(1), 3);
+        assert_eq!(inventory.backorders().count(), 0);
+    }
+
+    #[test]
+    fn cancelling_returns_units_held_for_filled_lines() {
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 4).unwrap();
+        inventory.add_stock(2, 1).unwrap();
+        let order = waiting_order(10, &[(1, 4, StockPolicy::Backorder), (2, 3, StockPolicy::Backorder)]);
+        assert_eq!(inventory.reserve_order(&order).unwrap(), Reservation::Waiting);
+        inventory.add_stock(2, 1).unwrap();
+        assert_eq!((inventory.check_stock(1), inventory.check_stock(2)), (0, 0));
+
+        assert!(inventory.cancel_backorder(10));
+        assert_eq!((inventory.check_stock(1), inventory.check_stock(2)), (4, 2));
+        assert_eq!(inventory.backorders().count(), 0);
+        assert!(!inventory.cancel_backorder(10));
+        assert_eq!(inventory.check_stock(1), 4);
+        assert_eq!(inventory.derived_level(1), 4);
+    }
+
+    #[test]
+    fn cancelling_a_ready_order_before_it_is_taken_returns_its_units() {
+        let mut inventory = Inventory::new();
+        let order = waiting_order(10, &[(1, 2, StockPolicy::Backorder)]);
+        inventory.reserve_order(&order).unwrap();
+        inventory.add_stock(1, 2).unwrap();
+
+        assert!(inventory.cancel_backorder(10));
+        assert_eq!(inventory.check_stock(1), 2);
+        assert!(inventory.take_ready_orders().is_empty());
+    }
+
+    #[test]
+    fn taken_ready_orders_are_no_longer_cancellable_here() {
+        let mut inventory = Inventory::new();
+        let order = waiting_order(10, &[(1, 2, StockPolicy::Backorder)]);
+        inventory.reserve_order(&order).unwrap();
+        inventory.add_stock(1, 2).unwrap();
+        assert_eq!(inventory.take_ready_orders(), vec![10]);
+
+        assert!(!inventory.cancel_backorder(10));
+        assert_eq!(inventory.check_stock(1), 0);
+    }
+
+    #[test]
+    fn estimated_availability_uses_release_and_receipt_dates() {
+        let mut inventory = Inventory::new();
+        let release = Utc::now() + chrono::Duration::days(10);
+        let receipt = Utc::now() + chrono::Duration::days(3);
+        let order = waiting_order(10, &[(1, 1, StockPolicy::PreOrder { release }), (2, 1, StockPolicy::Backorder)]);
+        inventory.reserve_order(&order).unwrap();
+        assert_eq!(inventory.estimated_availability(10), None);
+
+        inventory.expect_receipt(2, receipt);
+        assert_eq!(inventory.estimated_availability(10), Some(release));
+        inventory.expect_receipt(2, release + chrono::Duration::days(1));
+        assert_eq!(inventory.estimated_availability(10), Some(release + chrono::Duration::days(1)));
+        assert_eq!(inventory.estimated_availability(99), None);
+    }
+
+    fn bundle_order(id: u32, components: Vec<(u32, u32)>, quantity: u32) -> Order {
+        let user = crate::user::User::new_unchecked(1, "Ada".into(), "ada@example.com".into(), "1 Main St".into());
+        let mut order = Order::new(id, user);
+        let kit = Product::new(9, "Kit".into(), 30.0, String::new()).with_components(components);
+        order.add_product(kit, quantity).unwrap();
+        order
+    }
+
+    #[test]
+    fn bundles_reserve_every_component_or_nothing() {
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 4).unwrap();
+        inventory.add_stock(2, 1).unwrap();
+
+        let order = bundle_order(1, vec![(1, 2), (2, 1)], 2);
+        assert_eq!(
+            inventory.reserve_order(&order),
+            Err(InventoryError::InsufficientStock { product_id: 2, requested: 2, available: 1 })
+        );
+        assert_eq!((inventory.check_stock(1), inventory.check_stock(2)), (4, 1));
+
+        let order = bundle_order(2, vec![(1, 2), (2, 1)], 1);
+        assert_eq!(inventory.reserve_order(&order), Ok(Reservation::Reserved));
+        assert_eq!((inventory.check_stock(1), inventory.check_stock(2)), (2, 0));
+        inventory.return_order(&order, "test").unwrap();
+        assert_eq!((inventory.check_stock(1), inventory.check_stock(2)), (4, 1));
+    }
+
+    #[test]
+    fn bundles_cannot_wait_for_stock() {
+        let mut inventory = Inventory::new();
+        let user = crate::user::User::new_unchecked(1, "Ada".into(), "ada@example.com".into(), "1 Main St".into());
+        let mut order = Order::new(1, user);
+        let kit = Product::new(9, "Kit".into(), 30.0, String::new())
+            .with_components(vec![(1, 1)])
+            .with_stock_policy(StockPolicy::Backorder);
+        order.add_product(kit, 1).unwrap();
+
+        assert_eq!(inventory.reserve_order(&order), Err(InventoryError::BundlePolicy { product_id: 9 }));
+        assert!(inventory.backorders().next().is_none());
+    }
+
+    #[test]
+    fn oversized_bundle_orders_are_rejected_without_taking_stock() {
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 10).unwrap();
+        let order = bundle_order(1, vec![(1, 2)], u32::MAX);
+        assert_eq!(inventory.reserve_order(&order), Err(InventoryError::InvalidQuantity));
+        let kit = Product::new(9, "Kit".into(), 30.0, String::new()).with_components(vec![(1, 2)]);
+        assert_eq!(inventory.remove_product(&kit, u32::MAX), Err(InventoryError::InvalidQuantity));
+        assert_eq!(inventory.check_stock(1), 10);
+    }
+
+    #[test]
+    fn bundle_availability_counts_complete_sets() {
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 7).unwrap();
+        inventory.add_stock(2, 2).unwrap();
+        let kit = Product::new(9, "Kit".into(), 30.0, String::new()).with_components(vec![(1, 3), (2, 1)]);
+        assert_eq!(inventory.available(&kit), 2);
+        inventory.remove_product(&kit, 2).unwrap();
+        assert_eq!(inventory.available(&kit), 0);
+        assert_eq!(inventory.check_stock(1), 1);
     }
 }
@@ -0,0 +1,28 @@
+pub mod product;
+pub mod user;
+pub mod order;
+pub mod inventory;
+pub mod payment;
+pub mod shared_inventory;
+pub mod notification;
+pub mod review;
+pub mod pricing;
+pub mod catalog;
+pub mod csv;
+pub mod search;
+pub mod recommendation;
+pub mod shipment;
+pub mod currency;
+pub mod render;
+pub mod error;
+pub mod storage;
+pub mod shop;
+pub mod gift_card;
+pub mod loyalty;
+pub mod subscription;
+pub mod purchasing;
+pub mod price_history;
+pub mod fraud;
+pub mod webhook;
+pub mod order_query;
+pub mod simulation;
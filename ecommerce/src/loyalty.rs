@@ -0,0 +1,561 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::gift_card::Redemption;
+use crate::order::{Order, OrderStatus};
+use crate::payment::PaymentStatus;
+use crate::user::User;
+
+/// Redemption code recorded on orders paid partly with points
+pub const POINTS_CODE: &str = "LOYALTY-POINTS";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+pub enum Tier {
+    #[default]
+    Member,
+    Silver,
+    Gold,
+}
+
+impl Tier {
+    /// Bonus applied to points earned while in this tier
+    pub fn multiplier(&self) -> f64 {
+        match self {
+            Tier::Member => 1.0,
+            Tier::Silver => 1.25,
+            Tier::Gold => 1.5,
+        }
+    }
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Tier::Member => write!(f, "Member"),
+            Tier::Silver => write!(f, "🥈 Silver"),
+            Tier::Gold => write!(f, "🥇 Gold"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PointsKind {
+    Earned { order_id: u32 },
+    Redeemed { order_id: u32 },
+    Reversed { order_id: u32 },
+    Restored { order_id: u32 },
+    Expired,
+}
+
+impl fmt::Display for PointsKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PointsKind::Earned { order_id } => write!(f, "Earned on order #{}", order_id),
+            PointsKind::Redeemed { order_id } => write!(f, "Redeemed on order #{}", order_id),
+            PointsKind::Reversed { order_id } => write!(f, "Reversed for order #{}", order_id),
+            PointsKind::Restored { order_id } => write!(f, "Restored from order #{}", order_id),
+            PointsKind::Expired => write!(f, "Expired"),
+        }
+    }
+}
+
+/// One change to a customer's points; `points` is negative for debits
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointsEntry {
+    pub id: u64,
+    pub user_id: u32,
+    pub kind: PointsKind,
+    pub points: i64,
+    pub at: DateTime<Utc>,
+}
+
+/// Points credited together, spent oldest-expiry first
+#[derive(Debug, Clone)]
+struct Lot {
+    user_id: u32,
+    order_id: u32,
+    remaining: u32,
+    expired: u32, // written off unspent, so not clawed back on reversal
+    expires_at: DateTime<Utc>,
+}
+
+/// Spend that counts towards tiers
+#[derive(Debug, Clone)]
+struct Spend {
+    user_id: u32,
+    order_id: u32,
+    amount: f64,
+    at: DateTime<Utc>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LoyaltyError {
+    OrderNotDelivered,
+    AlreadyCredited,
+    InvalidPoints,
+    InsufficientPoints { available: u32 },
+    OrderNotPayable,
+    /// Only cancelled orders and delivered orders whose payment was refunded can be reversed
+    OrderNotReversible,
+}
+
+impl fmt::Display for LoyaltyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoyaltyError::OrderNotDelivered => write!(f, "Points are only earned on delivered orders"),
+            LoyaltyError::AlreadyCredited => write!(f, "Order has already earned points"),
+            LoyaltyError::InvalidPoints => write!(f, "Points to redeem must be positive"),
+            LoyaltyError::InsufficientPoints { available } => write!(f, "Not enough points: {} available", available),
+            LoyaltyError::OrderNotPayable => write!(f, "Order can no longer take a points discount"),
+            LoyaltyError::OrderNotReversible => write!(f, "Only cancelled or returned orders can be reversed"),
+        }
+    }
+}
+
+impl std::error::Error for LoyaltyError {}
+
+/// Earn rates, tiers and every customer's points
+#[derive(Debug)]
+pub struct LoyaltyProgram {
+    base_rate: f64,                      // points per unit of base currency
+    category_rates: HashMap<String, f64>, // lowercase category -> points per unit
+    silver_spend: f64,
+    gold_spend: f64,
+    spend_window: Duration,
+    points_lifetime: Duration,
+    point_value: f64, // discount per point, in base currency
+    lots: Vec<Lot>,
+    spends: Vec<Spend>,
+    entries: Vec<PointsEntry>,
+    credited: HashSet<u32>, // orders that have earned points
+    reversed: HashSet<u32>,
+}
+
+impl Default for LoyaltyProgram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoyaltyProgram {
+    /// One point per dollar; silver from $500 and gold from $2,000 spent in the last year;
+    /// points last a year and are worth a cent each
+    pub fn new() -> Self {
+        LoyaltyProgram {
+            base_rate: 1.0,
+            category_rates: HashMap::new(),
+            silver_spend: 500.0,
+            gold_spend: 2_000.0,
+            spend_window: Duration::days(365),
+            points_lifetime: Duration::days(365),
+            point_value: 0.01,
+            lots: Vec::new(),
+            spends: Vec::new(),
+            entries: Vec::new(),
+            credited: HashSet::new(),
+            reversed: HashSet::new(),
+        }
+    }
+
+    pub fn with_base_rate(mut self, points_per_unit: f64) -> Self {
+        self.base_rate = points_per_unit;
+        self
+    }
+
+    /// Overrides the earn rate for products in a category
+    pub fn with_category_rate(mut self, category: &str, points_per_unit: f64) -> Self {
+        self.category_rates.insert(category.trim().to_lowercase(), points_per_unit);
+        self
+    }
+
+    /// Rolling spend needed for each tier over `window`
+    pub fn with_tiers(mut self, silver_spend: f64, gold_spend: f64, window: Duration) -> Self {
+        self.silver_spend = silver_spend;
+        self.gold_spend = gold_spend;
+        self.spend_window = window;
+        self
+    }
+
+    pub fn with_points_lifetime(mut self, lifetime: Duration) -> Self {
+        self.points_lifetime = lifetime;
+        self
+    }
+
+    pub fn with_point_value(mut self, value: f64) -> Self {
+        self.point_value = value;
+        self
+    }
+
+    fn rate_for(&self, category: &str) -> f64 {
+        self.category_rates.get(&category.to_lowercase()).copied().unwrap_or(self.base_rate)
+    }
+
+    fn post(&mut self, user_id: u32, kind: PointsKind, points: i64, at: DateTime<Utc>) {
+        let id = self.entries.len() as u64 + 1;
+        self.entries.push(PointsEntry { id, user_id, kind, points, at });
+    }
+
+    /// Spend in the tier window ending at `now`
+    pub fn rolling_spend(&self, user_id: u32, now: DateTime<Utc>) -> f64 {
+        self.spends
+            .iter()
+            .filter(|s| s.user_id == user_id && s.at <= now && s.at > now - self.spend_window)
+            .map(|s| s.amount)
+            .sum()
+    }
+
+    pub fn tier(&self, user_id: u32, now: DateTime<Utc>) -> Tier {
+        let spend = self.rolling_spend(user_id, now);
+        if spend >= self.gold_spend {
+            Tier::Gold
+        } else if spend >= self.silver_spend {
+            Tier::Silver
+        } else {
+            Tier::Member
+        }
+    }
+
+    /// Points that can be spent at `now`
+    pub fn balance(&self, user_id: u32, now: DateTime<Utc>) -> u32 {
+        self.lots
+            .iter()
+            .filter(|lot| lot.user_id == user_id && lot.expires_at > now)
+            .map(|lot| lot.remaining)
+            .sum()
+    }
+
+    /// Credits points for a delivered order at the customer's current tier
+    pub fn earn(&mut self, order: &Order, now: DateTime<Utc>) -> Result<u32, LoyaltyError> {
+        if order.status != OrderStatus::Delivered {
+            return Err(LoyaltyError::OrderNotDelivered);
+        }
+        if self.credited.contains(&order.id) || self.reversed.contains(&order.id) {
+            return Err(LoyaltyError::AlreadyCredited);
+        }
+
+        let multiplier = self.tier(order.user.id, now).multiplier();
+        let mut points = 0.0;
+        let mut spend = 0.0;
+        for line in order.lines() {
+            let amount = line.price.unit_price * line.quantity as f64;
+            points += amount * self.rate_for(&line.product.category) * multiplier;
+            spend += amount;
+        }
+        // Money paid with points does not earn more points
+        let discount: f64 = order.redemptions.iter().filter(|r| r.code == POINTS_CODE).map(|r| r.amount).sum();
+        if spend > 0.0 {
+            points *= ((spend - discount) / spend).max(0.0);
+        }
+        let points = points.floor() as u32;
+
+        self.credited.insert(order.id);
+        self.spends.push(Spend { user_id: order.user.id, order_id: order.id, amount: spend, at: now });
+        if points > 0 {
+            self.lots.push(Lot {
+                user_id: order.user.id,
+                order_id: order.id,
+                remaining: points,
+                expired: 0,
+                expires_at: now + self.points_lifetime,
+            });
+            self.post(order.user.id, PointsKind::Earned { order_id: order.id }, points as i64, now);
+        }
+        Ok(points)
+    }
+
+    /// Takes up to `points` from the customer's unexpired lots, soonest-expiring first
+    fn debit(&mut self, user_id: u32, points: u32, now: DateTime<Utc>) -> u32 {
+        let mut lots: Vec<&mut Lot> = self.lots
+            .iter_mut()
+            .filter(|lot| lot.user_id == user_id && lot.expires_at > now && lot.remaining > 0)
+            .collect();
+        lots.sort_by_key(|lot| lot.expires_at);
+
+        let mut left = points;
+        for lot in lots {
+            let taken = lot.remaining.min(left);
+            lot.remaining -= taken;
+            left -= taken;
+            if left == 0 {
+                break;
+            }
+        }
+        points - left
+    }
+
+    /// Spends points as a discount on an unpaid order, returning the discount in the base currency
+    pub fn redeem(&mut self, order: &mut Order, points: u32, now: DateTime<Utc>) -> Result<f64, LoyaltyError> {
+        if points == 0 {
+            return Err(LoyaltyError::InvalidPoints);
+        }
+        if order.status != OrderStatus::Pending || !matches!(order.payment, PaymentStatus::Unpaid | PaymentStatus::Failed { .. }) {
+            return Err(LoyaltyError::OrderNotPayable);
+        }
+        let available = self.balance(order.user.id, now);
+        if available < points {
+            return Err(LoyaltyError::InsufficientPoints { available });
+        }
+
+        // Never discount more than is still owed
+        let outstanding = order.total - order.redeemed_total();
+        let points = points.min((outstanding / self.point_value).floor().max(0.0) as u32);
+        if points == 0 {
+            return Ok(0.0);
+        }
+        let taken = self.debit(order.user.id, points, now);
+        let discount = (taken as f64 * self.point_value * 100.0).round() / 100.0;
+        self.post(order.user.id, PointsKind::Redeemed { order_id: order.id }, -(taken as i64), now);
+        order.redemptions.push(Redemption { code: POINTS_CODE.to_string(), amount: discount });
+        Ok(discount)
+    }
+
+    /// Undoes an order's effect on points when it is cancelled or its goods are returned.
+    ///
+    /// Points it earned are taken back (from other lots if already spent, but
+    /// never below zero), its spend stops counting towards tiers, and points
+    /// spent on it are restored. Returns the net change in points. A returned
+    /// order is one that was delivered and then refunded.
+    pub fn reverse(&mut self, order: &Order, now: DateTime<Utc>) -> Result<i64, LoyaltyError> {
+        let returned = order.status == OrderStatus::Delivered && matches!(order.payment, PaymentStatus::Refunded { .. });
+        if order.status != OrderStatus::Cancelled && !returned {
+            return Err(LoyaltyError::OrderNotReversible);
+        }
+        if !self.reversed.insert(order.id) {
+            return Ok(0);
+        }
+        let user_id = order.user.id;
+        let mut change = 0;
+
+        if self.credited.contains(&order.id) {
+            // Write off anything past expiry first so it is not mistaken for unspent points
+            self.expire(now);
+            self.credited.remove(&order.id);
+            let earned: i64 = self.entries
+                .iter()
+                .filter(|e| e.kind == (PointsKind::Earned { order_id: order.id }))
+                .map(|e| e.points)
+                .sum();
+            // Points that expired unspent are already gone; only the rest is owed back
+            let mut owed = earned.max(0) as u32;
+            let mut from_lot = 0;
+            if let Some(lot) = self.lots.iter_mut().find(|lot| lot.order_id == order.id && lot.user_id == user_id) {
+                owed = owed.saturating_sub(lot.expired);
+                from_lot = lot.remaining.min(owed);
+                lot.remaining = 0;
+            }
+            let clawed_back = from_lot + self.debit(user_id, owed - from_lot, now);
+            if clawed_back > 0 {
+                self.post(user_id, PointsKind::Reversed { order_id: order.id }, -(clawed_back as i64), now);
+                change -= clawed_back as i64;
+            }
+            self.spends.retain(|s| s.order_id != order.id);
+        }
+
+        let spent: i64 = self.entries
+            .iter()
+            .filter(|e| e.kind == (PointsKind::Redeemed { order_id: order.id }))
+            .map(|e| -e.points)
+            .sum();
+        if spent > 0 {
+            self.lots.push(Lot { user_id, order_id: order.id, remaining: spent as u32, expired: 0, expires_at: now + self.points_lifetime });
+            self.post(user_id, PointsKind::Restored { order_id: order.id }, spent, now);
+            change += spent;
+        }
+        Ok(change)
+    }
+
+    /// Writes off every lot past its expiry, recording one entry per customer
+    pub fn expire(&mut self, now: DateTime<Utc>) -> u32 {
+        let mut expired: HashMap<u32, u32> = HashMap::new();
+        for lot in self.lots.iter_mut().filter(|lot| lot.expires_at <= now && lot.remaining > 0) {
+            *expired.entry(lot.user_id).or_insert(0) += lot.remaining;
+            lot.expired += lot.remaining;
+            lot.remaining = 0;
+        }
+        // Lots of orders that can still be reversed are kept even when empty
+        let credited = &self.credited;
+        self.lots.retain(|lot| lot.remaining > 0 || credited.contains(&lot.order_id));
+
+        let mut users: Vec<(u32, u32)> = expired.into_iter().collect();
+        users.sort_unstable();
+        for &(user_id, points) in &users {
+            self.post(user_id, PointsKind::Expired, -(points as i64), now);
+        }
+        users.iter().map(|(_, points)| points).sum()
+    }
+
+    /// Copies the customer's current balance and tier onto the user
+    pub fn refresh(&self, user: &mut User, now: DateTime<Utc>) {
+        user.loyalty_points = self.balance(user.id, now);
+        user.tier = self.tier(user.id, now);
+    }
+
+    /// A customer's points history, oldest first
+    pub fn entries_for(&self, user_id: u32) -> Vec<&PointsEntry> {
+        self.entries.iter().filter(|e| e.user_id == user_id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    use crate::product::Product;
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap()
+    }
+
+    fn order(id: u32, price: f64) -> Order {
+        let user = User::new_unchecked(1, "Ada".into(), "ada@example.com".into(), "1 Main St".into());
+        let mut order = Order::new(id, user);
+        order.add_product(Product::new(id, format!("P{}", id), price, String::new()), 1).unwrap();
+        order
+    }
+
+    fn delivered(id: u32, price: f64) -> Order {
+        let mut order = order(id, price);
+        order.status = OrderStatus::Delivered;
+        order
+    }
+
+    /// A delivered order whose payment was refunded
+    fn returned(id: u32, price: f64) -> Order {
+        let mut order = delivered(id, price);
+        order.payment = PaymentStatus::Refunded { transaction_id: "txn".into(), amount: price, currency: "USD".into() };
+        order
+    }
+
+    fn cancelled(mut order: Order) -> Order {
+        order.status = OrderStatus::Cancelled;
+        order
+    }
+
+    #[test]
+    fn earns_once_per_delivered_order() {
+        let mut program = LoyaltyProgram::new();
+        assert_eq!(program.earn(&order(1, 100.0), start()), Err(LoyaltyError::OrderNotDelivered));
+        assert_eq!(program.earn(&delivered(1, 100.0), start()), Ok(100));
+        assert_eq!(program.earn(&delivered(1, 100.0), start()), Err(LoyaltyError::AlreadyCredited));
+        assert_eq!(program.balance(1, start()), 100);
+    }
+
+    #[test]
+    fn tiers_raise_the_earn_rate() {
+        let mut program = LoyaltyProgram::new().with_tiers(150.0, 1_000.0, Duration::days(30));
+        program.earn(&delivered(1, 200.0), start()).unwrap();
+        assert_eq!(program.tier(1, start()), Tier::Silver);
+        assert_eq!(program.earn(&delivered(2, 100.0), start()), Ok(125));
+        // Spend drops out of the window
+        assert_eq!(program.tier(1, start() + Duration::days(31)), Tier::Member);
+    }
+
+    #[test]
+    fn redeeming_is_capped_by_balance_and_amount_owed() {
+        let mut program = LoyaltyProgram::new();
+        program.earn(&delivered(1, 500.0), start()).unwrap();
+
+        let mut small = order(2, 2.0);
+        assert_eq!(program.redeem(&mut small, 0, start()), Err(LoyaltyError::InvalidPoints));
+        assert_eq!(program.redeem(&mut small, 501, start()), Err(LoyaltyError::InsufficientPoints { available: 500 }));
+        assert_eq!(program.redeem(&mut small, 400, start()), Ok(2.0));
+        assert_eq!(program.balance(1, start()), 300);
+
+        let mut shipped = order(3, 50.0);
+        shipped.status = OrderStatus::Shipped;
+        assert_eq!(program.redeem(&mut shipped, 10, start()), Err(LoyaltyError::OrderNotPayable));
+    }
+
+    #[test]
+    fn expired_points_cannot_be_spent() {
+        let mut program = LoyaltyProgram::new().with_points_lifetime(Duration::days(10));
+        program.earn(&delivered(1, 100.0), start()).unwrap();
+        let later = start() + Duration::days(10);
+        assert_eq!(program.balance(1, later), 0);
+        assert_eq!(program.expire(later), 100);
+        assert_eq!(program.expire(later), 0);
+        assert_eq!(program.entries_for(1).last().unwrap().kind, PointsKind::Expired);
+    }
+
+    #[test]
+    fn reversal_claws_back_points_spent_elsewhere() {
+        let mut program = LoyaltyProgram::new();
+        program.earn(&delivered(1, 100.0), start()).unwrap();
+        program.earn(&delivered(2, 100.0), start() + Duration::days(1)).unwrap();
+
+        // Spending takes the soonest-expiring lot, which is order 1's
+        let mut purchase = order(3, 10.0);
+        program.redeem(&mut purchase, 100, start() + Duration::days(2)).unwrap();
+        // An unrelated expiry pass must not forget the emptied lot
+        program.expire(start() + Duration::days(2));
+
+        assert_eq!(program.reverse(&returned(1, 100.0), start() + Duration::days(3)), Ok(-100));
+        assert_eq!(program.balance(1, start() + Duration::days(3)), 0);
+        assert_eq!(program.reverse(&returned(1, 100.0), start() + Duration::days(3)), Ok(0));
+    }
+
+    #[test]
+    fn reversal_never_takes_more_than_the_customer_has() {
+        let mut program = LoyaltyProgram::new();
+        program.earn(&delivered(1, 100.0), start()).unwrap();
+        let mut purchase = order(2, 10.0);
+        program.redeem(&mut purchase, 60, start()).unwrap();
+
+        assert_eq!(program.reverse(&returned(1, 100.0), start()), Ok(-40));
+        assert_eq!(program.balance(1, start()), 0);
+    }
+
+    #[test]
+    fn reversal_skips_points_that_already_expired() {
+        let mut program = LoyaltyProgram::new().with_points_lifetime(Duration::days(10));
+        program.earn(&delivered(1, 100.0), start()).unwrap();
+        let mut purchase = order(2, 10.0);
+        program.redeem(&mut purchase, 30, start()).unwrap();
+        program.earn(&delivered(3, 100.0), start() + Duration::days(5)).unwrap();
+
+        // Order 1's unspent 70 points expire; only the 30 it funded are owed back
+        let later = start() + Duration::days(11);
+        assert_eq!(program.reverse(&returned(1, 100.0), later), Ok(-30));
+        assert_eq!(program.balance(1, later), 70);
+    }
+
+    #[test]
+    fn reversing_a_points_purchase_restores_them() {
+        let mut program = LoyaltyProgram::new();
+        program.earn(&delivered(1, 100.0), start()).unwrap();
+        let mut purchase = order(2, 10.0);
+        program.redeem(&mut purchase, 50, start()).unwrap();
+
+        let mut purchase = cancelled(purchase);
+        assert_eq!(program.reverse(&purchase, start()), Ok(50));
+        assert_eq!(program.balance(1, start()), 100);
+        purchase.status = OrderStatus::Delivered;
+        assert_eq!(program.earn(&purchase, start()), Err(LoyaltyError::AlreadyCredited));
+    }
+
+    #[test]
+    fn only_cancelled_or_returned_orders_are_reversed() {
+        let mut program = LoyaltyProgram::new();
+        program.earn(&delivered(1, 100.0), start()).unwrap();
+
+        let mut pending = order(2, 10.0);
+        program.redeem(&mut pending, 20, start()).unwrap();
+        assert_eq!(program.reverse(&pending, start()), Err(LoyaltyError::OrderNotReversible));
+        assert_eq!(program.reverse(&delivered(1, 100.0), start()), Err(LoyaltyError::OrderNotReversible));
+        assert_eq!(program.balance(1, start()), 80);
+
+        // A rejected reversal is not recorded, so the order can still earn and be reversed later
+        pending.status = OrderStatus::Delivered;
+        assert_eq!(program.earn(&pending, start()), Ok(9)); // 10.00 less the 0.20 points discount
+        let mut shipped = order(3, 10.0);
+        shipped.status = OrderStatus::Shipped;
+        shipped.payment = PaymentStatus::Refunded { transaction_id: "txn".into(), amount: 10.0, currency: "USD".into() };
+        assert_eq!(program.reverse(&shipped, start()), Err(LoyaltyError::OrderNotReversible));
+        // Taking back the 9 earned and restoring the 20 spent
+        assert_eq!(program.reverse(&cancelled(pending), start()), Ok(11));
+    }
+}
@@ -1,19 +1,50 @@
-mod product;
-mod user;
-mod order;
-mod inventory;
-
-use product::Product;
-use user::User;
-use order::{Order, OrderStatus};
-use inventory::Inventory;
+use ecommerce::product::{Product, StockPolicy};
+use ecommerce::user::User;
+use ecommerce::order::{Order, OrderStatus};
+use ecommerce::inventory::{Inventory, MovementReason, Reservation};
+use ecommerce::payment::{MockGateway, MockResponse};
+use ecommerce::review::ReviewBoard;
+use ecommerce::error::{self, Error};
+use ecommerce::gift_card::GiftCardBook;
+use ecommerce::loyalty::LoyaltyProgram;
+use ecommerce::subscription::{Cadence, SubscriptionBook};
+use ecommerce::catalog::Catalog;
+use ecommerce::purchasing::{Purchasing, Supplier};
+use ecommerce::render::{Format, Render};
+use ecommerce::currency::{Currency, RateTable, Rounding, RoundingMode};
+use ecommerce::recommendation::Recommender;
+use ecommerce::search::{SearchFilter, SearchIndex};
+use ecommerce::shop::Shop;
+use ecommerce::storage::{FileStorage, MemoryStorage, Storage};
+use ecommerce::pricing::{PricingEngine, RuleCondition};
+use ecommerce::price_history::PriceHistory;
+use ecommerce::fraud::FraudScreen;
+use ecommerce::order_query::{self, OrderQuery};
+use ecommerce::webhook::{self, EventKind, RetryPolicy, Transport, WebhookDispatcher, WebhookError, WebhookNotifier};
+use chrono::{Duration, Utc};
+use ecommerce::notification::{Notifiers, OutboxNotifier, StdoutNotifier, TemplateSet};
+use std::env;
+use std::io;
+use std::sync::{Arc, Mutex};
 
 fn main() {
     println!("🏪 E-Commerce System Demo");
     println!("========================\n");
 
-    // Initialize the inventory system
+    // Initialize the inventory system and payment gateway
     let mut inventory = Inventory::new();
+    let mut gateway = MockGateway::new();
+
+    // Customer notifications go to stdout and to an outbox of .eml files
+    let outbox_dir = env::temp_dir().join("ecommerce-outbox");
+    let mut notifiers = Notifiers::new().with_error_sink(Arc::new(|event, e| {
+        eprintln!("Order #{}: {}", event.order().id, error::report(e));
+    }));
+    notifiers.add(Arc::new(StdoutNotifier::default()));
+    notifiers.add(Arc::new(
+        OutboxNotifier::new(&outbox_dir, "shop@example.com", TemplateSet::default())
+            .expect("Failed to create outbox"),
+    ));
 
     // Create example products
     let laptop = Product::new(
@@ -21,30 +52,44 @@ fn main() {
         String::from("MacBook Pro"),
         1299.99,
         String::from("Latest model with M1 chip and 16GB RAM"),
-    );
+    ).with_category("Laptops");
     let mouse = Product::new(
         2,
         String::from("Magic Mouse"),
         79.99,
         String::from("Wireless Magic Mouse 2"),
-    );
+    ).with_category("Accessories");
     let keyboard = Product::new(
         3,
         String::from("Magic Keyboard"),
         99.99,
         String::from("Wireless keyboard with numeric keypad"),
-    );
+    ).with_category("Accessories");
 
     // Add products to inventory
     println!("📦 Stocking Inventory...");
-    inventory.add_stock(laptop.id, 5);
-    inventory.add_stock(mouse.id, 10);
-    inventory.add_stock(keyboard.id, 8);
+    inventory.add_stock(laptop.id, 5).expect("Failed to receive stock");
+    inventory.add_stock(mouse.id, 10).expect("Failed to receive stock");
+    inventory.add_stock(keyboard.id, 8).expect("Failed to receive stock");
 
     // Display initial inventory
     println!("\n📋 Initial Inventory Status:");
     inventory.display_stock();
 
+    // Index the catalog for search and typeahead
+    println!("\n🔍 Searching the Catalog...");
+    let mut search = SearchIndex::from_products([&laptop, &mouse, &keyboard]);
+    let filter = SearchFilter { max_price: Some(100.0), in_stock_only: true, ..SearchFilter::default() };
+    for hit in search.search("wireless", &filter, Some(&inventory)) {
+        let product = search.get(hit.product_id).expect("Hit is indexed");
+        println!("{} (score {:.2})", product.name, hit.score);
+    }
+    println!("Suggestions for \"mag\": {}", search.suggest("mag", 5).join(", "));
+    let mut renamed = keyboard.clone();
+    renamed.name = String::from("Magic Keyboard with Touch ID");
+    search.upsert(renamed);
+    println!("Suggestions for \"touch\": {}", search.suggest("touch", 5).join(", "));
+
     // Create a user
     println!("\n👤 Creating New User...");
     let user = User::new(
@@ -60,21 +105,28 @@ fn main() {
 
     // Create and process first order
     println!("\n🛒 Processing First Order...");
-    let mut order1 = Order::new(1, user.clone());
+    let mut order1 = Order::with_notifiers(1, user.clone(), notifiers.clone());
     order1.add_product(laptop.clone(), 1).expect("Failed to add product");
     order1.add_product(mouse.clone(), 2).expect("Failed to add product");
+    order1.place().expect("Failed to place order");
 
     // Process the order
     println!("\n📦 Order Details:");
     order1.display();
 
-    if inventory.remove_stock(laptop.id, 1) && inventory.remove_stock(mouse.id, 2) {
-        order1.update_status(OrderStatus::Processing).expect("Failed to update status");
-        println!("\n✅ Order Processed Successfully");
-        println!("Order Summary: {}", order1.get_order_summary());
-    } else {
-        order1.update_status(OrderStatus::Cancelled).expect("Failed to update status");
-        println!("\n❌ Order Processing Failed: Insufficient Stock");
+    let reserved = inventory
+        .remove_stock(laptop.id, 1)
+        .and_then(|_| inventory.remove_stock(mouse.id, 2));
+    match reserved {
+        Ok(()) => {
+            order1.update_status(OrderStatus::Processing).expect("Failed to update status");
+            println!("\n✅ Order Processed Successfully");
+            println!("Order Summary: {}", order1.get_order_summary());
+        }
+        Err(e) => {
+            order1.update_status(OrderStatus::Cancelled).expect("Failed to update status");
+            println!("\n❌ Order Processing Failed: {}", e);
+        }
     }
 
     // Show updated inventory
@@ -89,29 +141,90 @@ fn main() {
     println!("\n📦 Large Order Details:");
     order2.display();
 
-    if inventory.remove_stock(laptop.id, 10) {
-        order2.update_status(OrderStatus::Processing).expect("Failed to update status");
-        println!("\n✅ Large Order Processed");
-    } else {
-        order2.update_status(OrderStatus::Cancelled).expect("Failed to update status");
-        println!("\n❌ Large Order Failed: Insufficient Stock");
+    match inventory.reserve_order(&order2) {
+        Ok(_) => {
+            order2.update_status(OrderStatus::Processing).expect("Failed to update status");
+            println!("\n✅ Large Order Processed");
+        }
+        Err(e) => {
+            order2.update_status(OrderStatus::Cancelled).expect("Failed to update status");
+            let e = Error::from(e);
+            println!("\n❌ Large Order Failed [{}]: {}", e.code(), e);
+        }
     }
 
     // Final inventory check
     println!("\n📋 Final Inventory Status:");
     inventory.display_stock();
 
+    // Every stock change is a ledger movement; a cycle count posts the variance
+    println!("\n🧾 Auditing Stock Movements...");
+    inventory
+        .adjust(mouse.id, -1, MovementReason::Damage, "warehouse: alice")
+        .expect("Failed to record damage");
+    for variance in inventory.reconcile(&[(laptop.id, 4), (mouse.id, 6)], "warehouse: bob") {
+        println!("Variance on Product ID {}: expected {}, counted {} ({:+})",
+            variance.product_id, variance.expected, variance.counted, variance.delta);
+    }
+    inventory.display_movements(mouse.id);
+    println!("Level derived from ledger: {}", inventory.derived_level(mouse.id));
+
+    // Backorderable products wait for the next delivery instead of failing
+    println!("\n⏳ Backordering Headphones...");
+    let headphones = Product::new(
+        4,
+        String::from("AirPods Max"),
+        549.00,
+        String::from("Over-ear headphones with noise cancellation"),
+    ).with_category("Audio").with_stock_policy(StockPolicy::Backorder);
+    inventory.add_stock(headphones.id, 2).expect("Failed to receive stock");
+
+    let mut backorder = Order::new(5, user.clone());
+    backorder.add_product(headphones.clone(), 3).expect("Failed to add product");
+    if inventory.reserve_order(&backorder).expect("Failed to reserve stock") == Reservation::Waiting {
+        backorder.update_status(OrderStatus::Backordered).expect("Failed to update status");
+    }
+    inventory.expect_receipt(headphones.id, Utc::now() + Duration::days(5));
+    println!("{}", backorder.get_order_summary());
+    if let Some(eta) = inventory.estimated_availability(backorder.id) {
+        println!("Estimated availability: {}", eta.format("%Y-%m-%d"));
+    }
+
+    inventory.add_stock(headphones.id, 4).expect("Failed to receive stock");
+    for order_id in inventory.take_ready_orders() {
+        if order_id == backorder.id {
+            backorder.update_status(OrderStatus::Processing).expect("Failed to update status");
+        }
+    }
+    println!("After delivery: {}", backorder.get_order_summary());
+    println!("Headphones left in stock: {}", inventory.check_stock(headphones.id));
+
     // Demonstrate order status progression
     println!("\n🔄 Demonstrating Order Status Progression...");
-    let mut order3 = Order::new(3, user);
+    let mut order3 = Order::with_notifiers(3, user, notifiers.clone());
     order3.add_product(keyboard.clone(), 1).expect("Failed to add product");
+    order3.place().expect("Failed to place order");
     
     println!("\nInitial Status: {}", order3.get_order_summary());
     
     // Process order
     order3.update_status(OrderStatus::Processing).expect("Failed to update status");
     println!("After Processing: {}", order3.get_order_summary());
-    
+
+    // Shipping requires a captured payment
+    if let Err(e) = order3.update_status(OrderStatus::Shipped) {
+        println!("Cannot ship yet: {}", e);
+    }
+
+    // First authorization attempt is declined, the retry goes through
+    gateway.script(MockResponse::Decline(String::from("insufficient funds")));
+    if let Err(e) = order3.authorize_payment(&mut gateway) {
+        println!("Authorization failed: {}", error::report(&e));
+    }
+    order3.authorize_payment(&mut gateway).expect("Failed to authorize payment");
+    order3.capture_payment(&mut gateway).expect("Failed to capture payment");
+    println!("Payment: {}", order3.payment);
+
     // Ship order
     order3.update_status(OrderStatus::Shipped).expect("Failed to update status");
     println!("After Shipping: {}", order3.get_order_summary());
@@ -120,5 +233,362 @@ fn main() {
     order3.update_status(OrderStatus::Delivered).expect("Failed to update status");
     println!("After Delivery: {}", order3.get_order_summary());
     
+    // Only customers with a delivered order may review a product
+    println!("\n⭐ Collecting Reviews...");
+    let mut reviews = ReviewBoard::new();
+    let orders = [order1, order3];
+    if let Err(e) = reviews.submit(&orders[1].user, laptop.id, 5, String::from("Blazing fast"), &orders) {
+        println!("Laptop review rejected: {}", e);
+    }
+    let review_id = reviews
+        .submit(&orders[1].user, keyboard.id, 4, String::from("Comfortable keys"), &orders)
+        .expect("Failed to submit review");
+    reviews.approve(review_id).expect("Failed to approve review");
+
+    let stats = reviews.stats_for(keyboard.id);
+    println!("{}: {:.1}/5 from {} review(s)", keyboard.name, stats.average, stats.count);
+
+    let mut catalog = vec![laptop.clone(), mouse.clone(), keyboard.clone()];
+    reviews.sort_by_rating(&mut catalog);
+    let ranked: Vec<&str> = catalog.iter().map(|p| p.name.as_str()).collect();
+    println!("Catalog by rating: {}", ranked.join(", "));
+
+    // Volume breaks, group prices and sales pick the best unit price per line
+    println!("\n💲 Pricing a B2B Order...");
+    let now = Utc::now();
+    let mut pricing = PricingEngine::new();
+    pricing.add_rule(mouse.id, RuleCondition::MinQuantity(10), 69.99).expect("Failed to add rule");
+    pricing.add_rule(mouse.id, RuleCondition::MinQuantity(50), 59.99).expect("Failed to add rule");
+    pricing
+        .add_rule(keyboard.id, RuleCondition::CustomerGroup(String::from("wholesale")), 84.99)
+        .expect("Failed to add rule");
+    pricing
+        .add_rule(laptop.id, RuleCondition::Sale { starts: now - Duration::days(1), ends: now + Duration::days(6) }, 1199.99)
+        .expect("Failed to add rule");
+    pricing.assign_group(orders[1].user.id, "wholesale");
+
+    let mut b2b_order = Order::new(4, orders[1].user.clone());
+    b2b_order.add_product_priced(mouse.clone(), 12, &pricing, now).expect("Failed to add product");
+    b2b_order.add_product_priced(keyboard.clone(), 12, &pricing, now).expect("Failed to add product");
+    b2b_order.add_product_priced(laptop.clone(), 2, &pricing, now).expect("Failed to add product");
+    b2b_order.display();
+
+    // Ship the B2B order in two parcels; its status follows the shipments
+    println!("\n🚚 Shipping the B2B Order in Parts...");
+    b2b_order.update_status(OrderStatus::Processing).expect("Failed to update status");
+    b2b_order.authorize_payment(&mut gateway).expect("Failed to authorize payment");
+    b2b_order.capture_payment(&mut gateway).expect("Failed to capture payment");
+    let first = b2b_order
+        .add_shipment("UPS", "1Z999AA10123456784", vec![(mouse.id, 12), (keyboard.id, 6)])
+        .expect("Failed to create shipment");
+    println!("{}", b2b_order.get_order_summary());
+    if let Err(e) = b2b_order.add_shipment("UPS", "1Z999AA10123456785", vec![(keyboard.id, 7)]) {
+        println!("Cannot ship: {}", e);
+    }
+    let second = b2b_order
+        .add_shipment("DHL", "JD014600006281230704", vec![(keyboard.id, 6), (laptop.id, 2)])
+        .expect("Failed to create shipment");
+    b2b_order.deliver_shipment(first).expect("Failed to deliver shipment");
+    println!("{}", b2b_order.get_order_summary());
+    b2b_order.deliver_shipment(second).expect("Failed to deliver shipment");
+    b2b_order.display();
+
+    // International orders freeze the exchange rate at checkout
+    println!("\n💱 Checking Out in Euros...");
+    let rates = RateTable::load(concat!(env!("CARGO_MANIFEST_DIR"), "/data/rates.csv"))
+        .expect("Failed to load exchange rates");
+    let mut euro_order = Order::new(6, orders[1].user.clone());
+    euro_order.add_product(mouse.clone(), 1).expect("Failed to add product");
+    euro_order.add_product(keyboard.clone(), 1).expect("Failed to add product");
+    euro_order
+        .checkout(Currency::from_code("EUR"), &rates, Rounding::new(RoundingMode::HalfEven), Utc::now())
+        .expect("Failed to check out");
+    println!("{}", euro_order.get_order_summary());
+    let swiss = Rounding::new(RoundingMode::HalfUp).with_increment(0.05).expect("Invalid rounding increment");
+    let chf = Currency::from_code("CHF");
+    let chf_rate = rates.rate("USD", "CHF", Utc::now()).expect("Missing CHF rate");
+    println!("Same basket in Swiss francs: {}",
+        chf.format(swiss.apply(euro_order.total * chf_rate.rate, &chf)));
+
+    // Gift cards pay part of an order; cancelling puts the money back on the card
+    println!("\n🎁 Paying with a Gift Card...");
+    let mut gift_cards = GiftCardBook::new();
+    let code = gift_cards
+        .issue(100.0, Some(Utc::now() + Duration::days(365)), Utc::now())
+        .expect("Failed to issue gift card");
+    let mut gift_order = Order::new(7, orders[1].user.clone());
+    gift_order.add_product(keyboard.clone(), 1).expect("Failed to add product");
+    gift_order.add_product(mouse.clone(), 1).expect("Failed to add product");
+    let covered = gift_cards.redeem(&code, &mut gift_order, Utc::now()).expect("Failed to redeem gift card");
+    gift_order.authorize_payment(&mut gateway).expect("Failed to authorize payment");
+    gift_order.capture_payment(&mut gateway).expect("Failed to capture payment");
+    println!("Gift card covered {}, card charged {}",
+        gift_order.format_amount(covered), gift_order.currency.format(gift_order.balance_due()));
+    gift_order.update_status(OrderStatus::Cancelled).expect("Failed to cancel order");
+    gift_cards.reverse(&gift_order, Utc::now()).expect("Failed to reverse gift card payment");
+    let credit = gift_cards.refund_to_store_credit(&mut gift_order, Utc::now()).expect("Failed to refund");
+    gift_cards.display_card(&code);
+    gift_cards.display_card(&credit);
+
+    // Delivered orders earn points; returns take them back
+    println!("\n⭐ Loyalty Points...");
+    let mut loyalty = LoyaltyProgram::new().with_category_rate("Accessories", 2.0);
+    let mut member = orders[1].user.clone();
+    for delivered in [&orders[1], &b2b_order] {
+        let points = loyalty.earn(delivered, Utc::now()).expect("Failed to earn points");
+        println!("Order #{} earned {} points", delivered.id, points);
+    }
+    loyalty.refresh(&mut member, Utc::now());
+    println!("{} is {} with {} points", member.name, member.tier(), member.points_balance());
+    let mut points_order = Order::new(8, member.clone());
+    points_order.add_product(mouse.clone(), 1).expect("Failed to add product");
+    let discount = loyalty.redeem(&mut points_order, 2_000, Utc::now()).expect("Failed to redeem points");
+    println!("2000 points took {} off order #{}", points_order.format_amount(discount), points_order.id);
+    b2b_order.refund_payment(&mut gateway).expect("Failed to refund order");
+    let returned = loyalty.reverse(&b2b_order, Utc::now()).expect("Failed to reverse points");
+    println!("B2B order returned: {:+} points", returned);
+    loyalty.refresh(&mut member, Utc::now());
+    println!("{} is now {} with {} points", member.name, member.tier(), member.points_balance());
+
+    // Subscriptions reorder on a schedule; the scheduler reports what it could not place
+    println!("\n🔁 Running Subscriptions...");
+    let mut subscriptions = SubscriptionBook::new();
+    let start = Utc::now() - Duration::days(1);
+    let monthly = subscriptions
+        .subscribe(member.clone(), vec![(mouse.id, 1)], Cadence::Monthly, start)
+        .expect("Failed to subscribe");
+    subscriptions
+        .subscribe(member.clone(), vec![(keyboard.id, 1)], Cadence::Weekly, start)
+        .expect("Failed to subscribe");
+    subscriptions
+        .subscribe(member.clone(), vec![(laptop.id, 50)], Cadence::EveryDays(90), start)
+        .expect("Failed to subscribe");
+    // Each run is priced from the catalog as it stands then
+    let mut catalog = Catalog::new();
+    for product in [&laptop, &mouse, &keyboard] {
+        catalog.upsert(product.clone());
+    }
+    // The second subscription's card is declined
+    gateway.script(MockResponse::Approve).script(MockResponse::Approve)
+        .script(MockResponse::Decline(String::from("Card expired")));
+    // The demo numbers its own orders below 100
+    subscriptions.run_due(Utc::now(), 100, &catalog, &mut inventory, &mut gateway).display();
+    if let Some(subscription) = subscriptions.get(monthly) {
+        println!("Subscription #{} ({}) next runs {}",
+            subscription.id, subscription.cadence, subscription.next_run.format("%Y-%m-%d"));
+    }
+
+    // A bundle has its own price but sells stock from its components
+    println!("\n🎒 Selling a Bundle...");
+    let starter_kit = Product::new(
+        9,
+        String::from("Laptop Starter Kit"),
+        1399.99,
+        String::from("MacBook Pro with Magic Mouse and Magic Keyboard"),
+    ).with_category("Bundles").with_components(vec![(laptop.id, 1), (mouse.id, 1), (keyboard.id, 1)]);
+    starter_kit.display();
+    println!("Kits available: {}", inventory.available(&starter_kit));
+    let mut kit_order = Order::new(9, member.clone());
+    kit_order.add_product(starter_kit.clone(), 1).expect("Failed to add bundle");
+    match inventory.reserve_order(&kit_order) {
+        Ok(_) => println!("Reserved 1 kit for order #{}; kits now available: {}", kit_order.id, inventory.available(&starter_kit)),
+        Err(e) => println!("❌ Could not reserve kit: {}", e),
+    }
+    for (product_id, quantity) in starter_kit.stock_lines(1).expect("One kit fits in a stock level") {
+        println!("  Product {} stock: {} (1 kit uses {})", product_id, inventory.check_stock(product_id), quantity);
+    }
+
+    // Replenishment goes through purchase orders, which record what each unit cost
+    println!("\n🚚 Purchasing Stock...");
+    let mut purchasing = Purchasing::new();
+    let apple = Supplier::new(1, "Apple Distribution", 7)
+        .with_cost(laptop.id, 949.00)
+        .and_then(|supplier| supplier.with_cost(mouse.id, 45.00))
+        .and_then(|supplier| supplier.with_cost(keyboard.id, 62.50))
+        .expect("Supplier costs are valid");
+    purchasing.add_supplier(apple);
+    purchasing.add_supplier(Supplier::new(2, "Peripherals Direct", 3).with_cost(mouse.id, 41.00).expect("Supplier cost is valid"));
+    let mouse_supplier = purchasing.cheapest_supplier(mouse.id).expect("Mouse has a supplier").id;
+    let po = purchasing.create_order(1, Utc::now()).expect("Failed to create purchase order");
+    purchasing.add_line(po, laptop.id, 4).expect("Failed to add line");
+    purchasing.add_line(po, keyboard.id, 10).expect("Failed to add line");
+    let mouse_po = purchasing.create_order(mouse_supplier, Utc::now()).expect("Failed to create purchase order");
+    purchasing.add_line(mouse_po, mouse.id, 20).expect("Failed to add line");
+    for id in [po, mouse_po] {
+        purchasing.send(id, &mut inventory, Utc::now()).expect("Failed to send purchase order");
+    }
+    purchasing.receive(po, &[(laptop.id, 4), (keyboard.id, 6)], &mut inventory, Utc::now())
+        .expect("Failed to receive stock");
+    purchasing.receive(mouse_po, &[(mouse.id, 20)], &mut inventory, Utc::now()).expect("Failed to receive stock");
+    if let Err(e) = purchasing.receive(po, &[(keyboard.id, 5)], &mut inventory, Utc::now()) {
+        println!("❌ Receipt rejected: {}", e);
+    }
+    for order in purchasing.orders() {
+        order.display();
+    }
+    println!("Margins:");
+    purchasing.display_margins([&laptop, &mouse, &keyboard, &starter_kit]);
+
+    // List prices change over time; orders keep what was actually charged
+    println!("\n🕰️ Price History...");
+    let mut history = PriceHistory::new();
+    let launch = Utc::now() - Duration::days(90);
+    history.track(&keyboard, launch);
+    history.set_price(keyboard.id, 109.99, launch + Duration::days(30)).expect("Failed to set price");
+    history.set_price(keyboard.id, 104.99, Utc::now() + Duration::days(30)).expect("Failed to set price");
+    history
+        .schedule_sale(keyboard.id, 89.99, Utc::now() - Duration::days(2), Utc::now() + Duration::days(5))
+        .expect("Failed to schedule sale");
+    history.display_history(keyboard.id, Utc::now());
+    let last_month = Utc::now() - Duration::days(45);
+    println!("Price on {}: {}", last_month.format("%Y-%m-%d"),
+        Currency::base().format(history.price_at(keyboard.id, last_month).expect("Keyboard is tracked")));
+    let mut sale_order = Order::new(10, member.clone());
+    sale_order
+        .add_product_as_of(keyboard.clone(), 2, &history, Utc::now())
+        .expect("Failed to add product");
+    let mut repriced = keyboard.clone();
+    history.refresh([&mut repriced], Utc::now() + Duration::days(10));
+    println!("Order #{} charged {} per keyboard; after the sale the list price is {}",
+        sale_order.id,
+        Currency::base().format(sale_order.charged_price(keyboard.id).expect("Line exists").unit_price),
+        Currency::base().format(repriced.price()));
+
+    // Risky orders are held for a person to check before any stock is taken
+    println!("\n🛡️ Screening Orders for Fraud...");
+    let screen = FraudScreen::default();
+    let newcomer = User::new(20, String::from("Sam Newman"), String::from("sam@example.com"), String::from("9 Harbour Rd"))
+        .expect("Failed to create user");
+    let mut risky = Order::new(11, newcomer.clone()).with_countries("US", "NG");
+    risky.add_product(laptop.clone(), 1).expect("Failed to add product");
+    let settled = member.clone().with_registered_at(Utc::now() - Duration::days(400));
+    let mut routine = Order::new(12, settled).with_countries("US", "US");
+    routine.add_product(mouse.clone(), 1).expect("Failed to add product");
+    for order in [&mut risky, &mut routine] {
+        screen.check(order, &[], Utc::now()).expect("Failed to screen order").display();
+    }
+    let mut screened_shop = Shop::open(MemoryStorage::new()).expect("Failed to open shop").with_fraud_screen(screen);
+    screened_shop.add_product(laptop.clone(), 10).expect("Failed to save product");
+    screened_shop.register_user(newcomer).expect("Failed to save user");
+    let held = screened_shop.place_order(20, &[(laptop.id, 4)]).expect("Failed to place order");
+    println!("Order #{} is {}; laptops in stock: {}", held.id, held.status, screened_shop.inventory().check_stock(laptop.id));
+    let approved = screened_shop.approve_order(held.id).expect("Failed to approve order");
+    println!("Order #{} is {}; laptops in stock: {}", approved.id, approved.status, screened_shop.inventory().check_stock(laptop.id));
+
+    // The warehouse gets signed pushes for order and stock changes; failed sends back off and retry
+    println!("\n📡 Sending Webhooks...");
+    let mut receiver = DemoReceiver::new("http://warehouse.test/hooks", "whsec_demo");
+    let dispatcher = Arc::new(Mutex::new(WebhookDispatcher::new().with_retry_policy(RetryPolicy {
+        max_attempts: 2,
+        base_delay: Duration::seconds(30),
+        max_delay: Duration::minutes(10),
+    })));
+    {
+        let mut dispatcher = dispatcher.lock().expect("Dispatcher lock");
+        dispatcher
+            .subscribe("http://warehouse.test/hooks", "whsec_demo", &[EventKind::OrderStatusChanged, EventKind::StockThreshold])
+            .expect("Failed to subscribe");
+        // The demo receiver refuses this one, so every delivery here fails
+        dispatcher.subscribe("http://retired.test/gone", "whsec_old", &[EventKind::StockThreshold]).expect("Failed to subscribe");
+        dispatcher.watch_stock(laptop.id, 10);
+        dispatcher.check_stock(&inventory, Utc::now());
+    }
+    let mut hooked = Notifiers::new();
+    hooked.add(Arc::new(WebhookNotifier::new(Arc::clone(&dispatcher))));
+    let mut hooked_order = Order::with_notifiers(13, member.clone(), hooked);
+    hooked_order.add_product(mouse.clone(), 1).expect("Failed to add product");
+    hooked_order.place().expect("Failed to place order");
+    hooked_order.update_status(OrderStatus::Processing).expect("Failed to update status");
+
+    let mut dispatcher = dispatcher.lock().expect("Dispatcher lock");
+    receiver.statuses.push(503);
+    dispatcher.deliver_due(Utc::now(), &mut receiver).display();
+    dispatcher.deliver_due(Utc::now() + Duration::minutes(1), &mut receiver).display();
+    for (event, signature_valid) in &receiver.received {
+        println!("  {} (signature {})", event, if *signature_valid { "valid" } else { "INVALID" });
+    }
+    for dead in dispatcher.dead_letters() {
+        println!("  Dead letter #{} after {} attempt(s): {}", dead.id, dead.attempts, dead.last_error.as_deref().unwrap_or(""));
+    }
+    drop(dispatcher);
+
+    // The same order rendered for machines instead of people
+    println!("\n🧩 Machine-Readable Output...");
+    euro_order.render_io(&mut io::stdout(), Format::Json).expect("Failed to write order");
+    inventory.render_io(&mut io::stdout(), Format::KeyValue).expect("Failed to write inventory");
+
+    // Orders so far feed the "frequently bought together" model
+    println!("\n🤝 Frequently Bought Together...");
+    let mut recommender = Recommender::from_orders(&orders, 1);
+    recommender.add_order(&b2b_order);
+    for suggestion in recommender.recommend_for_cart(&[laptop.id], &inventory, 3) {
+        println!("Product ID {}: confidence {:.2}, lift {:.2}",
+            suggestion.product_id, suggestion.association.confidence, suggestion.association.lift);
+    }
+
+    // Services run against the Storage trait; here the durable file backend
+    println!("\n💾 Persisting the Shop...");
+    let store_path = env::temp_dir().join("ecommerce-store.jsonl");
+    let _ = std::fs::remove_file(&store_path);
+    let storage = FileStorage::open(&store_path).expect("Failed to open store");
+    let mut shop = Shop::open(storage).expect("Failed to open shop");
+    shop.add_product(laptop.clone(), 5).expect("Failed to save product");
+    shop.add_product(mouse.clone(), 20).expect("Failed to save product");
+    shop.register_user(orders[1].user.clone()).expect("Failed to save user");
+    let placed = shop
+        .place_order(orders[1].user.id, &[(laptop.id, 1), (mouse.id, 2)])
+        .expect("Failed to place order");
+    shop.cancel_order(placed.id).expect("Failed to cancel order");
+    shop.place_order(orders[1].user.id, &[(mouse.id, 3)]).expect("Failed to place order");
+    drop(shop);
+
+    let mut reopened = FileStorage::open(&store_path).expect("Failed to reopen store");
+    reopened.compact().expect("Failed to compact store");
+    let shop = Shop::open(reopened).expect("Failed to reopen shop");
+    println!("Reloaded {} product(s), {} user(s), {} order(s) from {}",
+        shop.storage().products().len(), shop.storage().users().len(),
+        shop.storage().orders().len(), store_path.display());
+    println!("Laptop stock after cancellation: {}", shop.inventory().check_stock(laptop.id));
+
+    // Support staff look orders up with the same query the CLI and HTTP API take
+    println!("\n🔎 Querying Orders...");
+    let customer = orders[1].user.id;
+    let query = OrderQuery::from_query_string(&format!("user={}&status=cancelled,processing&from=-7d&sort=-total", customer), Utc::now())
+        .expect("Failed to parse query");
+    shop.find_orders(&query).display();
+    let (status, body) = order_query::api_response(&format!("/orders?product={}&per_page=1", laptop.id), &shop.storage().orders(), Utc::now());
+    println!("GET /orders?product={}&per_page=1 -> {} {}", laptop.id, status, body.len());
+    let (status, body) = order_query::api_response("/orders?status=lost", &shop.storage().orders(), Utc::now());
+    println!("GET /orders?status=lost -> {} {}", status, body);
+
+    println!("\n📬 Notifications written to {}", outbox_dir.display());
     println!("\n✨ Demo Completed Successfully!");
 }
+
+/// Stands in for the warehouse's webhook endpoint so the demo needs no network.
+///
+/// Answers each request with the next scripted status, then 200; refuses other URLs.
+struct DemoReceiver {
+    url: &'static str,
+    secret: &'static str,
+    statuses: Vec<u16>,
+    received: Vec<(String, bool)>, // event name and whether the signature checked out
+}
+
+impl DemoReceiver {
+    fn new(url: &'static str, secret: &'static str) -> Self {
+        DemoReceiver { url, secret, statuses: Vec::new(), received: Vec::new() }
+    }
+}
+
+impl Transport for DemoReceiver {
+    fn post(&mut self, url: &str, headers: &[(&str, String)], body: &str) -> Result<u16, WebhookError> {
+        if url != self.url {
+            return Err(io::Error::new(io::ErrorKind::ConnectionRefused, "connection refused").into());
+        }
+        let header = |name: &str| headers.iter().find(|(n, _)| *n == name).map(|(_, value)| value.as_str());
+        let signature_valid = header(webhook::SIGNATURE_HEADER).is_some_and(|signature| webhook::verify(self.secret, signature, body));
+        self.received.push((header(webhook::EVENT_HEADER).unwrap_or_default().to_string(), signature_valid));
+        Ok(if self.statuses.is_empty() { 200 } else { self.statuses.remove(0) })
+    }
+}
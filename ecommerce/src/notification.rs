@@ -0,0 +1,469 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crate::order::{Order, OrderLine, OrderStatus};
+
+/// Something that happened to an order that a customer should hear about
+#[derive(Debug)]
+pub enum OrderEvent<'a> {
+    Created { order: &'a Order },
+    StatusChanged { order: &'a Order, from: OrderStatus, to: OrderStatus },
+}
+
+impl OrderEvent<'_> {
+    pub fn order(&self) -> &Order {
+        match self {
+            OrderEvent::Created { order } | OrderEvent::StatusChanged { order, .. } => order,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum NotifyError {
+    Io(io::Error),
+}
+
+impl fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotifyError::Io(_) => write!(f, "Failed to deliver notification"),
+        }
+    }
+}
+
+impl std::error::Error for NotifyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NotifyError::Io(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for NotifyError {
+    fn from(err: io::Error) -> Self {
+        NotifyError::Io(err)
+    }
+}
+
+/// Receives order events; shared between orders and threads
+pub trait Notifier: Send + Sync {
+    fn notify(&self, event: &OrderEvent) -> Result<(), NotifyError>;
+}
+
+/// Called with each notification that could not be delivered
+pub type ErrorSink = Arc<dyn Fn(&OrderEvent, &NotifyError) + Send + Sync>;
+
+/// The notifiers attached to an order
+#[derive(Clone, Default)]
+pub struct Notifiers {
+    notifiers: Vec<Arc<dyn Notifier>>,
+    on_error: Option<ErrorSink>,
+}
+
+impl Notifiers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports delivery failures to `sink`, e.g. a logger; without one they are dropped
+    pub fn with_error_sink(mut self, sink: ErrorSink) -> Self {
+        self.on_error = Some(sink);
+        self
+    }
+
+    pub fn add(&mut self, notifier: Arc<dyn Notifier>) {
+        self.notifiers.push(notifier);
+    }
+
+    /// Sends the event to every notifier and returns the failures; a failing notifier never blocks the others
+    pub fn dispatch(&self, event: &OrderEvent) -> Vec<NotifyError> {
+        let mut failures = Vec::new();
+        for notifier in &self.notifiers {
+            if let Err(e) = notifier.notify(event) {
+                if let Some(sink) = &self.on_error {
+                    sink(event, &e);
+                }
+                failures.push(e);
+            }
+        }
+        failures
+    }
+}
+
+impl fmt::Debug for Notifiers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Notifiers({})", self.notifiers.len())
+    }
+}
+
+impl From<Vec<Arc<dyn Notifier>>> for Notifiers {
+    fn from(notifiers: Vec<Arc<dyn Notifier>>) -> Self {
+        Notifiers { notifiers, on_error: None }
+    }
+}
+
+/// A rendered notification ready to be delivered
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Subject and body with `{placeholder}` fields.
+///
+/// Supported placeholders: `{order_id}`, `{customer_name}`, `{customer_email}`,
+/// `{status}`, `{previous_status}`, `{total}`, `{item_count}` and `{items}`.
+#[derive(Debug, Clone)]
+pub struct MessageTemplate {
+    pub subject: String,
+    pub body: String,
+}
+
+impl MessageTemplate {
+    pub fn new(subject: &str, body: &str) -> Self {
+        MessageTemplate {
+            subject: subject.to_string(),
+            body: body.to_string(),
+        }
+    }
+
+    pub fn render(&self, event: &OrderEvent) -> Message {
+        let order = event.order();
+        Message {
+            to: order.user.email.clone(),
+            subject: Self::fill(&self.subject, event),
+            body: Self::fill(&self.body, event),
+        }
+    }
+
+    /// Replaces each known placeholder in one pass, so filled-in values are never expanded again
+    fn fill(text: &str, event: &OrderEvent) -> String {
+        let order = event.order();
+        let value = |name: &str| -> Option<String> {
+            Some(match name {
+                "order_id" => order.id.to_string(),
+                "customer_name" => order.user.name.clone(),
+                "customer_email" => order.user.email.clone(),
+                "status" => match event {
+                    OrderEvent::StatusChanged { to, .. } => to.to_string(),
+                    OrderEvent::Created { .. } => order.status.to_string(),
+                },
+                "previous_status" => match event {
+                    OrderEvent::StatusChanged { from, .. } => from.to_string(),
+                    OrderEvent::Created { .. } => String::new(),
+                },
+                "total" => order.currency.format(order.amount_due()),
+                "item_count" => order.lines().len().to_string(),
+                "items" => order
+                    .lines()
+                    .iter()
+                    .map(|OrderLine { product, quantity, price }| {
+                        format!("  {}x {} ({} each)", quantity, product.name, order.format_amount(price.unit_price))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                _ => return None,
+            })
+        };
+
+        let mut filled = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            filled.push_str(&rest[..start]);
+            let placeholder = &rest[start..];
+            match placeholder.find('}').and_then(|end| Some((end, value(&placeholder[1..end])?))) {
+                Some((end, value)) => {
+                    filled.push_str(&value);
+                    rest = &placeholder[end + 1..];
+                }
+                None => {
+                    filled.push('{');
+                    rest = &placeholder[1..];
+                }
+            }
+        }
+        filled.push_str(rest);
+        filled
+    }
+}
+
+/// One template per kind of event
+#[derive(Debug, Clone)]
+pub struct TemplateSet {
+    pub created: MessageTemplate,
+    pub status_changed: MessageTemplate,
+}
+
+impl TemplateSet {
+    pub fn render(&self, event: &OrderEvent) -> Message {
+        match event {
+            OrderEvent::Created { .. } => self.created.render(event),
+            OrderEvent::StatusChanged { .. } => self.status_changed.render(event),
+        }
+    }
+}
+
+impl Default for TemplateSet {
+    fn default() -> Self {
+        TemplateSet {
+            created: MessageTemplate::new(
+                "Order #{order_id} received",
+                "Hi {customer_name},\n\nThanks for your order #{order_id}.\n\n{items}\n\nTotal: {total}\n",
+            ),
+            status_changed: MessageTemplate::new(
+                "Order #{order_id} is now {status}",
+                "Hi {customer_name},\n\nYour order #{order_id} moved from {previous_status} to {status}.\n\nTotal: {total}\n",
+            ),
+        }
+    }
+}
+
+/// Writes each notification as an `.eml` file into an outbox directory
+#[derive(Debug)]
+pub struct OutboxNotifier {
+    dir: PathBuf,
+    from: String,
+    templates: TemplateSet,
+    sequence: AtomicU32,
+}
+
+impl OutboxNotifier {
+    /// Creates the outbox directory if it does not exist yet
+    pub fn new(dir: impl Into<PathBuf>, from: &str, templates: TemplateSet) -> Result<Self, NotifyError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(OutboxNotifier {
+            dir,
+            from: from.to_string(),
+            templates,
+            sequence: AtomicU32::new(0),
+        })
+    }
+}
+
+/// Replaces CR, LF and other control characters so a value cannot start a new header
+fn header_value(value: &str) -> String {
+    value.chars().map(|c| if c.is_control() { ' ' } else { c }).collect()
+}
+
+impl Notifier for OutboxNotifier {
+    /// Writes to the next unused file name, so messages from earlier runs are never overwritten
+    fn notify(&self, event: &OrderEvent) -> Result<(), NotifyError> {
+        let message = self.templates.render(event);
+        let contents = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}",
+            header_value(&self.from),
+            header_value(&message.to),
+            header_value(&message.subject),
+            message.body.replace('\n', "\r\n"),
+        );
+
+        loop {
+            let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+            let path = self.dir.join(format!("order-{}-{:04}.eml", event.order().id, sequence));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => return Ok(file.write_all(contents.as_bytes())?),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Logs each notification to stdout
+#[derive(Debug, Default)]
+pub struct StdoutNotifier {
+    templates: TemplateSet,
+}
+
+impl StdoutNotifier {
+    pub fn new(templates: TemplateSet) -> Self {
+        StdoutNotifier { templates }
+    }
+}
+
+impl Notifier for StdoutNotifier {
+    fn notify(&self, event: &OrderEvent) -> Result<(), NotifyError> {
+        let message = self.templates.render(event);
+        println!("📧 To: {} | {}", message.to, message.subject);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::product::Product;
+    use crate::user::User;
+    use std::sync::Mutex;
+
+    /// Remembers the subject of every message it is asked to send
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Notifier for Recorder {
+        fn notify(&self, event: &OrderEvent) -> Result<(), NotifyError> {
+            self.0.lock().unwrap().push(TemplateSet::default().render(event).subject);
+            Ok(())
+        }
+    }
+
+    struct Failing;
+
+    impl Notifier for Failing {
+        fn notify(&self, _: &OrderEvent) -> Result<(), NotifyError> {
+            Err(io::Error::other("mail server down").into())
+        }
+    }
+
+    fn order(notifiers: Notifiers) -> Order {
+        let user = User::new_unchecked(4, "Grace".into(), "grace@example.com".into(), "2 Side St".into());
+        let mut order = Order::with_notifiers(42, user, notifiers);
+        order.add_product(Product::new(1, "Lamp".into(), 12.5, String::new()), 2).unwrap();
+        order
+    }
+
+    #[test]
+    fn created_is_sent_when_the_order_is_placed() {
+        let recorder = Arc::new(Recorder::default());
+        let mut notifiers = Notifiers::new();
+        notifiers.add(recorder.clone());
+        let mut order = order(notifiers);
+        assert!(recorder.0.lock().unwrap().is_empty());
+
+        order.place().unwrap();
+        assert!(order.place().is_err());
+        order.update_status(OrderStatus::Processing).unwrap();
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec!["Order #42 received".to_string(), "Order #42 is now ⚙️ Processing".to_string()]
+        );
+    }
+
+    #[test]
+    fn a_failing_notifier_does_not_block_the_others() {
+        let recorder = Arc::new(Recorder::default());
+        let notifiers = Notifiers::from(vec![Arc::new(Failing) as Arc<dyn Notifier>, recorder.clone()]);
+        order(notifiers).place().unwrap();
+        assert_eq!(recorder.0.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn failures_are_returned_and_reported_to_the_sink() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&reported);
+        let mut notifiers = Notifiers::new().with_error_sink(Arc::new(move |event, e| {
+            sink.lock().unwrap().push(format!("{}: {}", event.order().id, crate::error::report(e)));
+        }));
+        notifiers.add(Arc::new(Failing));
+        notifiers.add(Arc::new(Recorder::default()));
+
+        let placed = order(Notifiers::new());
+        let failures = notifiers.dispatch(&OrderEvent::Created { order: &placed });
+        assert_eq!(failures.len(), 1);
+        assert!(matches!(&failures[0], NotifyError::Io(e) if e.to_string() == "mail server down"));
+        assert_eq!(*reported.lock().unwrap(), vec!["42: Failed to deliver notification: mail server down".to_string()]);
+        assert!(order(notifiers).place().is_ok());
+    }
+
+    #[test]
+    fn templates_fill_every_placeholder() {
+        let order = order(Notifiers::new());
+        let template = MessageTemplate::new(
+            "{order_id}/{status}/{previous_status}",
+            "{customer_name} <{customer_email}> {item_count} {total}\n{items}",
+        );
+        let event = OrderEvent::StatusChanged { order: &order, from: OrderStatus::Pending, to: OrderStatus::Processing };
+        let message = template.render(&event);
+        assert_eq!(message.to, "grace@example.com");
+        assert_eq!(message.subject, "42/⚙️ Processing/🕒 Pending");
+        assert_eq!(message.body, "Grace <grace@example.com> 1 $25.00\n  2x Lamp ($12.50 each)");
+    }
+
+    #[test]
+    fn filled_values_are_not_expanded_again() {
+        let user = User::new_unchecked(4, "{items} {total}".into(), "grace@example.com".into(), "2 Side St".into());
+        let mut order = Order::new(42, user);
+        order.add_product(Product::new(1, "{status} lamp".into(), 12.5, String::new()), 1).unwrap();
+        let template = MessageTemplate::new("{unknown} {order_id}{", "{customer_name}|{items}|{status}|{{order_id}}");
+        let message = template.render(&OrderEvent::Created { order: &order });
+        assert_eq!(message.subject, "{unknown} 42{");
+        assert_eq!(message.body, "{items} {total}|  1x {status} lamp ($12.50 each)|🕒 Pending|{42}");
+    }
+
+    fn outbox_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("outbox-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn outbox_writes_one_file_per_message() {
+        let dir = outbox_dir("single");
+        let outbox = OutboxNotifier::new(&dir, "shop@example.com", TemplateSet::default()).unwrap();
+        let mut notifiers = Notifiers::new();
+        notifiers.add(Arc::new(outbox));
+        order(notifiers).place().unwrap();
+
+        let written = fs::read_to_string(dir.join("order-42-0001.eml")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(written.starts_with("From: shop@example.com\r\nTo: grace@example.com\r\nSubject: Order #42 received\r\n"));
+        assert!(written.contains("Total: $25.00\r\n"));
+    }
+
+    #[test]
+    fn a_restarted_outbox_does_not_overwrite_earlier_messages() {
+        let dir = outbox_dir("restart");
+        for _ in 0..2 {
+            let outbox = OutboxNotifier::new(&dir, "shop@example.com", TemplateSet::default()).unwrap();
+            let order = order(Notifiers::new());
+            outbox.notify(&OrderEvent::Created { order: &order }).unwrap();
+            outbox
+                .notify(&OrderEvent::StatusChanged { order: &order, from: OrderStatus::Pending, to: OrderStatus::Processing })
+                .unwrap();
+        }
+
+        let mut names: Vec<String> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        names.sort();
+        let subjects: Vec<bool> = names
+            .iter()
+            .map(|name| fs::read_to_string(dir.join(name)).unwrap().contains("Subject: Order #42 received"))
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names, ["order-42-0001.eml", "order-42-0002.eml", "order-42-0003.eml", "order-42-0004.eml"]);
+        assert_eq!(subjects, [true, false, true, false]);
+    }
+
+    #[test]
+    fn header_values_cannot_inject_headers() {
+        let dir = outbox_dir("headers");
+        let user = User::new_unchecked(4, "Grace".into(), "grace@example.com\r\nBcc: all@example.com".into(), "Here".into());
+        let mut order = Order::new(42, user);
+        order.add_product(Product::new(1, "Lamp".into(), 12.5, String::new()), 1).unwrap();
+        let templates = TemplateSet {
+            created: MessageTemplate::new("Hi {customer_name}\nX-Injected: yes", "Line one\nLine two"),
+            ..TemplateSet::default()
+        };
+        let outbox = OutboxNotifier::new(&dir, "shop@example.com\r\n", templates).unwrap();
+        outbox.notify(&OrderEvent::Created { order: &order }).unwrap();
+
+        let written = fs::read_to_string(dir.join("order-42-0001.eml")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let (headers, body) = written.split_once("\r\n\r\n").unwrap();
+        assert_eq!(
+            headers.split("\r\n").collect::<Vec<_>>(),
+            [
+                "From: shop@example.com  ",
+                "To: grace@example.com  bcc: all@example.com",
+                "Subject: Hi Grace X-Injected: yes",
+                "Content-Type: text/plain; charset=utf-8",
+            ]
+        );
+        assert_eq!(body, "Line one\r\nLine two");
+    }
+}
@@ -1,21 +1,55 @@
 use std::fmt;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::currency::{Currency, CurrencyError, FrozenRate, RateTable, Rounding};
+use crate::gift_card::Redemption;
+use crate::notification::{Notifier, Notifiers, OrderEvent};
+use crate::payment::{PaymentError, PaymentGateway, PaymentStatus};
+use crate::price_history::PriceHistory;
+use crate::pricing::{LinePrice, PricingEngine};
 use crate::product::Product;
+use crate::render::{Format, Render};
+use crate::shipment::{Shipment, ShipmentStatus};
 use crate::user::User;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum OrderStatus {
     Pending,
+    /// Held by fraud screening until someone approves or cancels it
+    UnderReview,
+    Backordered,
     Processing,
+    PartiallyShipped,
     Shipped,
     Delivered,
     Cancelled,
 }
 
+impl OrderStatus {
+    /// Whether an order may move from this status to `next`; once goods ship the order can no longer be cancelled
+    pub fn can_transition_to(&self, next: &OrderStatus) -> bool {
+        use OrderStatus::*;
+        matches!(
+            (self, next),
+            (Pending, UnderReview | Backordered | Processing | Cancelled)
+                | (UnderReview, Backordered | Processing | Cancelled)
+                | (Backordered, Processing | Cancelled)
+                | (Processing, PartiallyShipped | Shipped | Cancelled)
+                | (PartiallyShipped, Shipped)
+                | (Shipped, Delivered)
+        )
+    }
+}
+
 impl fmt::Display for OrderStatus {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             OrderStatus::Pending => write!(f, "🕒 Pending"),
+            OrderStatus::UnderReview => write!(f, "🔎 Under review"),
+            OrderStatus::Backordered => write!(f, "⏳ Backordered"),
             OrderStatus::Processing => write!(f, "⚙️ Processing"),
+            OrderStatus::PartiallyShipped => write!(f, "📦 Partially shipped"),
             OrderStatus::Shipped => write!(f, "🚚 Shipped"),
             OrderStatus::Delivered => write!(f, "✅ Delivered"),
             OrderStatus::Cancelled => write!(f, "❌ Cancelled"),
@@ -23,13 +57,33 @@ impl fmt::Display for OrderStatus {
     }
 }
 
-#[derive(Debug)]
+/// A product on an order, with the unit price it was charged at
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderLine {
+    pub product: Product,
+    pub quantity: u32,
+    pub price: LinePrice,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Order {
     pub id: u32,
     pub user: User,
-    pub products: Vec<(Product, u32)>, // (Product, quantity)
+    lines: Vec<OrderLine>,
     pub status: OrderStatus,
-    pub total: f64,
+    pub total: f64, // in the base currency
+    pub currency: Currency,
+    pub frozen_rate: Option<FrozenRate>, // set at checkout
+    pub payment: PaymentStatus,
+    pub redemptions: Vec<Redemption>, // gift card and store-credit payments
+    pub shipments: Vec<Shipment>,
+    #[serde(default = "crate::user::unix_epoch")]
+    pub created_at: DateTime<Utc>,
+    pub billing_country: Option<String>, // ISO 3166 alpha-2, e.g. "US"
+    pub shipping_country: Option<String>,
+    placed: bool,
+    #[serde(skip)]
+    notifiers: Notifiers,
 }
 
 #[derive(Debug)]
@@ -38,6 +92,11 @@ pub enum OrderError {
     ProductNotFound,
     InvalidStatus,
     EmptyOrder,
+    PaymentNotCaptured,
+    Payment(PaymentError),
+    ShipmentNotFound,
+    ExceedsUnshipped { product_id: u32, unshipped: u32 },
+    Currency(CurrencyError),
 }
 
 impl fmt::Display for OrderError {
@@ -47,6 +106,22 @@ impl fmt::Display for OrderError {
             OrderError::ProductNotFound => write!(f, "Product not found in order"),
             OrderError::InvalidStatus => write!(f, "Invalid status transition"),
             OrderError::EmptyOrder => write!(f, "Cannot process empty order"),
+            OrderError::PaymentNotCaptured => write!(f, "Payment must be captured before shipping"),
+            OrderError::Payment(_) => write!(f, "Payment failed"),
+            OrderError::ShipmentNotFound => write!(f, "Shipment not found on order"),
+            OrderError::ExceedsUnshipped { product_id, unshipped } => write!(
+                f, "Only {} unit(s) of product {} are left to ship", unshipped, product_id),
+            OrderError::Currency(_) => write!(f, "Currency conversion failed"),
+        }
+    }
+}
+
+impl std::error::Error for OrderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OrderError::Payment(err) => Some(err),
+            OrderError::Currency(err) => Some(err),
+            _ => None,
         }
     }
 }
@@ -56,28 +131,126 @@ impl Order {
         Order {
             id,
             user,
-            products: Vec::new(),
+            lines: Vec::new(),
             status: OrderStatus::Pending,
             total: 0.0,
+            currency: Currency::base(),
+            frozen_rate: None,
+            payment: PaymentStatus::Unpaid,
+            redemptions: Vec::new(),
+            shipments: Vec::new(),
+            created_at: Utc::now(),
+            billing_country: None,
+            shipping_country: None,
+            placed: false,
+            notifiers: Notifiers::new(),
         }
     }
 
+    /// Sets when the order was placed, e.g. when importing or replaying orders
+    pub fn with_created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = created_at;
+        self
+    }
+
+    /// Sets the billing and shipping country codes used by fraud screening
+    pub fn with_countries(mut self, billing: &str, shipping: &str) -> Self {
+        self.billing_country = Some(billing.trim().to_uppercase());
+        self.shipping_country = Some(shipping.trim().to_uppercase());
+        self
+    }
+
+    /// Creates an order whose notifiers hear about it once it is placed
+    pub fn with_notifiers(id: u32, user: User, notifiers: Notifiers) -> Self {
+        let mut order = Order::new(id, user);
+        order.notifiers = notifiers;
+        order
+    }
+
+    /// Submits the finished basket and tells the notifiers the order was created
+    pub fn place(&mut self) -> Result<(), OrderError> {
+        if self.placed || self.status != OrderStatus::Pending {
+            return Err(OrderError::InvalidStatus);
+        }
+        if self.is_empty() {
+            return Err(OrderError::EmptyOrder);
+        }
+        self.placed = true;
+        self.notifiers.dispatch(&OrderEvent::Created { order: self });
+        Ok(())
+    }
+
+    pub fn is_placed(&self) -> bool {
+        self.placed
+    }
+
+    /// Attaches a notifier that will hear about every later status change
+    pub fn add_notifier(&mut self, notifier: Arc<dyn Notifier>) {
+        self.notifiers.add(notifier);
+    }
+
+    /// Adds a line charged at the product's list price
     pub fn add_product(&mut self, product: Product, quantity: u32) -> Result<(), OrderError> {
+        let price = LinePrice::list(&product);
+        self.add_product_at(product, quantity, price)
+    }
+
+    /// Adds a line charged at whatever the pricing engine quotes for this customer right now
+    pub fn add_product_priced(
+        &mut self,
+        product: Product,
+        quantity: u32,
+        pricing: &PricingEngine,
+        at: DateTime<Utc>,
+    ) -> Result<(), OrderError> {
+        let price = pricing.quote(&product, &self.user, quantity, at);
+        self.add_product_at(product, quantity, price)
+    }
+
+    /// Adds a line charged at the product's list price in effect at `at`
+    pub fn add_product_as_of(
+        &mut self,
+        product: Product,
+        quantity: u32,
+        history: &PriceHistory,
+        at: DateTime<Utc>,
+    ) -> Result<(), OrderError> {
+        let price = history.line_price(&product, at);
+        self.add_product_at(product, quantity, price)
+    }
+
+    /// Adds a line charged at an explicit unit price
+    pub fn add_product_at(&mut self, product: Product, quantity: u32, price: LinePrice) -> Result<(), OrderError> {
+        if self.status != OrderStatus::Pending {
+            return Err(OrderError::InvalidStatus);
+        }
         if quantity == 0 {
             return Err(OrderError::InvalidQuantity);
         }
         
         // Update total
-        self.total += product.price * quantity as f64;
-        self.products.push((product, quantity));
+        self.total += price.unit_price * quantity as f64;
+        self.lines.push(OrderLine { product, quantity, price });
         Ok(())
     }
 
+    pub fn lines(&self) -> &[OrderLine] {
+        &self.lines
+    }
+
+    /// The price charged for a product's line, whatever the product costs today
+    pub fn charged_price(&self, product_id: u32) -> Option<&LinePrice> {
+        self.lines.iter().find(|line| line.product.id == product_id).map(|line| &line.price)
+    }
+
+    /// Lines can only change before checkout; afterwards stock has been reserved for them
     pub fn remove_product(&mut self, product_id: u32) -> Result<(), OrderError> {
-        if let Some(index) = self.products.iter().position(|(p, _)| p.id == product_id) {
-            let (product, quantity) = &self.products[index];
-            self.total -= product.price * *quantity as f64;
-            self.products.remove(index);
+        if self.status != OrderStatus::Pending {
+            return Err(OrderError::InvalidStatus);
+        }
+        if let Some(index) = self.lines.iter().position(|line| line.product.id == product_id) {
+            let line = self.lines.remove(index);
+            self.total -= line.price.unit_price * line.quantity as f64;
             Ok(())
         } else {
             Err(OrderError::ProductNotFound)
@@ -87,16 +260,238 @@ impl Order {
     pub fn update_status(&mut self, status: OrderStatus) -> Result<(), OrderError> {
         // Validate status transition
         match (&self.status, &status) {
-            (OrderStatus::Cancelled, _) => {
+            (from, to) if !from.can_transition_to(to) => {
                 return Err(OrderError::InvalidStatus)
             }
-            (OrderStatus::Delivered, _) => {
-                return Err(OrderError::InvalidStatus)
+            (_, OrderStatus::PartiallyShipped | OrderStatus::Shipped) if !self.is_paid() => {
+                return Err(OrderError::PaymentNotCaptured)
             }
             _ => {}
         }
 
-        self.status = status;
+        let from = std::mem::replace(&mut self.status, status);
+        let event = OrderEvent::StatusChanged { order: self, from, to: self.status.clone() };
+        self.notifiers.dispatch(&event);
+        Ok(())
+    }
+
+    /// Units of a product ordered but not yet in any shipment
+    pub fn unshipped_quantity(&self, product_id: u32) -> u32 {
+        let ordered: u32 = self.lines
+            .iter()
+            .filter(|line| line.product.id == product_id)
+            .map(|line| line.quantity)
+            .sum();
+        let shipped: u32 = self.shipments.iter().map(|s| s.quantity_of(product_id)).sum();
+        ordered.saturating_sub(shipped)
+    }
+
+    /// Ships some of the order's lines; the order status follows its shipments
+    pub fn add_shipment(
+        &mut self,
+        carrier: &str,
+        tracking_number: &str,
+        lines: Vec<(u32, u32)>,
+    ) -> Result<u32, OrderError> {
+        if !matches!(self.status, OrderStatus::Processing | OrderStatus::PartiallyShipped) {
+            return Err(OrderError::InvalidStatus);
+        }
+        if !self.is_paid() {
+            return Err(OrderError::PaymentNotCaptured);
+        }
+        if lines.is_empty() {
+            return Err(OrderError::EmptyOrder);
+        }
+        for (index, &(product_id, quantity)) in lines.iter().enumerate() {
+            if quantity == 0 {
+                return Err(OrderError::InvalidQuantity);
+            }
+            if !self.lines.iter().any(|line| line.product.id == product_id) {
+                return Err(OrderError::ProductNotFound);
+            }
+            // Count the same product appearing earlier in this shipment too
+            let requested: u32 = lines[..=index]
+                .iter()
+                .filter(|(id, _)| *id == product_id)
+                .map(|(_, quantity)| quantity)
+                .sum();
+            let unshipped = self.unshipped_quantity(product_id);
+            if requested > unshipped {
+                return Err(OrderError::ExceedsUnshipped { product_id, unshipped });
+            }
+        }
+
+        let id = self.shipments.len() as u32 + 1;
+        self.shipments.push(Shipment {
+            id,
+            carrier: carrier.to_string(),
+            tracking_number: tracking_number.to_string(),
+            lines,
+            status: ShipmentStatus::InTransit,
+        });
+        if let Err(e) = self.refresh_fulfillment_status() {
+            self.shipments.pop();
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    /// Marks a shipment as delivered; the order is delivered once every unit has arrived
+    pub fn deliver_shipment(&mut self, shipment_id: u32) -> Result<(), OrderError> {
+        let index = self.shipments
+            .iter()
+            .position(|s| s.id == shipment_id)
+            .ok_or(OrderError::ShipmentNotFound)?;
+        let previous = std::mem::replace(&mut self.shipments[index].status, ShipmentStatus::Delivered);
+        self.refresh_fulfillment_status().inspect_err(|_| self.shipments[index].status = previous)
+    }
+
+    /// Derives the order status from its shipments; leaves it unchanged on error
+    fn refresh_fulfillment_status(&mut self) -> Result<(), OrderError> {
+        let all_shipped = self.lines.iter().all(|line| self.unshipped_quantity(line.product.id) == 0);
+        let all_delivered = self.shipments.iter().all(|s| s.status == ShipmentStatus::Delivered);
+
+        let derived = match (all_shipped, all_delivered) {
+            (true, true) => OrderStatus::Delivered,
+            (true, false) => OrderStatus::Shipped,
+            (false, _) => OrderStatus::PartiallyShipped,
+        };
+        if derived != self.status {
+            self.update_status(derived)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the order currency and freezes today's rate and rounding onto the order
+    pub fn checkout(
+        &mut self,
+        currency: Currency,
+        rates: &RateTable,
+        rounding: Rounding,
+        at: DateTime<Utc>,
+    ) -> Result<(), OrderError> {
+        if self.frozen_rate.is_some() {
+            return Err(OrderError::Currency(CurrencyError::AlreadyFrozen));
+        }
+        let rate = rates
+            .rate(&Currency::base().code, &currency.code, at)
+            .map_err(OrderError::Currency)?;
+
+        self.currency = currency;
+        self.frozen_rate = Some(FrozenRate { rate, rounding, frozen_at: at });
+        Ok(())
+    }
+
+    /// Converts a base-currency amount with the frozen rate, if there is one
+    pub fn to_order_currency(&self, amount: f64) -> f64 {
+        match &self.frozen_rate {
+            Some(frozen) => frozen.convert(amount, &self.currency),
+            None => amount,
+        }
+    }
+
+    /// Total in the order currency; each unit price is converted and rounded before summing
+    pub fn amount_due(&self) -> f64 {
+        match &self.frozen_rate {
+            Some(_) => self.lines
+                .iter()
+                .map(|line| self.to_order_currency(line.price.unit_price) * line.quantity as f64)
+                .sum(),
+            None => self.total,
+        }
+    }
+
+    /// Base-currency amount paid with gift cards and store credit
+    pub fn redeemed_total(&self) -> f64 {
+        self.redemptions.iter().map(|r| r.amount).sum()
+    }
+
+    /// What is left for the payment gateway once gift cards are applied, in the order currency
+    pub fn balance_due(&self) -> f64 {
+        if self.redemptions.is_empty() {
+            return self.amount_due();
+        }
+        (self.amount_due() - self.to_order_currency(self.redeemed_total())).max(0.0)
+    }
+
+    /// True when gift cards cover the whole order, leaving nothing to charge
+    fn covered_by_redemptions(&self) -> bool {
+        !self.redemptions.is_empty() && self.balance_due() < 0.005
+    }
+
+    /// Whether the order has been paid for, by captured card payment or entirely by gift cards
+    pub fn is_paid(&self) -> bool {
+        matches!(self.payment, PaymentStatus::Captured { .. })
+            || (self.covered_by_redemptions() && matches!(self.payment, PaymentStatus::Unpaid))
+    }
+
+    /// Formats a base-currency amount in the order currency
+    pub fn format_amount(&self, amount: f64) -> String {
+        self.currency.format(self.to_order_currency(amount))
+    }
+
+    /// Authorizes whatever gift cards do not cover with the gateway; nothing is charged if they cover it all
+    pub fn authorize_payment(&mut self, gateway: &mut dyn PaymentGateway) -> Result<(), OrderError> {
+        if self.is_empty() {
+            return Err(OrderError::EmptyOrder);
+        }
+        if !matches!(self.payment, PaymentStatus::Unpaid | PaymentStatus::Failed { .. }) {
+            return Err(OrderError::Payment(PaymentError::InvalidState));
+        }
+        if self.covered_by_redemptions() {
+            self.payment = PaymentStatus::Unpaid;
+            return Ok(());
+        }
+
+        let amount = self.balance_due();
+        match gateway.authorize(self.id, amount) {
+            Ok(transaction_id) => {
+                self.payment = PaymentStatus::Authorized { transaction_id, amount, currency: self.currency.code.clone() };
+                Ok(())
+            }
+            Err(err) => {
+                self.payment = PaymentStatus::Failed { reason: err.to_string() };
+                Err(OrderError::Payment(err))
+            }
+        }
+    }
+
+    /// Captures a previously authorized payment; a failed capture leaves the authorization in place
+    pub fn capture_payment(&mut self, gateway: &mut dyn PaymentGateway) -> Result<(), OrderError> {
+        if self.is_paid() && matches!(self.payment, PaymentStatus::Unpaid) {
+            return Ok(());
+        }
+        let (transaction_id, amount, currency) = match &self.payment {
+            PaymentStatus::Authorized { transaction_id, amount, currency } => (transaction_id.clone(), *amount, currency.clone()),
+            _ => return Err(OrderError::Payment(PaymentError::InvalidState)),
+        };
+
+        gateway.capture(&transaction_id, amount).map_err(OrderError::Payment)?;
+        self.payment = PaymentStatus::Captured { transaction_id, amount, currency };
+        Ok(())
+    }
+
+    /// Releases an authorization that has not been captured
+    pub fn void_payment(&mut self, gateway: &mut dyn PaymentGateway) -> Result<(), OrderError> {
+        let transaction_id = match &self.payment {
+            PaymentStatus::Authorized { transaction_id, .. } => transaction_id.clone(),
+            _ => return Err(OrderError::Payment(PaymentError::InvalidState)),
+        };
+
+        gateway.void(&transaction_id).map_err(OrderError::Payment)?;
+        self.payment = PaymentStatus::Voided { transaction_id };
+        Ok(())
+    }
+
+    /// Refunds the full captured amount
+    pub fn refund_payment(&mut self, gateway: &mut dyn PaymentGateway) -> Result<(), OrderError> {
+        let (transaction_id, amount, currency) = match &self.payment {
+            PaymentStatus::Captured { transaction_id, amount, currency } => (transaction_id.clone(), *amount, currency.clone()),
+            _ => return Err(OrderError::Payment(PaymentError::InvalidState)),
+        };
+
+        gateway.refund(&transaction_id, amount).map_err(OrderError::Payment)?;
+        self.payment = PaymentStatus::Refunded { transaction_id, amount, currency };
         Ok(())
     }
 
@@ -105,33 +500,157 @@ impl Order {
     }
 
     pub fn is_empty(&self) -> bool {
-        self.products.is_empty()
+        self.lines.is_empty()
     }
 
     pub fn display(&self) {
-        println!("┌─────────── Order Details ────────────┐");
-        println!("│ Order ID: {:<24} │", self.id);
-        println!("│ Status: {:<25} │", self.status);
-        println!("└────────────────────────────────────┘");
-        
-        println!("Customer Information:");
-        self.user.display();
-        
-        println!("Products:");
-        println!("┌─────────────────────────────────────────────┐");
-        for (product, quantity) in &self.products {
-            println!("│ {}x {} (${:.2} each)", 
-                quantity, product.name, product.price);
-        }
-        println!("└─────────────────────────────────────────────┘");
-        println!("Total: ${:.2}", self.total);
+        print!("{}", self.render_to_string(Format::Boxed));
     }
 
     pub fn get_order_summary(&self) -> String {
-        format!("Order #{} - {} - {} items - Total: ${:.2}", 
+        format!("Order #{} - {} - {} items - Total: {}", 
             self.id, 
             self.status, 
-            self.products.len(),
-            self.total)
+            self.lines.len(),
+            self.currency.format(self.amount_due()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payment::{MockGateway, MockResponse};
+
+    fn order_with(lines: &[(u32, f64, u32)]) -> Order {
+        let user = User::new_unchecked(1, "Ada".into(), "ada@example.com".into(), "1 Main St".into());
+        let mut order = Order::new(1, user);
+        for &(id, price, quantity) in lines {
+            order.add_product(Product::new(id, format!("P{}", id), price, String::new()), quantity).unwrap();
+        }
+        order
+    }
+
+    #[test]
+    fn payment_lifecycle() {
+        let mut gateway = MockGateway::new();
+        let mut order = order_with(&[(1, 10.0, 2)]);
+        order.authorize_payment(&mut gateway).unwrap();
+        assert!(matches!(order.payment, PaymentStatus::Authorized { amount, .. } if amount == 20.0));
+        assert!(!order.is_paid());
+        order.capture_payment(&mut gateway).unwrap();
+        assert!(order.is_paid());
+        order.refund_payment(&mut gateway).unwrap();
+        assert!(matches!(order.payment, PaymentStatus::Refunded { .. }));
+    }
+
+    #[test]
+    fn declined_authorization_can_be_retried() {
+        let mut gateway = MockGateway::new();
+        gateway.script(MockResponse::Decline("card expired".into()));
+        let mut order = order_with(&[(1, 10.0, 1)]);
+        assert!(matches!(order.authorize_payment(&mut gateway), Err(OrderError::Payment(_))));
+        assert!(matches!(order.payment, PaymentStatus::Failed { .. }));
+        order.authorize_payment(&mut gateway).unwrap();
+        order.void_payment(&mut gateway).unwrap();
+        assert!(order.capture_payment(&mut gateway).is_err());
+    }
+
+    #[test]
+    fn empty_orders_cannot_be_paid() {
+        let mut order = order_with(&[]);
+        assert!(matches!(order.authorize_payment(&mut MockGateway::new()), Err(OrderError::EmptyOrder)));
+    }
+
+    #[test]
+    fn lines_keep_the_price_they_were_charged() {
+        let user = User::new_unchecked(1, "Ada".into(), "ada@example.com".into(), "1 Main St".into());
+        let mut pricing = PricingEngine::new();
+        pricing.add_rule(1, crate::pricing::RuleCondition::MinQuantity(3), 8.0).unwrap();
+        let mut order = Order::new(1, user);
+        let widget = Product::new(1, "Widget".into(), 10.0, String::new());
+        order.add_product_priced(widget, 3, &pricing, Utc::now()).unwrap();
+        order.add_product(Product::new(2, "Gadget".into(), 5.0, String::new()), 2).unwrap();
+
+        assert_eq!(order.total, 34.0);
+        assert_eq!(order.charged_price(1).unwrap().unit_price, 8.0);
+        assert!(order.charged_price(1).unwrap().rule.is_some());
+        order.remove_product(1).unwrap();
+        assert_eq!(order.total, 10.0);
+        assert_eq!(order.lines().len(), 1);
+        assert!(matches!(order.remove_product(1), Err(OrderError::ProductNotFound)));
+        assert!(matches!(order.add_product(Product::new(3, "X".into(), 1.0, String::new()), 0), Err(OrderError::InvalidQuantity)));
+    }
+
+    #[test]
+    fn lines_are_frozen_once_the_order_moves_on() {
+        let mut order = order_with(&[(1, 10.0, 1)]);
+        order.update_status(OrderStatus::Processing).unwrap();
+        assert!(matches!(order.remove_product(1), Err(OrderError::InvalidStatus)));
+        assert!(matches!(order.add_product(Product::new(2, "X".into(), 1.0, String::new()), 1), Err(OrderError::InvalidStatus)));
+    }
+
+    #[test]
+    fn shipping_requires_captured_payment() {
+        let mut order = order_with(&[(1, 10.0, 1)]);
+        order.update_status(OrderStatus::Processing).unwrap();
+        assert!(matches!(order.update_status(OrderStatus::Shipped), Err(OrderError::PaymentNotCaptured)));
+    }
+
+    fn paid_processing_order(lines: &[(u32, f64, u32)]) -> Order {
+        let mut order = order_with(lines);
+        let mut gateway = MockGateway::new();
+        order.authorize_payment(&mut gateway).unwrap();
+        order.capture_payment(&mut gateway).unwrap();
+        order.update_status(OrderStatus::Processing).unwrap();
+        order
+    }
+
+    #[test]
+    fn order_status_follows_its_shipments() {
+        let mut order = paid_processing_order(&[(1, 10.0, 3), (2, 5.0, 1)]);
+        let first = order.add_shipment("UPS", "1Z1", vec![(1, 2)]).unwrap();
+        assert_eq!(order.status, OrderStatus::PartiallyShipped);
+        assert_eq!(order.unshipped_quantity(1), 1);
+
+        let second = order.add_shipment("DHL", "JD2", vec![(1, 1), (2, 1)]).unwrap();
+        assert_eq!(order.status, OrderStatus::Shipped);
+        order.deliver_shipment(first).unwrap();
+        assert_eq!(order.status, OrderStatus::Shipped);
+        order.deliver_shipment(second).unwrap();
+        assert_eq!(order.status, OrderStatus::Delivered);
+        assert!(matches!(order.deliver_shipment(9), Err(OrderError::ShipmentNotFound)));
+    }
+
+    #[test]
+    fn shipments_cannot_exceed_what_was_ordered() {
+        let mut order = paid_processing_order(&[(1, 10.0, 2)]);
+        assert!(matches!(
+            order.add_shipment("UPS", "1Z1", vec![(1, 1), (1, 2)]),
+            Err(OrderError::ExceedsUnshipped { product_id: 1, unshipped: 2 })
+        ));
+        assert!(matches!(order.add_shipment("UPS", "1Z1", vec![(2, 1)]), Err(OrderError::ProductNotFound)));
+        assert!(matches!(order.add_shipment("UPS", "1Z1", vec![(1, 0)]), Err(OrderError::InvalidQuantity)));
+        assert!(matches!(order.add_shipment("UPS", "1Z1", vec![]), Err(OrderError::EmptyOrder)));
+        assert!(order.shipments.is_empty());
+        assert_eq!(order.status, OrderStatus::Processing);
+    }
+
+    #[test]
+    fn unpaid_orders_cannot_ship() {
+        let mut order = order_with(&[(1, 10.0, 1)]);
+        order.update_status(OrderStatus::Processing).unwrap();
+        assert!(matches!(order.add_shipment("UPS", "1Z1", vec![(1, 1)]), Err(OrderError::PaymentNotCaptured)));
+        assert!(order.shipments.is_empty());
+    }
+
+    #[test]
+    fn a_rejected_delivery_leaves_the_shipment_in_transit() {
+        let mut order = paid_processing_order(&[(1, 10.0, 1)]);
+        let id = order.add_shipment("UPS", "1Z1", vec![(1, 1)]).unwrap();
+        order.status = OrderStatus::Cancelled;
+
+        assert!(matches!(order.deliver_shipment(id), Err(OrderError::InvalidStatus)));
+        assert_eq!(order.shipments[0].status, ShipmentStatus::InTransit);
+        assert_eq!(order.status, OrderStatus::Cancelled);
     }
 }
@@ -0,0 +1,496 @@
+use std::fmt;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use crate::error::Error;
+use crate::order::{Order, OrderStatus};
+use crate::render::{json_string, Format, Render};
+
+/// Largest page a single query may ask for
+pub const MAX_PER_PAGE: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderSort {
+    Id,
+    CreatedAt,
+    Total,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum QueryError {
+    UnknownParameter(String),
+    InvalidValue { parameter: String, value: String },
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryError::UnknownParameter(name) => write!(f, "Unknown query parameter '{}'", name),
+            QueryError::InvalidValue { parameter, value } => {
+                write!(f, "Invalid value '{}' for query parameter '{}'", value, parameter)
+            }
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// Which orders to find and how to page through them; unset filters match everything
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderQuery {
+    pub user_id: Option<u32>,
+    pub statuses: Vec<OrderStatus>, // any of these; empty matches all
+    pub created_from: Option<DateTime<Utc>>, // inclusive
+    pub created_to: Option<DateTime<Utc>>,   // exclusive
+    pub min_total: Option<f64>,
+    pub max_total: Option<f64>,
+    pub product_id: Option<u32>,
+    pub sort: OrderSort,
+    pub descending: bool,
+    pub page: u32, // from 1
+    pub per_page: u32,
+}
+
+impl Default for OrderQuery {
+    /// Newest orders first, 20 to a page
+    fn default() -> Self {
+        OrderQuery {
+            user_id: None,
+            statuses: Vec::new(),
+            created_from: None,
+            created_to: None,
+            min_total: None,
+            max_total: None,
+            product_id: None,
+            sort: OrderSort::CreatedAt,
+            descending: true,
+            page: 1,
+            per_page: 20,
+        }
+    }
+}
+
+/// Parses a status as written in queries, e.g. `pending` or `partially_shipped`
+fn parse_status(name: &str) -> Option<OrderStatus> {
+    Some(match name.to_lowercase().replace('-', "_").as_str() {
+        "pending" => OrderStatus::Pending,
+        "under_review" => OrderStatus::UnderReview,
+        "backordered" => OrderStatus::Backordered,
+        "processing" => OrderStatus::Processing,
+        "partially_shipped" => OrderStatus::PartiallyShipped,
+        "shipped" => OrderStatus::Shipped,
+        "delivered" => OrderStatus::Delivered,
+        "cancelled" => OrderStatus::Cancelled,
+        _ => return None,
+    })
+}
+
+/// Parses an RFC 3339 timestamp, a `YYYY-MM-DD` date, `today`, or `-7d` for days before now.
+///
+/// Dates mean midnight UTC at the start of the day, or the end of it when `end_of_day` is set.
+/// Anything unparseable or outside chrono's date range is an invalid value for `parameter`.
+fn parse_time(parameter: &str, value: &str, now: DateTime<Utc>, end_of_day: bool) -> Result<DateTime<Utc>, QueryError> {
+    let invalid = || QueryError::InvalidValue { parameter: parameter.to_string(), value: value.to_string() };
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    if let Some(days) = value.strip_prefix('-').and_then(|v| v.strip_suffix('d')) {
+        let days = days.parse::<i64>().ok().filter(|days| *days >= 0).and_then(Duration::try_days).ok_or_else(invalid)?;
+        return now.checked_sub_signed(days).ok_or_else(invalid);
+    }
+    let date = if value.eq_ignore_ascii_case("today") {
+        now.date_naive()
+    } else {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| invalid())?
+    };
+    let date = if end_of_day { date.succ_opt().ok_or_else(invalid)? } else { date };
+    Ok(date.and_hms_opt(0, 0, 0).ok_or_else(invalid)?.and_utc())
+}
+
+/// Decodes `%XX` escapes and `+` as space
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = |b: u8| (b as char).to_digit(16);
+                match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                    (Some(high), Some(low)) => {
+                        decoded.push((high * 16 + low) as u8);
+                        i += 2;
+                    }
+                    _ => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Splits `a=1&b=2` into decoded `(name, value)` pairs
+pub fn parse_query_string(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) => (percent_decode(name), percent_decode(value)),
+            None => (percent_decode(pair), String::new()),
+        })
+        .collect()
+}
+
+impl OrderQuery {
+    /// Builds a query from `(name, value)` pairs, the shared form of CLI flags and URL parameters.
+    ///
+    /// Names: `user`, `status` (comma-separated), `from`, `to`, `min_total`,
+    /// `max_total`, `product`, `sort` (`id`, `created` or `total`; prefix `-`
+    /// for descending), `page` and `per_page`. `to` dates include the whole day.
+    pub fn from_params<I>(params: I, now: DateTime<Utc>) -> Result<Self, QueryError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut query = OrderQuery::default();
+        for (name, value) in params {
+            let invalid = || QueryError::InvalidValue { parameter: name.clone(), value: value.clone() };
+            let number = |value: &str| value.parse::<f64>().ok().filter(|n| n.is_finite()).ok_or_else(invalid);
+            let id = |value: &str| value.parse::<u32>().map_err(|_| invalid());
+            match name.as_str() {
+                "user" => query.user_id = Some(id(&value)?),
+                "status" => {
+                    for status in value.split(',').filter(|s| !s.is_empty()) {
+                        query.statuses.push(parse_status(status.trim()).ok_or_else(invalid)?);
+                    }
+                }
+                "from" => query.created_from = Some(parse_time(&name, &value, now, false)?),
+                "to" => query.created_to = Some(parse_time(&name, &value, now, true)?),
+                "min_total" => query.min_total = Some(number(&value)?),
+                "max_total" => query.max_total = Some(number(&value)?),
+                "product" => query.product_id = Some(id(&value)?),
+                "sort" => {
+                    let (descending, key) = match value.strip_prefix('-') {
+                        Some(key) => (true, key),
+                        None => (false, value.as_str()),
+                    };
+                    query.sort = match key {
+                        "id" => OrderSort::Id,
+                        "created" => OrderSort::CreatedAt,
+                        "total" => OrderSort::Total,
+                        _ => return Err(invalid()),
+                    };
+                    query.descending = descending;
+                }
+                "page" => query.page = id(&value)?.max(1),
+                "per_page" => query.per_page = id(&value)?.clamp(1, MAX_PER_PAGE),
+                _ => return Err(QueryError::UnknownParameter(name)),
+            }
+        }
+        Ok(query)
+    }
+
+    /// Parses a URL query string such as `user=3&status=pending&from=-7d`
+    pub fn from_query_string(query: &str, now: DateTime<Utc>) -> Result<Self, QueryError> {
+        Self::from_params(parse_query_string(query), now)
+    }
+
+    /// Whether an order passes every filter; a product filter also matches bundles containing it
+    pub fn matches(&self, order: &Order) -> bool {
+        self.user_id.is_none_or(|id| order.user.id == id)
+            && (self.statuses.is_empty() || self.statuses.contains(&order.status))
+            && self.created_from.is_none_or(|from| order.created_at >= from)
+            && self.created_to.is_none_or(|to| order.created_at < to)
+            && self.min_total.is_none_or(|min| order.total >= min)
+            && self.max_total.is_none_or(|max| order.total <= max)
+            && self.product_id.is_none_or(|id| {
+                order.lines().iter().any(|line| line.product.id == id || line.product.components.iter().any(|(c, _)| *c == id))
+            })
+    }
+
+    /// Filters, sorts and pages the orders; ties are broken by order id
+    pub fn run<'a>(&self, orders: impl IntoIterator<Item = &'a Order>) -> OrderPage {
+        let mut matched: Vec<&Order> = orders.into_iter().filter(|order| self.matches(order)).collect();
+        matched.sort_by(|a, b| {
+            let ordering = match self.sort {
+                OrderSort::Id => a.id.cmp(&b.id),
+                OrderSort::CreatedAt => a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)),
+                OrderSort::Total => a.total.total_cmp(&b.total).then(a.id.cmp(&b.id)),
+            };
+            if self.descending { ordering.reverse() } else { ordering }
+        });
+
+        let per_page = self.per_page.clamp(1, MAX_PER_PAGE);
+        let page = self.page.max(1);
+        let start = ((page - 1) as usize).saturating_mul(per_page as usize);
+        OrderPage {
+            orders: matched.iter().skip(start).take(per_page as usize).map(|order| (*order).clone()).collect(),
+            total_matches: matched.len(),
+            page,
+            per_page,
+        }
+    }
+}
+
+/// One page of query results
+#[derive(Debug, Clone)]
+pub struct OrderPage {
+    pub orders: Vec<Order>,
+    pub total_matches: usize,
+    pub page: u32,
+    pub per_page: u32,
+}
+
+impl OrderPage {
+    pub fn page_count(&self) -> u32 {
+        self.total_matches.div_ceil(self.per_page as usize) as u32
+    }
+
+    pub fn display(&self) {
+        println!("{} matching order(s), page {} of {}", self.total_matches, self.page, self.page_count().max(1));
+        for order in &self.orders {
+            println!("  {} - {}", order.created_at.format("%Y-%m-%d %H:%M"), order.get_order_summary());
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let orders: Vec<String> = self.orders
+            .iter()
+            .map(|order| order.render_to_string(Format::Json).trim_end().to_string())
+            .collect();
+        format!(
+            "{{\"total_matches\":{},\"page\":{},\"per_page\":{},\"page_count\":{},\"orders\":[{}]}}",
+            self.total_matches,
+            self.page,
+            self.per_page,
+            self.page_count(),
+            orders.join(","),
+        )
+    }
+}
+
+/// Answers an HTTP API request target such as `/orders?status=pending`, returning the status code and JSON body
+pub fn api_response<'a>(target: &str, orders: impl IntoIterator<Item = &'a Order>, now: DateTime<Utc>) -> (u16, String) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path != "/orders" {
+        return (404, String::from("{\"error\":{\"code\":\"NOT_FOUND\",\"message\":\"Unknown path\"}}"));
+    }
+    match OrderQuery::from_query_string(query, now) {
+        Ok(query) => (200, query.run(orders).to_json()),
+        Err(err) => {
+            let err = Error::from(err);
+            (400, format!(
+                "{{\"error\":{{\"code\":\"{}\",\"message\":{}}}}}",
+                err.code(),
+                json_string(&crate::error::report(&err))
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::product::Product;
+    use crate::user::User;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 5, 10, 15, 30, 0).unwrap()
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn invalid(parameter: &str, value: &str) -> QueryError {
+        QueryError::InvalidValue { parameter: parameter.into(), value: value.into() }
+    }
+
+    /// Orders 1..=count for users alternating 1 and 2, created an hour apart with totals of 10 × id
+    fn orders(count: u32) -> Vec<Order> {
+        (1..=count)
+            .map(|id| {
+                let user = User::new_unchecked(id % 2 + 1, "Ada".into(), "ada@example.com".into(), "Here".into());
+                let mut order = Order::new(id, user);
+                order.add_product(Product::new(id, format!("P{}", id), 10.0 * id as f64, String::new()), 1).unwrap();
+                order.created_at = now() - Duration::hours((count - id) as i64);
+                order
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_every_time_form() {
+        let now = now();
+        assert_eq!(parse_time("from", "-7d", now, false), Ok(now - Duration::days(7)));
+        assert_eq!(parse_time("from", "-0d", now, false), Ok(now));
+        assert_eq!(parse_time("from", "today", now, false), Ok(Utc.with_ymd_and_hms(2026, 5, 10, 0, 0, 0).unwrap()));
+        assert_eq!(parse_time("to", "TODAY", now, true), Ok(Utc.with_ymd_and_hms(2026, 5, 11, 0, 0, 0).unwrap()));
+        assert_eq!(parse_time("to", "2026-02-28", now, true), Ok(Utc.with_ymd_and_hms(2026, 3, 1, 0, 0, 0).unwrap()));
+        assert_eq!(
+            parse_time("from", "2026-01-02T03:04:05+02:00", now, true),
+            Ok(Utc.with_ymd_and_hms(2026, 1, 2, 1, 4, 5).unwrap())
+        );
+    }
+
+    #[test]
+    fn out_of_range_times_are_invalid_not_panics() {
+        let now = now();
+        for value in ["-999999999999d", "-9223372036854775807d", "-200000000d", "--5d", "-d", "-1.5d", "2026-02-30", "soon", ""] {
+            assert_eq!(parse_time("from", value, now, false), Err(invalid("from", value)), "{}", value);
+        }
+        // The last representable day has no following midnight
+        let last = NaiveDate::MAX.format("%Y-%m-%d").to_string();
+        assert_eq!(parse_time("to", &last, now, true), Err(invalid("to", &last)));
+        assert!(parse_time("from", &last, now, false).is_ok());
+    }
+
+    #[test]
+    fn builds_queries_from_params() {
+        let query = OrderQuery::from_query_string(
+            "user=3&status=pending,Partially-Shipped&from=-7d&to=2026-05-10&min_total=5&max_total=50.5&product=9&sort=-total&page=2&per_page=10",
+            now(),
+        )
+        .unwrap();
+        assert_eq!(query.user_id, Some(3));
+        assert_eq!(query.statuses, vec![OrderStatus::Pending, OrderStatus::PartiallyShipped]);
+        assert_eq!(query.created_from, Some(now() - Duration::days(7)));
+        assert_eq!(query.created_to, Some(Utc.with_ymd_and_hms(2026, 5, 11, 0, 0, 0).unwrap()));
+        assert_eq!((query.min_total, query.max_total, query.product_id), (Some(5.0), Some(50.5), Some(9)));
+        assert_eq!((query.sort, query.descending), (OrderSort::Total, true));
+        assert_eq!((query.page, query.per_page), (2, 10));
+
+        assert_eq!(OrderQuery::from_query_string("", now()), Ok(OrderQuery::default()));
+        let query = OrderQuery::from_params(params(&[("sort", "id"), ("page", "0"), ("per_page", "5000")]), now()).unwrap();
+        assert_eq!((query.sort, query.descending, query.page, query.per_page), (OrderSort::Id, false, 1, MAX_PER_PAGE));
+        assert_eq!(OrderQuery::from_params(params(&[("per_page", "0")]), now()).unwrap().per_page, 1);
+    }
+
+    #[test]
+    fn rejects_bad_params() {
+        let now = now();
+        assert_eq!(
+            OrderQuery::from_params(params(&[("colour", "red")]), now),
+            Err(QueryError::UnknownParameter("colour".into()))
+        );
+        for (name, value) in [
+            ("user", "-1"),
+            ("user", "abc"),
+            ("status", "pending,lost"),
+            ("from", "-99999999999999d"),
+            ("to", "yesterday"),
+            ("min_total", "NaN"),
+            ("max_total", "inf"),
+            ("sort", "name"),
+            ("sort", "-"),
+            ("page", "4294967296"),
+        ] {
+            assert_eq!(OrderQuery::from_params(params(&[(name, value)]), now), Err(invalid(name, value)));
+        }
+    }
+
+    #[test]
+    fn decodes_query_strings() {
+        assert_eq!(
+            parse_query_string("a=1%2C2&b+c=x+y&flag&&bad=%zz&end=%4"),
+            params(&[("a", "1,2"), ("b c", "x y"), ("flag", ""), ("bad", "%zz"), ("end", "%4")])
+        );
+    }
+
+    #[test]
+    fn filters_and_sorts() {
+        let orders = orders(6);
+        let query = OrderQuery { user_id: Some(1), sort: OrderSort::Total, descending: false, ..OrderQuery::default() };
+        let ids: Vec<u32> = query.run(&orders).orders.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![2, 4, 6]);
+
+        let query = OrderQuery { min_total: Some(20.0), max_total: Some(40.0), ..OrderQuery::default() };
+        let ids: Vec<u32> = query.run(&orders).orders.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![4, 3, 2]); // newest first by default
+
+        let query = OrderQuery {
+            created_from: Some(now() - Duration::hours(2)),
+            created_to: Some(now()),
+            ..OrderQuery::default()
+        };
+        let ids: Vec<u32> = query.run(&orders).orders.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![5, 4]); // `to` is exclusive
+
+        let query = OrderQuery { statuses: vec![OrderStatus::Cancelled], ..OrderQuery::default() };
+        assert_eq!(query.run(&orders).total_matches, 0);
+    }
+
+    #[test]
+    fn equal_keys_fall_back_to_order_id() {
+        let mut orders = orders(3);
+        for order in &mut orders {
+            order.created_at = now();
+        }
+        let ids: Vec<u32> = OrderQuery::default().run(&orders).orders.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![3, 2, 1]);
+        let query = OrderQuery { descending: false, ..OrderQuery::default() };
+        let ids: Vec<u32> = query.run(&orders).orders.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn product_filter_matches_bundles_containing_it() {
+        let user = User::new_unchecked(1, "Ada".into(), "ada@example.com".into(), "Here".into());
+        let mut bundle_order = Order::new(1, user.clone());
+        let bundle = Product::new(50, "Kit".into(), 30.0, String::new()).with_components(vec![(7, 2), (8, 1)]);
+        bundle_order.add_product(bundle, 1).unwrap();
+        let mut plain_order = Order::new(2, user);
+        plain_order.add_product(Product::new(8, "Cable".into(), 5.0, String::new()), 1).unwrap();
+        let orders = [bundle_order, plain_order];
+
+        let ids = |product_id| {
+            let query = OrderQuery { product_id: Some(product_id), sort: OrderSort::Id, descending: false, ..OrderQuery::default() };
+            query.run(&orders).orders.iter().map(|o| o.id).collect::<Vec<u32>>()
+        };
+        assert_eq!(ids(7), vec![1]);
+        assert_eq!(ids(8), vec![1, 2]);
+        assert_eq!(ids(50), vec![1]);
+        assert!(ids(9).is_empty());
+    }
+
+    #[test]
+    fn pages_through_results() {
+        let orders = orders(25);
+        let query = OrderQuery { sort: OrderSort::Id, descending: false, per_page: 10, ..OrderQuery::default() };
+        let page = query.run(&orders);
+        assert_eq!((page.total_matches, page.page_count(), page.orders.len()), (25, 3, 10));
+
+        let last = OrderQuery { page: 3, ..query.clone() }.run(&orders);
+        assert_eq!(last.orders.iter().map(|o| o.id).collect::<Vec<_>>(), vec![21, 22, 23, 24, 25]);
+        let beyond = OrderQuery { page: u32::MAX, ..query.clone() }.run(&orders);
+        assert!(beyond.orders.is_empty());
+        assert_eq!(beyond.total_matches, 25);
+
+        // Out-of-range values set directly are clamped the same way as parsed ones
+        let clamped = OrderQuery { page: 0, per_page: 0, ..query }.run(&orders);
+        assert_eq!((clamped.page, clamped.per_page, clamped.orders[0].id), (1, 1, 1));
+        assert_eq!(clamped.page_count(), 25);
+
+        let empty = OrderQuery::default().run(&[]);
+        assert_eq!((empty.total_matches, empty.page_count()), (0, 0));
+        assert!(empty.to_json().starts_with("{\"total_matches\":0,\"page\":1,\"per_page\":20,\"page_count\":0,\"orders\":[]"));
+    }
+
+    #[test]
+    fn api_responses() {
+        let orders = orders(3);
+        let (status, body) = api_response("/orders?per_page=2&sort=id", &orders, now());
+        assert_eq!(status, 200);
+        assert!(body.starts_with("{\"total_matches\":3,\"page\":1,\"per_page\":2,\"page_count\":2,"), "{}", body);
+
+        let (status, body) = api_response("/orders?from=-99999999999d", &orders, now());
+        assert_eq!(status, 400);
+        assert!(body.contains("-99999999999d"), "{}", body);
+        assert_eq!(api_response("/orders?bogus=1", &orders, now()).0, 400);
+        assert_eq!(api_response("/users", &orders, now()).0, 404);
+        assert_eq!(api_response("/orders/", &orders, now()).0, 404);
+    }
+}
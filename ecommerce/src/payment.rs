@@ -0,0 +1,205 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Identifier handed out by a gateway when a payment is authorized
+pub type TransactionId = String;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PaymentStatus {
+    Unpaid,
+    Authorized { transaction_id: TransactionId, amount: f64, currency: String },
+    Captured { transaction_id: TransactionId, amount: f64, currency: String },
+    Voided { transaction_id: TransactionId },
+    Refunded { transaction_id: TransactionId, amount: f64, currency: String },
+    Failed { reason: String },
+}
+
+impl fmt::Display for PaymentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaymentStatus::Unpaid => write!(f, "Unpaid"),
+            PaymentStatus::Authorized { amount, currency, .. } => write!(f, "Authorized ({:.2} {})", amount, currency),
+            PaymentStatus::Captured { amount, currency, .. } => write!(f, "Captured ({:.2} {})", amount, currency),
+            PaymentStatus::Voided { .. } => write!(f, "Voided"),
+            PaymentStatus::Refunded { amount, currency, .. } => write!(f, "Refunded ({:.2} {})", amount, currency),
+            PaymentStatus::Failed { reason } => write!(f, "Failed: {}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaymentError {
+    Declined(String),
+    Timeout,
+    InvalidAmount,
+    UnknownTransaction,
+    InvalidState,
+}
+
+impl fmt::Display for PaymentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaymentError::Declined(reason) => write!(f, "Payment declined: {}", reason),
+            PaymentError::Timeout => write!(f, "Payment gateway timed out"),
+            PaymentError::InvalidAmount => write!(f, "Invalid payment amount"),
+            PaymentError::UnknownTransaction => write!(f, "Unknown payment transaction"),
+            PaymentError::InvalidState => write!(f, "Payment is not in a valid state for this operation"),
+        }
+    }
+}
+
+impl std::error::Error for PaymentError {}
+
+/// A payment provider able to move money for an order
+pub trait PaymentGateway {
+    /// Reserves `amount` on the customer's payment method
+    fn authorize(&mut self, order_id: u32, amount: f64) -> Result<TransactionId, PaymentError>;
+
+    /// Collects a previously authorized amount
+    fn capture(&mut self, transaction_id: &str, amount: f64) -> Result<(), PaymentError>;
+
+    /// Releases an authorization that was never captured
+    fn void(&mut self, transaction_id: &str) -> Result<(), PaymentError>;
+
+    /// Returns captured money to the customer
+    fn refund(&mut self, transaction_id: &str, amount: f64) -> Result<(), PaymentError>;
+}
+
+/// Scripted outcome for the next call made against a `MockGateway`
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockResponse {
+    Approve,
+    Decline(String),
+    Timeout,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum MockTransaction {
+    Authorized(f64),
+    Captured(f64),
+    Voided,
+    Refunded(f64),
+}
+
+/// In-process gateway that approves everything unless told otherwise
+#[derive(Debug, Default)]
+pub struct MockGateway {
+    script: VecDeque<MockResponse>,
+    transactions: HashMap<TransactionId, MockTransaction>,
+    next_id: u32,
+    calls: Vec<String>,
+}
+
+impl MockGateway {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues the outcome of the next gateway call; unscripted calls are approved
+    pub fn script(&mut self, response: MockResponse) -> &mut Self {
+        self.script.push_back(response);
+        self
+    }
+
+    /// Lists every call made against the gateway, in order
+    pub fn calls(&self) -> &[String] {
+        &self.calls
+    }
+
+    fn next_response(&mut self, call: String) -> Result<(), PaymentError> {
+        self.calls.push(call);
+        match self.script.pop_front().unwrap_or(MockResponse::Approve) {
+            MockResponse::Approve => Ok(()),
+            MockResponse::Decline(reason) => Err(PaymentError::Declined(reason)),
+            MockResponse::Timeout => Err(PaymentError::Timeout),
+        }
+    }
+}
+
+impl PaymentGateway for MockGateway {
+    fn authorize(&mut self, order_id: u32, amount: f64) -> Result<TransactionId, PaymentError> {
+        if amount <= 0.0 {
+            return Err(PaymentError::InvalidAmount);
+        }
+        self.next_response(format!("authorize order={} amount={:.2}", order_id, amount))?;
+
+        self.next_id += 1;
+        let transaction_id = format!("mock-txn-{}", self.next_id);
+        self.transactions.insert(transaction_id.clone(), MockTransaction::Authorized(amount));
+        Ok(transaction_id)
+    }
+
+    fn capture(&mut self, transaction_id: &str, amount: f64) -> Result<(), PaymentError> {
+        match self.transactions.get(transaction_id) {
+            Some(MockTransaction::Authorized(authorized)) if amount > 0.0 && amount <= *authorized => {}
+            Some(MockTransaction::Authorized(_)) => return Err(PaymentError::InvalidAmount),
+            Some(_) => return Err(PaymentError::InvalidState),
+            None => return Err(PaymentError::UnknownTransaction),
+        }
+        self.next_response(format!("capture txn={} amount={:.2}", transaction_id, amount))?;
+        self.transactions.insert(transaction_id.to_string(), MockTransaction::Captured(amount));
+        Ok(())
+    }
+
+    fn void(&mut self, transaction_id: &str) -> Result<(), PaymentError> {
+        match self.transactions.get(transaction_id) {
+            Some(MockTransaction::Authorized(_)) => {}
+            Some(_) => return Err(PaymentError::InvalidState),
+            None => return Err(PaymentError::UnknownTransaction),
+        }
+        self.next_response(format!("void txn={}", transaction_id))?;
+        self.transactions.insert(transaction_id.to_string(), MockTransaction::Voided);
+        Ok(())
+    }
+
+    fn refund(&mut self, transaction_id: &str, amount: f64) -> Result<(), PaymentError> {
+        match self.transactions.get(transaction_id) {
+            Some(MockTransaction::Captured(captured)) if amount > 0.0 && amount <= *captured => {}
+            Some(MockTransaction::Captured(_)) => return Err(PaymentError::InvalidAmount),
+            Some(_) => return Err(PaymentError::InvalidState),
+            None => return Err(PaymentError::UnknownTransaction),
+        }
+        self.next_response(format!("refund txn={} amount={:.2}", transaction_id, amount))?;
+        self.transactions.insert(transaction_id.to_string(), MockTransaction::Refunded(amount));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn authorize_capture_refund() {
+        let mut gateway = MockGateway::new();
+        let txn = gateway.authorize(1, 50.0).unwrap();
+        assert_eq!(gateway.capture(&txn, 60.0), Err(PaymentError::InvalidAmount));
+        gateway.capture(&txn, 50.0).unwrap();
+        assert_eq!(gateway.void(&txn), Err(PaymentError::InvalidState));
+        gateway.refund(&txn, 50.0).unwrap();
+        assert_eq!(gateway.refund(&txn, 50.0), Err(PaymentError::InvalidState));
+        assert_eq!(gateway.calls().len(), 3);
+    }
+
+    #[test]
+    fn rejects_bad_amounts_and_unknown_transactions() {
+        let mut gateway = MockGateway::new();
+        assert_eq!(gateway.authorize(1, 0.0), Err(PaymentError::InvalidAmount));
+        assert_eq!(gateway.capture("nope", 1.0), Err(PaymentError::UnknownTransaction));
+        assert_eq!(gateway.void("nope"), Err(PaymentError::UnknownTransaction));
+        assert!(gateway.calls().is_empty());
+    }
+
+    #[test]
+    fn scripted_responses_apply_in_order() {
+        let mut gateway = MockGateway::new();
+        gateway.script(MockResponse::Decline("insufficient funds".into())).script(MockResponse::Timeout);
+        assert_eq!(gateway.authorize(1, 10.0), Err(PaymentError::Declined("insufficient funds".into())));
+        assert_eq!(gateway.authorize(1, 10.0), Err(PaymentError::Timeout));
+        let txn = gateway.authorize(1, 10.0).unwrap();
+        gateway.void(&txn).unwrap();
+        assert_eq!(gateway.capture(&txn, 10.0), Err(PaymentError::InvalidState));
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+use crate::currency::Currency;
+use crate::pricing::{LinePrice, PricingError};
+use crate::product::Product;
+
+/// Why a product's list price changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PriceChangeKind {
+    // Declared in the order changes at the same moment apply: a sale can end,
+    // the regular price change and a new sale start all at once
+    /// The price going back to what it would have been without the sale
+    SaleEnd,
+    /// A new regular price
+    Regular,
+    SaleStart,
+}
+
+impl fmt::Display for PriceChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PriceChangeKind::Regular => write!(f, "Price change"),
+            PriceChangeKind::SaleStart => write!(f, "Sale starts"),
+            PriceChangeKind::SaleEnd => write!(f, "Sale ends"),
+        }
+    }
+}
+
+/// A list price that holds from `effective_from` until the next change
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceChange {
+    pub price: Option<f64>, // None when a sale ends; the latest regular price applies
+    pub effective_from: DateTime<Utc>,
+    pub kind: PriceChangeKind,
+}
+
+/// Every list price each product has had or is scheduled to have
+#[derive(Debug, Default)]
+pub struct PriceHistory {
+    changes: HashMap<u32, Vec<PriceChange>>, // product_id -> changes, oldest first
+}
+
+impl PriceHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a product's history at its current price
+    pub fn track(&mut self, product: &Product, since: DateTime<Utc>) {
+        let change = PriceChange { price: Some(product.price()), effective_from: since, kind: PriceChangeKind::Regular };
+        self.insert(product.id, change);
+    }
+
+    /// Keeps changes sorted by time, then kind; a change of the same kind at the same moment replaces it
+    fn insert(&mut self, product_id: u32, change: PriceChange) {
+        let changes = self.changes.entry(product_id).or_default();
+        match changes.binary_search_by(|c| (c.effective_from, c.kind).cmp(&(change.effective_from, change.kind))) {
+            Ok(index) => changes[index] = change,
+            Err(index) => changes.insert(index, change),
+        }
+    }
+
+    /// Sets a new list price from `effective_from`, which may be in the future
+    pub fn set_price(&mut self, product_id: u32, price: f64, effective_from: DateTime<Utc>) -> Result<(), PricingError> {
+        if !price.is_finite() || price <= 0.0 {
+            return Err(PricingError::InvalidPrice);
+        }
+        if !self.changes.contains_key(&product_id) {
+            return Err(PricingError::NoHistory { product_id });
+        }
+        self.insert(product_id, PriceChange { price: Some(price), effective_from, kind: PriceChangeKind::Regular });
+        Ok(())
+    }
+
+    /// Drops the list price to `price` from `starts` until `ends`.
+    ///
+    /// At `ends` the price returns to the latest regular price, including
+    /// regular changes scheduled after the sale was added. Changes already
+    /// scheduled inside the window still take effect when they come.
+    pub fn schedule_sale(
+        &mut self,
+        product_id: u32,
+        price: f64,
+        starts: DateTime<Utc>,
+        ends: DateTime<Utc>,
+    ) -> Result<(), PricingError> {
+        if !price.is_finite() || price <= 0.0 {
+            return Err(PricingError::InvalidPrice);
+        }
+        if ends <= starts {
+            return Err(PricingError::InvalidSaleWindow);
+        }
+        if self.regular_price_at(product_id, ends).is_none() {
+            return Err(PricingError::NoHistory { product_id });
+        }
+        self.insert(product_id, PriceChange { price: Some(price), effective_from: starts, kind: PriceChangeKind::SaleStart });
+        self.insert(product_id, PriceChange { price: None, effective_from: ends, kind: PriceChangeKind::SaleEnd });
+        Ok(())
+    }
+
+    /// The change in effect for a product at `at`
+    pub fn change_at(&self, product_id: u32, at: DateTime<Utc>) -> Option<&PriceChange> {
+        self.changes.get(&product_id)?.iter().rev().find(|change| change.effective_from <= at)
+    }
+
+    /// The latest regular price set at or before `at`, ignoring sales
+    fn regular_price_at(&self, product_id: u32, at: DateTime<Utc>) -> Option<f64> {
+        self.changes
+            .get(&product_id)?
+            .iter()
+            .rev()
+            .filter(|change| change.effective_from <= at)
+            .find_map(|change| change.price.filter(|_| change.kind == PriceChangeKind::Regular))
+    }
+
+    /// The price a change sets, looking up what a sale's end restores
+    pub fn resolved_price(&self, product_id: u32, change: &PriceChange) -> Option<f64> {
+        change.price.or_else(|| self.regular_price_at(product_id, change.effective_from))
+    }
+
+    /// List price of a product at `at`; `None` before its history starts
+    pub fn price_at(&self, product_id: u32, at: DateTime<Utc>) -> Option<f64> {
+        self.resolved_price(product_id, self.change_at(product_id, at)?)
+    }
+
+    /// What a line for this product would be charged at `at`, falling back to its current price
+    pub fn line_price(&self, product: &Product, at: DateTime<Utc>) -> LinePrice {
+        LinePrice {
+            unit_price: self.price_at(product.id, at).unwrap_or(product.price()),
+            rule: None,
+        }
+    }
+
+    /// Changes that have not taken effect yet, soonest first
+    pub fn scheduled(&self, product_id: u32, now: DateTime<Utc>) -> Vec<&PriceChange> {
+        self.history(product_id).iter().filter(|change| change.effective_from > now).collect()
+    }
+
+    pub fn history(&self, product_id: u32) -> &[PriceChange] {
+        self.changes.get(&product_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Brings each product's `price` up to date with its history, returning the ids that changed
+    pub fn refresh<'a>(&self, products: impl IntoIterator<Item = &'a mut Product>, now: DateTime<Utc>) -> Vec<u32> {
+        let mut changed = Vec::new();
+        for product in products {
+            if let Some(price) = self.price_at(product.id, now) {
+                if price != product.price() {
+                    product.set_price(price);
+                    changed.push(product.id);
+                }
+            }
+        }
+        changed
+    }
+
+    pub fn display_history(&self, product_id: u32, now: DateTime<Utc>) {
+        let base = Currency::base();
+        println!("Price History for Product ID {}:", product_id);
+        for change in self.history(product_id) {
+            let marker = if change.effective_from > now { " (scheduled)" } else { "" };
+            let price = self.resolved_price(product_id, change).map_or_else(|| String::from("-"), |price| base.format(price));
+            println!("{} {} {}{}", change.effective_from.format("%Y-%m-%d %H:%M"), change.kind, price, marker);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn day(n: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + Duration::days(n)
+    }
+
+    fn tracked() -> (PriceHistory, Product) {
+        let product = Product::new(1, "Widget".into(), 10.0, String::new());
+        let mut history = PriceHistory::new();
+        history.track(&product, day(0));
+        (history, product)
+    }
+
+    #[test]
+    fn prices_hold_until_the_next_change() {
+        let (mut history, _) = tracked();
+        history.set_price(1, 12.0, day(10)).unwrap();
+        assert_eq!(history.price_at(1, day(-1)), None);
+        assert_eq!(history.price_at(1, day(9)), Some(10.0));
+        assert_eq!(history.price_at(1, day(10)), Some(12.0));
+        assert_eq!(history.scheduled(1, day(5)).len(), 1);
+    }
+
+    #[test]
+    fn untracked_products_have_no_history() {
+        let mut history = PriceHistory::new();
+        assert_eq!(history.set_price(2, 5.0, day(0)), Err(PricingError::NoHistory { product_id: 2 }));
+        assert_eq!(history.schedule_sale(2, 4.0, day(0), day(1)), Err(PricingError::NoHistory { product_id: 2 }));
+        assert!(history.history(2).is_empty());
+    }
+
+    #[test]
+    fn invalid_changes_are_rejected() {
+        let (mut history, _) = tracked();
+        assert_eq!(history.set_price(1, 0.0, day(1)), Err(PricingError::InvalidPrice));
+        assert_eq!(history.set_price(1, f64::NAN, day(1)), Err(PricingError::InvalidPrice));
+        assert_eq!(history.schedule_sale(1, 8.0, day(5), day(5)), Err(PricingError::InvalidSaleWindow));
+        // A sale ending before the history starts has nothing to go back to
+        assert_eq!(history.schedule_sale(1, 8.0, day(-5), day(-1)), Err(PricingError::NoHistory { product_id: 1 }));
+        assert_eq!(history.history(1).len(), 1);
+    }
+
+    #[test]
+    fn sales_end_at_the_latest_regular_price() {
+        let (mut history, _) = tracked();
+        history.schedule_sale(1, 8.0, day(5), day(20)).unwrap();
+        // Set after the sale was scheduled, but before it ends
+        history.set_price(1, 11.0, day(15)).unwrap();
+
+        assert_eq!(history.price_at(1, day(6)), Some(8.0));
+        assert_eq!(history.price_at(1, day(15)), Some(11.0));
+        assert_eq!(history.price_at(1, day(20)), Some(11.0));
+        history.set_price(1, 9.5, day(1)).unwrap();
+        history.set_price(1, 9.0, day(15)).unwrap();
+        assert_eq!(history.price_at(1, day(20)), Some(9.0));
+        let end = history.change_at(1, day(20)).unwrap();
+        assert_eq!((end.kind, end.price), (PriceChangeKind::SaleEnd, None));
+        assert_eq!(history.resolved_price(1, end), Some(9.0));
+    }
+
+    #[test]
+    fn changes_at_the_same_moment_keep_each_kind() {
+        let (mut history, _) = tracked();
+        history.schedule_sale(1, 8.0, day(5), day(10)).unwrap();
+        history.set_price(1, 12.0, day(10)).unwrap();
+        history.schedule_sale(1, 7.0, day(10), day(12)).unwrap();
+
+        let kinds: Vec<_> = history.history(1).iter().map(|c| (c.effective_from, c.kind)).collect();
+        assert_eq!(kinds, vec![
+            (day(0), PriceChangeKind::Regular),
+            (day(5), PriceChangeKind::SaleStart),
+            (day(10), PriceChangeKind::SaleEnd),
+            (day(10), PriceChangeKind::Regular),
+            (day(10), PriceChangeKind::SaleStart),
+            (day(12), PriceChangeKind::SaleEnd),
+        ]);
+        assert_eq!(history.price_at(1, day(10)), Some(7.0));
+        assert_eq!(history.price_at(1, day(12)), Some(12.0));
+
+        // The same kind at the same moment is a correction and replaces the old change
+        history.set_price(1, 13.0, day(10)).unwrap();
+        assert_eq!(history.history(1).len(), 6);
+        assert_eq!(history.price_at(1, day(12)), Some(13.0));
+    }
+
+    #[test]
+    fn refresh_moves_products_to_their_current_price() {
+        let (mut history, mut product) = tracked();
+        let mut untracked = Product::new(2, "Gadget".into(), 5.0, String::new());
+        history.set_price(1, 12.0, day(10)).unwrap();
+
+        assert!(history.refresh([&mut product, &mut untracked], day(5)).is_empty());
+        assert_eq!(history.refresh([&mut product, &mut untracked], day(10)), vec![1]);
+        assert_eq!((product.price(), untracked.price()), (12.0, 5.0));
+        assert_eq!(history.line_price(&untracked, day(10)).unit_price, 5.0);
+        assert_eq!(history.line_price(&product, day(3)).unit_price, 10.0);
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::product::Product;
+use crate::user::User;
+
+/// When a price rule applies
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleCondition {
+    /// Buying at least this many units in one line
+    MinQuantity(u32),
+    /// The customer belongs to the named group (e.g. "wholesale")
+    CustomerGroup(String),
+    /// Time-bounded sale, `starts` inclusive and `ends` exclusive
+    Sale { starts: DateTime<Utc>, ends: DateTime<Utc> },
+}
+
+impl fmt::Display for RuleCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleCondition::MinQuantity(min) => write!(f, "{}+ units", min),
+            RuleCondition::CustomerGroup(group) => write!(f, "{} customers", group),
+            RuleCondition::Sale { starts, ends } => write!(
+                f,
+                "sale {} – {}",
+                starts.format("%Y-%m-%d"),
+                ends.format("%Y-%m-%d")
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceRule {
+    pub id: u32,
+    pub product_id: u32,
+    pub condition: RuleCondition,
+    pub unit_price: f64,
+}
+
+/// The rule that set a line's unit price
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppliedRule {
+    pub rule_id: u32,
+    pub description: String,
+}
+
+/// Unit price charged for an order line and where it came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinePrice {
+    pub unit_price: f64,
+    pub rule: Option<AppliedRule>, // None -> list price
+}
+
+impl LinePrice {
+    /// The product's own price with no rule applied
+    pub fn list(product: &Product) -> Self {
+        LinePrice {
+            unit_price: product.price(),
+            rule: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PricingError {
+    InvalidPrice,
+    InvalidQuantity,
+    InvalidSaleWindow,
+    /// The product's price history has not been started with `PriceHistory::track`
+    NoHistory { product_id: u32 },
+}
+
+impl fmt::Display for PricingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PricingError::InvalidPrice => write!(f, "Price must be a positive amount"),
+            PricingError::InvalidQuantity => write!(f, "Quantity tier must start above zero"),
+            PricingError::InvalidSaleWindow => write!(f, "Sale must end after it starts"),
+            PricingError::NoHistory { product_id } => write!(f, "Product {} has no price history", product_id),
+        }
+    }
+}
+
+impl std::error::Error for PricingError {}
+
+/// Picks the unit price for a product, customer and quantity.
+///
+/// Every matching rule is a candidate and the customer gets the lowest
+/// price among them and the list price.
+#[derive(Debug, Default)]
+pub struct PricingEngine {
+    rules: Vec<PriceRule>,
+    groups: HashMap<u32, String>, // user_id -> customer group
+    next_id: u32,
+}
+
+impl PricingEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_rule(&mut self, product_id: u32, condition: RuleCondition, unit_price: f64) -> Result<u32, PricingError> {
+        if !unit_price.is_finite() || unit_price <= 0.0 {
+            return Err(PricingError::InvalidPrice);
+        }
+        match &condition {
+            RuleCondition::MinQuantity(0) => return Err(PricingError::InvalidQuantity),
+            RuleCondition::Sale { starts, ends } if ends <= starts => return Err(PricingError::InvalidSaleWindow),
+            _ => {}
+        }
+
+        self.next_id += 1;
+        self.rules.push(PriceRule {
+            id: self.next_id,
+            product_id,
+            condition,
+            unit_price,
+        });
+        Ok(self.next_id)
+    }
+
+    pub fn remove_rule(&mut self, rule_id: u32) -> bool {
+        let before = self.rules.len();
+        self.rules.retain(|r| r.id != rule_id);
+        self.rules.len() != before
+    }
+
+    pub fn rules_for(&self, product_id: u32) -> Vec<&PriceRule> {
+        self.rules.iter().filter(|r| r.product_id == product_id).collect()
+    }
+
+    /// Places a user in a customer group, replacing any previous group
+    pub fn assign_group(&mut self, user_id: u32, group: &str) {
+        self.groups.insert(user_id, group.to_string());
+    }
+
+    pub fn group_of(&self, user_id: u32) -> Option<&str> {
+        self.groups.get(&user_id).map(String::as_str)
+    }
+
+    fn matches(&self, rule: &PriceRule, user: &User, quantity: u32, at: DateTime<Utc>) -> bool {
+        match &rule.condition {
+            RuleCondition::MinQuantity(min) => quantity >= *min,
+            RuleCondition::CustomerGroup(group) => self.group_of(user.id) == Some(group.as_str()),
+            RuleCondition::Sale { starts, ends } => *starts <= at && at < *ends,
+        }
+    }
+
+    /// Computes the unit price for a line at the given moment
+    pub fn quote(&self, product: &Product, user: &User, quantity: u32, at: DateTime<Utc>) -> LinePrice {
+        self.rules
+            .iter()
+            .filter(|r| r.product_id == product.id && r.unit_price < product.price())
+            .filter(|r| self.matches(r, user, quantity, at))
+            .min_by(|a, b| a.unit_price.total_cmp(&b.unit_price).then(a.id.cmp(&b.id)))
+            .map(|rule| LinePrice {
+                unit_price: rule.unit_price,
+                rule: Some(AppliedRule {
+                    rule_id: rule.id,
+                    description: rule.condition.to_string(),
+                }),
+            })
+            .unwrap_or_else(|| LinePrice::list(product))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn setup() -> (PricingEngine, Product, User, DateTime<Utc>) {
+        let product = Product::new(1, "Widget".into(), 10.0, String::new());
+        let user = User::new_unchecked(5, "Bea".into(), "bea@example.com".into(), "Here".into());
+        (PricingEngine::new(), product, user, Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap())
+    }
+
+    #[test]
+    fn lowest_matching_rule_wins() {
+        let (mut engine, product, user, now) = setup();
+        let tier = engine.add_rule(1, RuleCondition::MinQuantity(10), 8.0).unwrap();
+        engine.add_rule(1, RuleCondition::CustomerGroup("wholesale".into()), 7.0).unwrap();
+
+        assert_eq!(engine.quote(&product, &user, 9, now), LinePrice::list(&product));
+        let quote = engine.quote(&product, &user, 10, now);
+        assert_eq!((quote.unit_price, quote.rule.unwrap().rule_id), (8.0, tier));
+
+        engine.assign_group(user.id, "wholesale");
+        assert_eq!(engine.quote(&product, &user, 10, now).unit_price, 7.0);
+    }
+
+    #[test]
+    fn sales_apply_inside_their_window_only() {
+        let (mut engine, product, user, now) = setup();
+        engine.add_rule(1, RuleCondition::Sale { starts: now, ends: now + Duration::days(1) }, 6.0).unwrap();
+        assert_eq!(engine.quote(&product, &user, 1, now - Duration::seconds(1)).unit_price, 10.0);
+        assert_eq!(engine.quote(&product, &user, 1, now).unit_price, 6.0);
+        assert_eq!(engine.quote(&product, &user, 1, now + Duration::days(1)).unit_price, 10.0);
+    }
+
+    #[test]
+    fn rules_above_list_price_are_ignored() {
+        let (mut engine, product, user, now) = setup();
+        let id = engine.add_rule(1, RuleCondition::MinQuantity(1), 12.0).unwrap();
+        assert_eq!(engine.quote(&product, &user, 5, now).rule, None);
+        assert!(engine.remove_rule(id));
+        assert!(!engine.remove_rule(id));
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        let (mut engine, _, _, now) = setup();
+        assert_eq!(engine.add_rule(1, RuleCondition::MinQuantity(2), 0.0), Err(PricingError::InvalidPrice));
+        assert_eq!(engine.add_rule(1, RuleCondition::MinQuantity(2), f64::NAN), Err(PricingError::InvalidPrice));
+        assert_eq!(engine.add_rule(1, RuleCondition::MinQuantity(0), 5.0), Err(PricingError::InvalidQuantity));
+        assert_eq!(
+            engine.add_rule(1, RuleCondition::Sale { starts: now, ends: now }, 5.0),
+            Err(PricingError::InvalidSaleWindow)
+        );
+        assert!(engine.rules_for(1).is_empty());
+    }
+}
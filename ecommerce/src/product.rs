@@ -1,9 +1,28 @@
-#[derive(Debug, Clone)]
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::inventory::InventoryError;
+use crate::render::{Format, Render};
+
+/// What happens when a customer orders more than is in stock
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StockPolicy {
+    /// The order is refused
+    InStockOnly,
+    /// The shortfall waits for the next delivery
+    Backorder,
+    /// The product is not released yet; orders wait for launch stock
+    PreOrder { release: DateTime<Utc> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Product {
     pub id: u32,
     pub name: String,
-    pub price: f64,
+    price: f64, // changed only through `PriceHistory::refresh`, so the history explains every price
     pub description: String,
+    pub category: String,
+    pub stock_policy: StockPolicy,
+    pub components: Vec<(u32, u32)>, // (product_id, quantity) per bundle; empty for single items
 }
 
 impl Product {
@@ -13,12 +32,84 @@ impl Product {
             name,
             description,
             price,
+            category: String::new(),
+            stock_policy: StockPolicy::InStockOnly,
+            components: Vec::new(),
+        }
+    }
+
+    pub fn price(&self) -> f64 {
+        self.price
+    }
+
+    pub(crate) fn set_price(&mut self, price: f64) {
+        self.price = price;
+    }
+
+    /// Places the product in a category
+    pub fn with_category(mut self, category: &str) -> Self {
+        self.category = category.trim().to_string();
+        self
+    }
+
+    /// Sets whether the product can be backordered or pre-ordered
+    pub fn with_stock_policy(mut self, policy: StockPolicy) -> Self {
+        self.stock_policy = policy;
+        self
+    }
+
+    /// Makes this product a bundle of other products; it keeps its own price but holds no stock itself.
+    ///
+    /// Bundles are sold from stock on hand, so they must keep the in-stock-only policy.
+    pub fn with_components(mut self, components: Vec<(u32, u32)>) -> Self {
+        self.components = components.into_iter().filter(|(_, quantity)| *quantity > 0).collect();
+        self
+    }
+
+    pub fn is_bundle(&self) -> bool {
+        !self.components.is_empty()
+    }
+
+    /// The `(product_id, quantity)` stock movements needed to sell `quantity` of this product
+    pub fn stock_lines(&self, quantity: u32) -> Result<Vec<(u32, u32)>, InventoryError> {
+        if self.is_bundle() {
+            self.components
+                .iter()
+                .map(|(id, per_bundle)| per_bundle.checked_mul(quantity).map(|total| (*id, total)).ok_or(InventoryError::InvalidQuantity))
+                .collect()
+        } else {
+            Ok(vec![(self.id, quantity)])
         }
     }
 
     pub fn display(&self) {
-        println!("Product: {} (ID: {})", self.name, self.id);
-        println!("Price: ${:.2}", self.price);
-        println!("Description: {}", self.description);
+        print!("{}", self.render_to_string(Format::Plain));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_items_move_their_own_stock() {
+        let widget = Product::new(1, "Widget".into(), 10.0, String::new());
+        assert!(!widget.is_bundle());
+        assert_eq!(widget.stock_lines(3), Ok(vec![(1, 3)]));
+    }
+
+    #[test]
+    fn bundles_move_each_component() {
+        let kit = Product::new(9, "Kit".into(), 30.0, String::new()).with_components(vec![(1, 2), (2, 0), (3, 1)]);
+        // Zero-quantity components are dropped
+        assert_eq!(kit.components, vec![(1, 2), (3, 1)]);
+        assert_eq!(kit.stock_lines(4), Ok(vec![(1, 8), (3, 4)]));
+    }
+
+    #[test]
+    fn oversized_bundle_quantities_are_rejected() {
+        let kit = Product::new(9, "Kit".into(), 30.0, String::new()).with_components(vec![(1, 2)]);
+        assert_eq!(kit.stock_lines(u32::MAX / 2), Ok(vec![(1, u32::MAX - 1)]));
+        assert_eq!(kit.stock_lines(u32::MAX / 2 + 1), Err(InventoryError::InvalidQuantity));
     }
 }
@@ -0,0 +1,527 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::currency::Currency;
+use crate::inventory::{Inventory, InventoryError, MovementReason};
+use crate::product::Product;
+
+/// A company we buy stock from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Supplier {
+    pub id: u32,
+    pub name: String,
+    pub lead_time_days: u32, // from sending a purchase order to delivery
+    costs: HashMap<u32, f64>, // product_id -> unit cost in the base currency
+}
+
+impl Supplier {
+    pub fn new(id: u32, name: &str, lead_time_days: u32) -> Self {
+        Supplier { id, name: name.to_string(), lead_time_days, costs: HashMap::new() }
+    }
+
+    /// Sets what the supplier charges per unit of a product; the cost must be finite and not negative
+    pub fn with_cost(mut self, product_id: u32, unit_cost: f64) -> Result<Self, PurchasingError> {
+        if !unit_cost.is_finite() || unit_cost < 0.0 {
+            return Err(PurchasingError::InvalidCost { product_id });
+        }
+        self.costs.insert(product_id, unit_cost);
+        Ok(self)
+    }
+
+    pub fn cost(&self, product_id: u32) -> Option<f64> {
+        self.costs.get(&product_id).copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PurchaseOrderStatus {
+    Draft,
+    Sent,
+    PartiallyReceived,
+    Received,
+}
+
+impl fmt::Display for PurchaseOrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PurchaseOrderStatus::Draft => write!(f, "📝 Draft"),
+            PurchaseOrderStatus::Sent => write!(f, "📨 Sent"),
+            PurchaseOrderStatus::PartiallyReceived => write!(f, "📦 Partially received"),
+            PurchaseOrderStatus::Received => write!(f, "✅ Received"),
+        }
+    }
+}
+
+/// One product on a purchase order, priced at the supplier's cost when it was added
+#[derive(Debug, Clone, PartialEq)]
+pub struct PurchaseOrderLine {
+    pub product_id: u32,
+    pub ordered: u32,
+    pub received: u32,
+    pub unit_cost: f64,
+}
+
+impl PurchaseOrderLine {
+    pub fn outstanding(&self) -> u32 {
+        self.ordered - self.received
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PurchaseOrder {
+    pub id: u32,
+    pub supplier_id: u32,
+    pub lines: Vec<PurchaseOrderLine>,
+    pub status: PurchaseOrderStatus,
+    pub created_at: DateTime<Utc>,
+    pub expected_at: Option<DateTime<Utc>>, // set when sent
+}
+
+impl PurchaseOrder {
+    pub fn total_cost(&self) -> f64 {
+        self.lines.iter().map(|line| line.unit_cost * line.ordered as f64).sum()
+    }
+
+    pub fn display(&self) {
+        let base = Currency::base();
+        print!("PO-{} from supplier {} - {}", self.id, self.supplier_id, self.status);
+        match self.expected_at {
+            Some(expected) => println!(" (due {})", expected.format("%Y-%m-%d")),
+            None => println!(),
+        }
+        for line in &self.lines {
+            println!("  Product {}: {}/{} received at {} each",
+                line.product_id, line.received, line.ordered, base.format(line.unit_cost));
+        }
+        println!("  Total cost: {}", base.format(self.total_cost()));
+    }
+}
+
+/// Stock that arrived against a purchase order, and what each unit cost
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoodsReceipt {
+    pub purchase_order_id: u32,
+    pub product_id: u32,
+    pub quantity: u32,
+    pub unit_cost: f64,
+    pub movement_id: u64, // the inventory receipt this posted
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PurchasingError {
+    UnknownSupplier(u32),
+    UnknownPurchaseOrder(u32),
+    /// The supplier has no cost for the product, so cannot supply it
+    NotSupplied { supplier_id: u32, product_id: u32 },
+    InvalidQuantity,
+    InvalidStatus(PurchaseOrderStatus),
+    EmptyOrder,
+    OverReceipt { product_id: u32, outstanding: u32 },
+    InvalidCost { product_id: u32 },
+    /// The inventory refused a receipt, e.g. because the stock level would overflow
+    Inventory(InventoryError),
+}
+
+impl fmt::Display for PurchasingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PurchasingError::UnknownSupplier(id) => write!(f, "Unknown supplier {}", id),
+            PurchasingError::UnknownPurchaseOrder(id) => write!(f, "Unknown purchase order PO-{}", id),
+            PurchasingError::NotSupplied { supplier_id, product_id } => {
+                write!(f, "Supplier {} does not supply product {}", supplier_id, product_id)
+            }
+            PurchasingError::InvalidQuantity => write!(f, "Purchase quantities must be positive"),
+            PurchasingError::InvalidStatus(status) => write!(f, "Purchase order is {}", status),
+            PurchasingError::EmptyOrder => write!(f, "Purchase order has no lines"),
+            PurchasingError::OverReceipt { product_id, outstanding } => write!(
+                f,
+                "Receipt for product {} exceeds the {} unit(s) still outstanding",
+                product_id, outstanding
+            ),
+            PurchasingError::InvalidCost { product_id } => {
+                write!(f, "Unit cost for product {} must be a non-negative amount", product_id)
+            }
+            PurchasingError::Inventory(_) => write!(f, "Stock could not be received"),
+        }
+    }
+}
+
+impl std::error::Error for PurchasingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PurchasingError::Inventory(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Margin on one product at its current price and average received cost
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarginLine {
+    pub product_id: u32,
+    pub name: String,
+    pub price: f64,
+    pub unit_cost: f64,
+    pub margin: f64,
+    pub margin_percent: f64,
+}
+
+/// Suppliers, the purchase orders placed with them and the goods received against those orders
+#[derive(Debug, Default)]
+pub struct Purchasing {
+    suppliers: BTreeMap<u32, Supplier>,
+    orders: BTreeMap<u32, PurchaseOrder>,
+    receipts: Vec<GoodsReceipt>,
+}
+
+impl Purchasing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces a supplier
+    pub fn add_supplier(&mut self, supplier: Supplier) {
+        self.suppliers.insert(supplier.id, supplier);
+    }
+
+    pub fn supplier(&self, id: u32) -> Option<&Supplier> {
+        self.suppliers.get(&id)
+    }
+
+    /// The supplier with the lowest cost for a product, if any supplies it
+    pub fn cheapest_supplier(&self, product_id: u32) -> Option<&Supplier> {
+        self.suppliers
+            .values()
+            .filter(|supplier| supplier.cost(product_id).is_some())
+            .min_by(|a, b| a.costs[&product_id].total_cmp(&b.costs[&product_id]))
+    }
+
+    pub fn order(&self, id: u32) -> Option<&PurchaseOrder> {
+        self.orders.get(&id)
+    }
+
+    pub fn orders(&self) -> impl Iterator<Item = &PurchaseOrder> {
+        self.orders.values()
+    }
+
+    fn order_mut(&mut self, id: u32) -> Result<&mut PurchaseOrder, PurchasingError> {
+        self.orders.get_mut(&id).ok_or(PurchasingError::UnknownPurchaseOrder(id))
+    }
+
+    /// Opens an empty draft purchase order with a supplier and returns its id
+    pub fn create_order(&mut self, supplier_id: u32, now: DateTime<Utc>) -> Result<u32, PurchasingError> {
+        if !self.suppliers.contains_key(&supplier_id) {
+            return Err(PurchasingError::UnknownSupplier(supplier_id));
+        }
+        let id = self.orders.keys().next_back().map_or(1, |last| last + 1);
+        self.orders.insert(id, PurchaseOrder {
+            id,
+            supplier_id,
+            lines: Vec::new(),
+            status: PurchaseOrderStatus::Draft,
+            created_at: now,
+            expected_at: None,
+        });
+        Ok(id)
+    }
+
+    /// Adds units of a product to a draft, at the supplier's current cost
+    pub fn add_line(&mut self, order_id: u32, product_id: u32, quantity: u32) -> Result<(), PurchasingError> {
+        if quantity == 0 {
+            return Err(PurchasingError::InvalidQuantity);
+        }
+        let supplier_id = self.orders.get(&order_id).ok_or(PurchasingError::UnknownPurchaseOrder(order_id))?.supplier_id;
+        let unit_cost = self.suppliers[&supplier_id]
+            .cost(product_id)
+            .ok_or(PurchasingError::NotSupplied { supplier_id, product_id })?;
+
+        let order = self.order_mut(order_id)?;
+        if order.status != PurchaseOrderStatus::Draft {
+            return Err(PurchasingError::InvalidStatus(order.status));
+        }
+        match order.lines.iter_mut().find(|line| line.product_id == product_id) {
+            Some(line) => {
+                line.ordered = line.ordered.checked_add(quantity).ok_or(PurchasingError::InvalidQuantity)?;
+                line.unit_cost = unit_cost;
+            }
+            None => order.lines.push(PurchaseOrderLine { product_id, ordered: quantity, received: 0, unit_cost }),
+        }
+        Ok(())
+    }
+
+    /// Sends a draft to its supplier and tells the inventory when the stock is due
+    pub fn send(&mut self, order_id: u32, inventory: &mut Inventory, now: DateTime<Utc>) -> Result<(), PurchasingError> {
+        let supplier_id = self.orders.get(&order_id).ok_or(PurchasingError::UnknownPurchaseOrder(order_id))?.supplier_id;
+        let lead_time = Duration::days(self.suppliers[&supplier_id].lead_time_days as i64);
+
+        let order = self.order_mut(order_id)?;
+        if order.status != PurchaseOrderStatus::Draft {
+            return Err(PurchasingError::InvalidStatus(order.status));
+        }
+        if order.lines.is_empty() {
+            return Err(PurchasingError::EmptyOrder);
+        }
+        let expected = now + lead_time;
+        order.status = PurchaseOrderStatus::Sent;
+        order.expected_at = Some(expected);
+        for line in &order.lines {
+            inventory.expect_receipt(line.product_id, expected);
+        }
+        Ok(())
+    }
+
+    /// Books `(product_id, quantity)` deliveries against a sent order and posts them into stock.
+    ///
+    /// Either every line is accepted or nothing is posted. Movements are
+    /// stamped with `now`. Returns the inventory movement ids, one per line.
+    pub fn receive(
+        &mut self,
+        order_id: u32,
+        deliveries: &[(u32, u32)],
+        inventory: &mut Inventory,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<u64>, PurchasingError> {
+        let order = self.orders.get_mut(&order_id).ok_or(PurchasingError::UnknownPurchaseOrder(order_id))?;
+        if !matches!(order.status, PurchaseOrderStatus::Sent | PurchaseOrderStatus::PartiallyReceived) {
+            return Err(PurchasingError::InvalidStatus(order.status));
+        }
+        let mut incoming: HashMap<u32, u32> = HashMap::new();
+        for &(product_id, quantity) in deliveries {
+            if quantity == 0 {
+                return Err(PurchasingError::InvalidQuantity);
+            }
+            let total = incoming.entry(product_id).or_insert(0);
+            *total = total.checked_add(quantity).ok_or(PurchasingError::InvalidQuantity)?;
+        }
+        for (&product_id, &quantity) in &incoming {
+            let outstanding = order.lines
+                .iter()
+                .find(|line| line.product_id == product_id)
+                .map_or(0, PurchaseOrderLine::outstanding);
+            if quantity > outstanding {
+                return Err(PurchasingError::OverReceipt { product_id, outstanding });
+            }
+            if inventory.check_stock(product_id).checked_add(quantity).is_none() {
+                return Err(PurchasingError::Inventory(InventoryError::StockOverflow { product_id }));
+            }
+        }
+
+        let actor = format!("PO-{}", order_id);
+        let mut movements = Vec::new();
+        for &(product_id, quantity) in deliveries {
+            let Some(line) = order.lines.iter_mut().find(|line| line.product_id == product_id) else {
+                return Err(PurchasingError::OverReceipt { product_id, outstanding: 0 });
+            };
+            let movement_id = inventory
+                .adjust_at(product_id, quantity as i64, MovementReason::Receipt, &actor, now)
+                .map_err(PurchasingError::Inventory)?;
+            line.received += quantity;
+            movements.push(movement_id);
+            self.receipts.push(GoodsReceipt {
+                purchase_order_id: order_id,
+                product_id,
+                quantity,
+                unit_cost: line.unit_cost,
+                movement_id,
+                at: now,
+            });
+        }
+        order.status = if order.lines.iter().all(|line| line.outstanding() == 0) {
+            PurchaseOrderStatus::Received
+        } else {
+            PurchaseOrderStatus::PartiallyReceived
+        };
+        Ok(movements)
+    }
+
+    /// Every goods receipt, oldest first
+    pub fn receipts(&self) -> &[GoodsReceipt] {
+        &self.receipts
+    }
+
+    /// Average cost of the units received for a product, weighted by quantity
+    pub fn average_cost(&self, product_id: u32) -> Option<f64> {
+        let (units, cost) = self.receipts
+            .iter()
+            .filter(|receipt| receipt.product_id == product_id)
+            .fold((0u64, 0.0), |(units, cost), receipt| {
+                (units + receipt.quantity as u64, cost + receipt.unit_cost * receipt.quantity as f64)
+            });
+        (units > 0).then(|| cost / units as f64)
+    }
+
+    /// Unit cost of a product, summing the components of a bundle; `None` if any part was never received
+    pub fn product_cost(&self, product: &Product) -> Option<f64> {
+        if !product.is_bundle() {
+            return self.average_cost(product.id);
+        }
+        product.components
+            .iter()
+            .map(|(id, quantity)| self.average_cost(*id).map(|cost| cost * *quantity as f64))
+            .sum()
+    }
+
+    /// Margins for every product with a known cost, in the order given
+    pub fn margin_report<'a>(&self, products: impl IntoIterator<Item = &'a Product>) -> Vec<MarginLine> {
+        products
+            .into_iter()
+            .filter_map(|product| {
+                let unit_cost = self.product_cost(product)?;
+                let margin = product.price() - unit_cost;
+                Some(MarginLine {
+                    product_id: product.id,
+                    name: product.name.clone(),
+                    price: product.price(),
+                    unit_cost,
+                    margin,
+                    margin_percent: if product.price() > 0.0 { margin / product.price() * 100.0 } else { 0.0 },
+                })
+            })
+            .collect()
+    }
+
+    pub fn display_margins<'a>(&self, products: impl IntoIterator<Item = &'a Product>) {
+        let base = Currency::base();
+        for line in self.margin_report(products) {
+            println!("{}: price {}, cost {}, margin {} ({:.1}%)",
+                line.name, base.format(line.price), base.format(line.unit_cost), base.format(line.margin), line.margin_percent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, 8, 0, 0).unwrap()
+    }
+
+    /// One supplier of products 1 and 2, with PO-1 sent for 10 and 4 units
+    fn sent_order(inventory: &mut Inventory) -> Purchasing {
+        let mut purchasing = Purchasing::new();
+        purchasing.add_supplier(Supplier::new(1, "Acme", 5).with_cost(1, 2.0).unwrap().with_cost(2, 3.5).unwrap());
+        let po = purchasing.create_order(1, now()).unwrap();
+        purchasing.add_line(po, 1, 10).unwrap();
+        purchasing.add_line(po, 2, 4).unwrap();
+        purchasing.send(po, inventory, now()).unwrap();
+        purchasing
+    }
+
+    #[test]
+    fn supplier_costs_must_be_real_amounts() {
+        assert_eq!(Supplier::new(1, "Acme", 5).with_cost(1, f64::NAN), Err(PurchasingError::InvalidCost { product_id: 1 }));
+        assert_eq!(Supplier::new(1, "Acme", 5).with_cost(1, -0.01), Err(PurchasingError::InvalidCost { product_id: 1 }));
+        assert_eq!(Supplier::new(1, "Acme", 5).with_cost(1, f64::INFINITY), Err(PurchasingError::InvalidCost { product_id: 1 }));
+        assert_eq!(Supplier::new(1, "Acme", 5).with_cost(1, 0.0).unwrap().cost(1), Some(0.0));
+    }
+
+    #[test]
+    fn cheapest_supplier_wins() {
+        let mut purchasing = Purchasing::new();
+        purchasing.add_supplier(Supplier::new(1, "Acme", 5).with_cost(1, 2.0).unwrap());
+        purchasing.add_supplier(Supplier::new(2, "Budget", 9).with_cost(1, 1.5).unwrap().with_cost(2, 1.0).unwrap());
+        purchasing.add_supplier(Supplier::new(3, "Other", 1));
+        assert_eq!(purchasing.cheapest_supplier(1).unwrap().id, 2);
+        assert_eq!(purchasing.cheapest_supplier(2).unwrap().id, 2);
+        assert!(purchasing.cheapest_supplier(3).is_none());
+    }
+
+    #[test]
+    fn drafts_only_take_supplied_products() {
+        let mut purchasing = Purchasing::new();
+        purchasing.add_supplier(Supplier::new(1, "Acme", 5).with_cost(1, 2.0).unwrap());
+        assert_eq!(purchasing.create_order(9, now()), Err(PurchasingError::UnknownSupplier(9)));
+        let po = purchasing.create_order(1, now()).unwrap();
+        assert_eq!(purchasing.add_line(po, 1, 0), Err(PurchasingError::InvalidQuantity));
+        assert_eq!(purchasing.add_line(po, 2, 1), Err(PurchasingError::NotSupplied { supplier_id: 1, product_id: 2 }));
+        assert_eq!(purchasing.send(po, &mut Inventory::new(), now()), Err(PurchasingError::EmptyOrder));
+        purchasing.add_line(po, 1, u32::MAX).unwrap();
+        assert_eq!(purchasing.add_line(po, 1, 1), Err(PurchasingError::InvalidQuantity));
+    }
+
+    #[test]
+    fn sending_sets_the_expected_date() {
+        let mut inventory = Inventory::new();
+        let mut purchasing = sent_order(&mut inventory);
+        let order = purchasing.order(1).unwrap();
+        assert_eq!(order.status, PurchaseOrderStatus::Sent);
+        assert_eq!(order.expected_at, Some(now() + Duration::days(5)));
+        assert_eq!(order.total_cost(), 34.0);
+        assert_eq!(purchasing.add_line(1, 1, 1), Err(PurchasingError::InvalidStatus(PurchaseOrderStatus::Sent)));
+    }
+
+    #[test]
+    fn receipts_post_stock_at_the_time_given() {
+        let mut inventory = Inventory::new();
+        let mut purchasing = sent_order(&mut inventory);
+        let arrived = now() + Duration::days(4);
+
+        let movements = purchasing.receive(1, &[(1, 6)], &mut inventory, arrived).unwrap();
+        assert_eq!(purchasing.order(1).unwrap().status, PurchaseOrderStatus::PartiallyReceived);
+        assert_eq!(inventory.check_stock(1), 6);
+        let movement = &inventory.movements()[movements[0] as usize - 1];
+        assert_eq!((movement.at, movement.reason, movement.actor.as_str()), (arrived, MovementReason::Receipt, "PO-1"));
+        assert_eq!(purchasing.receipts()[0].at, arrived);
+
+        purchasing.receive(1, &[(1, 4), (2, 4)], &mut inventory, arrived).unwrap();
+        assert_eq!(purchasing.order(1).unwrap().status, PurchaseOrderStatus::Received);
+        assert_eq!(
+            purchasing.receive(1, &[(1, 1)], &mut inventory, arrived),
+            Err(PurchasingError::InvalidStatus(PurchaseOrderStatus::Received))
+        );
+    }
+
+    #[test]
+    fn rejected_receipts_post_nothing() {
+        let mut inventory = Inventory::new();
+        let mut purchasing = sent_order(&mut inventory);
+
+        assert_eq!(purchasing.receive(1, &[(1, 1), (2, 0)], &mut inventory, now()), Err(PurchasingError::InvalidQuantity));
+        assert_eq!(
+            purchasing.receive(1, &[(1, 1), (2, 3), (2, 2)], &mut inventory, now()),
+            Err(PurchasingError::OverReceipt { product_id: 2, outstanding: 4 })
+        );
+        assert_eq!(
+            purchasing.receive(1, &[(3, 1)], &mut inventory, now()),
+            Err(PurchasingError::OverReceipt { product_id: 3, outstanding: 0 })
+        );
+        assert!(inventory.movements().is_empty());
+        assert!(purchasing.receipts().is_empty());
+    }
+
+    #[test]
+    fn stock_overflow_is_reported_not_panicked() {
+        let mut inventory = Inventory::new();
+        let mut purchasing = sent_order(&mut inventory);
+        inventory.add_stock(2, u32::MAX - 1).unwrap();
+
+        let err = purchasing.receive(1, &[(1, 5), (2, 2)], &mut inventory, now()).unwrap_err();
+        assert_eq!(err, PurchasingError::Inventory(InventoryError::StockOverflow { product_id: 2 }));
+        assert_eq!(
+            crate::error::report(&err),
+            format!("Stock could not be received: {}", InventoryError::StockOverflow { product_id: 2 })
+        );
+        assert_eq!(inventory.check_stock(1), 0);
+        assert_eq!(purchasing.order(1).unwrap().lines[0].received, 0);
+    }
+
+    #[test]
+    fn margins_use_the_average_received_cost() {
+        let mut inventory = Inventory::new();
+        let mut purchasing = sent_order(&mut inventory);
+        purchasing.receive(1, &[(1, 10), (2, 4)], &mut inventory, now()).unwrap();
+
+        let widget = Product::new(1, "Widget".into(), 4.0, String::new());
+        let kit = Product::new(9, "Kit".into(), 10.0, String::new()).with_components(vec![(1, 2), (2, 1)]);
+        let unknown = Product::new(5, "Unknown".into(), 1.0, String::new());
+        let report = purchasing.margin_report([&widget, &kit, &unknown]);
+        assert_eq!(report.len(), 2);
+        assert_eq!((report[0].unit_cost, report[0].margin_percent), (2.0, 50.0));
+        assert_eq!((report[1].unit_cost, report[1].margin), (7.5, 2.5));
+    }
+}
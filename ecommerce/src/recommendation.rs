@@ -0,0 +1,234 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::Bound;
+
+use crate::inventory::Inventory;
+use crate::order::{Order, OrderStatus};
+use crate::product::Product;
+
+/// How strongly buying one product predicts buying another
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Association {
+    /// Share of all orders containing both products
+    pub support: f64,
+    /// Share of orders with the first product that also contain the second
+    pub confidence: f64,
+    /// Confidence relative to how often the second product is bought anyway
+    pub lift: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recommendation {
+    pub product_id: u32,
+    pub score: f64,
+    pub association: Association,
+}
+
+/// Item-to-item "frequently bought together" model built from order history.
+///
+/// Only co-occurrence counts and the products seen are stored, so new
+/// orders can be folded in with `add_order` without rebuilding from scratch.
+#[derive(Debug, Default)]
+pub struct Recommender {
+    order_count: u32,
+    item_counts: HashMap<u32, u32>,        // product_id -> orders containing it
+    pair_counts: HashMap<(u32, u32), u32>, // (lower id, higher id) -> orders containing both
+    seen_orders: HashSet<u32>,
+    products: HashMap<u32, Product>, // latest version seen, for stock checks on bundles
+    min_pair_count: u32,
+}
+
+impl Recommender {
+    /// `min_pair_count` is how many orders must share two products before they are associated
+    pub fn new(min_pair_count: u32) -> Self {
+        Recommender {
+            min_pair_count: min_pair_count.max(1),
+            ..Self::default()
+        }
+    }
+
+    pub fn from_orders<'a>(orders: impl IntoIterator<Item = &'a Order>, min_pair_count: u32) -> Self {
+        let mut recommender = Recommender::new(min_pair_count);
+        for order in orders {
+            recommender.add_order(order);
+        }
+        recommender
+    }
+
+    pub fn order_count(&self) -> u32 {
+        self.order_count
+    }
+
+    /// Folds an order into the model; cancelled, empty and already seen orders are ignored
+    pub fn add_order(&mut self, order: &Order) -> bool {
+        if order.status == OrderStatus::Cancelled || order.is_empty() || !self.seen_orders.insert(order.id) {
+            return false;
+        }
+
+        let items: BTreeSet<u32> = order.lines().iter().map(|line| line.product.id).collect();
+        for line in order.lines() {
+            self.products.insert(line.product.id, line.product.clone());
+        }
+        self.order_count += 1;
+        for &a in &items {
+            *self.item_counts.entry(a).or_insert(0) += 1;
+            for &b in items.range((Bound::Excluded(a), Bound::Unbounded)) {
+                *self.pair_counts.entry((a, b)).or_insert(0) += 1;
+            }
+        }
+        true
+    }
+
+    /// Association from `antecedent` to `consequent`, if they were bought together often enough
+    pub fn association(&self, antecedent: u32, consequent: u32) -> Option<Association> {
+        let key = (antecedent.min(consequent), antecedent.max(consequent));
+        let together = *self.pair_counts.get(&key).filter(|c| **c >= self.min_pair_count)? as f64;
+        let with_antecedent = *self.item_counts.get(&antecedent)? as f64;
+        let with_consequent = *self.item_counts.get(&consequent)? as f64;
+        let orders = self.order_count as f64;
+
+        let confidence = together / with_antecedent;
+        Some(Association {
+            support: together / orders,
+            confidence,
+            lift: confidence / (with_consequent / orders),
+        })
+    }
+
+    /// Ranked suggestions to show next to a product
+    pub fn recommend_for_product(&self, product_id: u32, inventory: &Inventory, limit: usize) -> Vec<Recommendation> {
+        self.recommend_for_cart(&[product_id], inventory, limit)
+    }
+
+    /// Ranked suggestions for a whole cart.
+    ///
+    /// A candidate's score is its summed confidence across the cart items,
+    /// weighted by lift so products that are bought with everything anyway
+    /// do not crowd out genuine pairings. Products that cannot be sold now,
+    /// judged the same way as search with `Inventory::available`, and
+    /// products already in the cart are skipped.
+    pub fn recommend_for_cart(&self, cart: &[u32], inventory: &Inventory, limit: usize) -> Vec<Recommendation> {
+        let mut best: HashMap<u32, Recommendation> = HashMap::new();
+
+        for &in_cart in cart {
+            for &candidate in self.item_counts.keys() {
+                let available = self.products.get(&candidate).map_or(0, |product| inventory.available(product));
+                if cart.contains(&candidate) || available == 0 {
+                    continue;
+                }
+                let Some(association) = self.association(in_cart, candidate) else {
+                    continue;
+                };
+
+                let score = association.confidence * association.lift;
+                best.entry(candidate)
+                    .and_modify(|r| {
+                        r.score += score;
+                        if association.confidence > r.association.confidence {
+                            r.association = association;
+                        }
+                    })
+                    .or_insert(Recommendation { product_id: candidate, score, association });
+            }
+        }
+
+        let mut recommendations: Vec<Recommendation> = best.into_values().collect();
+        recommendations.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.product_id.cmp(&b.product_id)));
+        recommendations.truncate(limit);
+        recommendations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::User;
+
+    fn order(id: u32, product_ids: &[u32]) -> Order {
+        let user = User::new_unchecked(1, "Cy".into(), "cy@example.com".into(), "Road".into());
+        let mut order = Order::new(id, user);
+        for &product_id in product_ids {
+            order.add_product(Product::new(product_id, String::new(), 1.0, String::new()), 1).unwrap();
+        }
+        order
+    }
+
+    fn stocked(ids: &[u32]) -> Inventory {
+        let mut inventory = Inventory::new();
+        for &id in ids {
+            inventory.add_stock(id, 5).unwrap();
+        }
+        inventory
+    }
+
+    #[test]
+    fn association_metrics() {
+        let orders = [order(1, &[1, 2]), order(2, &[1, 2]), order(3, &[1, 3]), order(4, &[2])];
+        let recommender = Recommender::from_orders(&orders, 1);
+        let association = recommender.association(1, 2).unwrap();
+        assert_eq!(association.support, 0.5);
+        assert!((association.confidence - 2.0 / 3.0).abs() < 1e-9);
+        assert!((association.lift - (2.0 / 3.0) / 0.75).abs() < 1e-9);
+        assert!(recommender.association(2, 3).is_none());
+    }
+
+    #[test]
+    fn ignores_cancelled_empty_and_repeated_orders() {
+        let mut cancelled = order(2, &[1, 2]);
+        cancelled.status = OrderStatus::Cancelled;
+        let mut recommender = Recommender::new(1);
+        assert!(recommender.add_order(&order(1, &[1, 2])));
+        assert!(!recommender.add_order(&order(1, &[1, 2])));
+        assert!(!recommender.add_order(&cancelled));
+        assert!(!recommender.add_order(&order(3, &[])));
+        assert_eq!(recommender.order_count(), 1);
+    }
+
+    #[test]
+    fn pairs_below_the_minimum_are_not_associated() {
+        let orders = [order(1, &[1, 2]), order(2, &[1, 3]), order(3, &[1, 3])];
+        let recommender = Recommender::from_orders(&orders, 2);
+        assert!(recommender.association(1, 2).is_none());
+        assert!(recommender.association(1, 3).is_some());
+    }
+
+    #[test]
+    fn recommendations_skip_cart_items_and_out_of_stock_products() {
+        let orders = [order(1, &[1, 2, 3]), order(2, &[1, 2]), order(3, &[1, 4]), order(4, &[2, 3])];
+        let recommender = Recommender::from_orders(&orders, 1);
+
+        let all = recommender.recommend_for_product(1, &stocked(&[2, 3, 4]), 10);
+        assert_eq!(all[0].product_id, 2);
+        assert_eq!(all.len(), 3);
+
+        let without_two = recommender.recommend_for_product(1, &stocked(&[3, 4]), 10);
+        assert!(without_two.iter().all(|r| r.product_id != 2));
+
+        let cart = recommender.recommend_for_cart(&[1, 2], &stocked(&[1, 2, 3, 4]), 1);
+        assert_eq!(cart.len(), 1);
+        assert_eq!(cart[0].product_id, 3);
+    }
+
+    #[test]
+    fn bundles_are_recommended_when_their_components_are_in_stock() {
+        let user = User::new_unchecked(1, "Cy".into(), "cy@example.com".into(), "Road".into());
+        let kit = Product::new(9, "Kit".into(), 5.0, String::new()).with_components(vec![(2, 2), (3, 1)]);
+        let orders: Vec<Order> = (1..=2)
+            .map(|id| {
+                let mut order = Order::new(id, user.clone());
+                order.add_product(Product::new(1, String::new(), 1.0, String::new()), 1).unwrap();
+                order.add_product(kit.clone(), 1).unwrap();
+                order
+            })
+            .collect();
+        let recommender = Recommender::from_orders(&orders, 1);
+
+        let suggested = recommender.recommend_for_product(1, &stocked(&[2, 3]), 10);
+        assert_eq!(suggested.iter().map(|r| r.product_id).collect::<Vec<_>>(), vec![9]);
+
+        // One of the two units a kit needs is not enough to make a set
+        let mut short = Inventory::new();
+        short.add_stock(2, 1).unwrap();
+        short.add_stock(3, 5).unwrap();
+        assert!(recommender.recommend_for_product(1, &short, 10).is_empty());
+    }
+}
@@ -0,0 +1,444 @@
+use std::fmt::{self, Write};
+use std::io;
+
+use crate::currency::Currency;
+use crate::inventory::Inventory;
+use crate::order::{Order, OrderLine};
+use crate::payment::PaymentStatus;
+use crate::product::Product;
+use crate::user::User;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `Label: value` lines for people reading a terminal
+    Plain,
+    /// Box-drawn panels, as printed by the `display` methods
+    Boxed,
+    /// A single JSON document
+    Json,
+    /// One `key=value` record per line (logfmt), for scripts and log pipelines
+    KeyValue,
+}
+
+/// Writes a value in any of the supported formats
+pub trait Render {
+    fn render(&self, out: &mut dyn Write, format: Format) -> fmt::Result;
+
+    fn render_to_string(&self, format: Format) -> String {
+        let mut out = String::new();
+        self.render(&mut out, format).expect("Writing to a String never fails");
+        out
+    }
+
+    fn render_io(&self, out: &mut dyn io::Write, format: Format) -> io::Result<()> {
+        out.write_all(self.render_to_string(format).as_bytes())
+    }
+}
+
+/// Quotes and escapes a string for JSON output
+pub fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Quotes a logfmt value when it contains spaces, quotes or `=`
+fn kv_value(value: &str) -> String {
+    if value.is_empty() || value.contains([' ', '"', '=', '\n']) {
+        json_string(value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Rounds money for JSON so it does not carry float noise
+fn json_amount(amount: f64) -> String {
+    format!("{:.2}", amount)
+}
+
+/// Lists bundle components as `2x #3, 1x #4`
+fn components_list(components: &[(u32, u32)]) -> String {
+    components.iter().map(|(id, quantity)| format!("{}x #{}", quantity, id)).collect::<Vec<_>>().join(", ")
+}
+
+impl Render for Product {
+    fn render(&self, out: &mut dyn Write, format: Format) -> fmt::Result {
+        let price = Currency::base().format(self.price());
+        match format {
+            Format::Plain => {
+                writeln!(out, "Product: {} (ID: {})", self.name, self.id)?;
+                writeln!(out, "Price: {}", price)?;
+                if !self.category.is_empty() {
+                    writeln!(out, "Category: {}", self.category)?;
+                }
+                if self.is_bundle() {
+                    writeln!(out, "Bundle of: {}", components_list(&self.components))?;
+                }
+                writeln!(out, "Description: {}", self.description)
+            }
+            Format::Boxed => {
+                writeln!(out, "┌────────── Product Details ─────────┐")?;
+                writeln!(out, "│ ID: {:<29} │", self.id)?;
+                writeln!(out, "│ Name: {:<27} │", self.name)?;
+                writeln!(out, "│ Price: {:<26} │", price)?;
+                writeln!(out, "│ Category: {:<23} │", self.category)?;
+                writeln!(out, "│ {:<34} │", self.description)?;
+                writeln!(out, "└────────────────────────────────────┘")
+            }
+            Format::Json => writeln!(
+                out,
+                "{{\"id\":{},\"name\":{},\"price\":{},\"description\":{},\"category\":{},\"components\":[{}]}}",
+                self.id,
+                json_string(&self.name),
+                json_amount(self.price()),
+                json_string(&self.description),
+                json_string(&self.category),
+                self.components
+                    .iter()
+                    .map(|(id, quantity)| format!("{{\"product_id\":{},\"quantity\":{}}}", id, quantity))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            Format::KeyValue => writeln!(
+                out,
+                "type=product id={} name={} price={:.2} category={}",
+                self.id,
+                kv_value(&self.name),
+                self.price(),
+                kv_value(&self.category),
+            ),
+        }
+    }
+}
+
+impl Render for User {
+    fn render(&self, out: &mut dyn Write, format: Format) -> fmt::Result {
+        match format {
+            Format::Plain => {
+                writeln!(out, "User: {} (ID: {})", self.name, self.id)?;
+                writeln!(out, "Email: {}", self.email)?;
+                writeln!(out, "Address: {}", self.address)?;
+                writeln!(out, "Loyalty: {} ({} points)", self.tier, self.loyalty_points)
+            }
+            Format::Boxed => {
+                writeln!(out, "┌─────────── User Details ───────────┐")?;
+                writeln!(out, "│ ID: {:<29} │", self.id)?;
+                writeln!(out, "│ Name: {:<27} │", self.name)?;
+                writeln!(out, "│ Email: {:<26} │", self.email)?;
+                writeln!(out, "│ Address: {:<24} │", self.address)?;
+                writeln!(out, "│ Loyalty: {:<24} │", format!("{} ({} pts)", self.tier, self.loyalty_points))?;
+                writeln!(out, "└────────────────────────────────────┘")
+            }
+            Format::Json => writeln!(out, "{}", user_json(self)),
+            Format::KeyValue => writeln!(
+                out,
+                "type=user id={} name={} email={} address={} tier={:?} points={}",
+                self.id,
+                kv_value(&self.name),
+                kv_value(&self.email),
+                kv_value(&self.address),
+                self.tier,
+                self.loyalty_points,
+            ),
+        }
+    }
+}
+
+fn user_json(user: &User) -> String {
+    format!(
+        "{{\"id\":{},\"name\":{},\"email\":{},\"address\":{},\"tier\":\"{:?}\",\"points\":{}}}",
+        user.id,
+        json_string(&user.name),
+        json_string(&user.email),
+        json_string(&user.address),
+        user.tier,
+        user.loyalty_points,
+    )
+}
+
+/// `{"status":..,"amount":..,"currency":..}`; amount and currency are null when no money has moved
+fn payment_json(payment: &PaymentStatus) -> String {
+    let (status, money) = match payment {
+        PaymentStatus::Unpaid => ("Unpaid", None),
+        PaymentStatus::Authorized { amount, currency, .. } => ("Authorized", Some((amount, currency))),
+        PaymentStatus::Captured { amount, currency, .. } => ("Captured", Some((amount, currency))),
+        PaymentStatus::Voided { .. } => ("Voided", None),
+        PaymentStatus::Refunded { amount, currency, .. } => ("Refunded", Some((amount, currency))),
+        PaymentStatus::Failed { .. } => ("Failed", None),
+    };
+    let (amount, currency) = money.map_or((String::from("null"), String::from("null")), |(amount, currency)| {
+        (json_amount(*amount), json_string(currency))
+    });
+    format!("{{\"status\":\"{}\",\"amount\":{},\"currency\":{}}}", status, amount, currency)
+}
+
+impl Order {
+    fn product_name(&self, product_id: u32) -> &str {
+        self.lines()
+            .iter()
+            .find(|line| line.product.id == product_id)
+            .map(|line| line.product.name.as_str())
+            .unwrap_or("Unknown product")
+    }
+
+    fn render_redemptions(&self, out: &mut dyn Write) -> fmt::Result {
+        if self.redemptions.is_empty() {
+            return Ok(());
+        }
+        for redemption in &self.redemptions {
+            writeln!(out, "{}: -{}", redemption.label(), self.format_amount(redemption.amount))?;
+        }
+        writeln!(out, "Balance due: {}", self.currency.format(self.balance_due()))
+    }
+}
+
+impl Render for Order {
+    fn render(&self, out: &mut dyn Write, format: Format) -> fmt::Result {
+        let lines = self.lines().iter();
+        match format {
+            Format::Plain => {
+                writeln!(out, "Order #{}", self.id)?;
+                writeln!(out, "Status: {}", self.status)?;
+                writeln!(out, "Payment: {}", self.payment)?;
+                writeln!(out, "Customer: {}", self.user)?;
+                writeln!(out, "Products:")?;
+                for OrderLine { product, quantity, price } in lines {
+                    writeln!(out, "  {}x {} ({} each)", quantity, product.name, self.format_amount(price.unit_price))?;
+                }
+                writeln!(out, "Total: {}", self.currency.format(self.amount_due()))?;
+                self.render_redemptions(out)?;
+                for shipment in &self.shipments {
+                    writeln!(out, "Shipment #{}: {} {} ({})",
+                        shipment.id, shipment.carrier, shipment.tracking_number, shipment.status)?;
+                }
+                Ok(())
+            }
+            Format::Boxed => {
+                writeln!(out, "┌─────────── Order Details ────────────┐")?;
+                writeln!(out, "│ Order ID: {:<24} │", self.id)?;
+                writeln!(out, "│ Status: {:<25} │", self.status)?;
+                writeln!(out, "│ Payment: {:<25} │", self.payment.to_string())?;
+                writeln!(out, "└────────────────────────────────────┘")?;
+
+                writeln!(out, "Customer Information:")?;
+                self.user.render(out, Format::Boxed)?;
+
+                writeln!(out, "Products:")?;
+                writeln!(out, "┌─────────────────────────────────────────────┐")?;
+                for OrderLine { product, quantity, price } in lines {
+                    match &price.rule {
+                        Some(rule) => writeln!(out, "│ {}x {} ({} each, {})",
+                            quantity, product.name, self.format_amount(price.unit_price), rule.description)?,
+                        None => writeln!(out, "│ {}x {} ({} each)",
+                            quantity, product.name, self.format_amount(price.unit_price))?,
+                    }
+                }
+                writeln!(out, "└─────────────────────────────────────────────┘")?;
+                writeln!(out, "Total: {}", self.currency.format(self.amount_due()))?;
+                self.render_redemptions(out)?;
+                if let Some(frozen) = &self.frozen_rate {
+                    writeln!(out, "Rate: 1 {} = {:.4} {} (effective {})",
+                        frozen.rate.from, frozen.rate.rate, frozen.rate.to, frozen.rate.effective.format("%Y-%m-%d"))?;
+                }
+
+                if !self.shipments.is_empty() {
+                    writeln!(out, "Shipments:")?;
+                    for shipment in &self.shipments {
+                        writeln!(out, "┌─ #{} {} {} ({})",
+                            shipment.id, shipment.carrier, shipment.tracking_number, shipment.status)?;
+                        for (product_id, quantity) in &shipment.lines {
+                            writeln!(out, "│ {}x {}", quantity, self.product_name(*product_id))?;
+                        }
+                        writeln!(out, "└──────────────────────")?;
+                    }
+                }
+                Ok(())
+            }
+            Format::Json => {
+                let lines: Vec<String> = lines
+                    .map(|OrderLine { product, quantity, price }| format!(
+                        "{{\"product_id\":{},\"name\":{},\"quantity\":{},\"unit_price\":{},\"rule\":{}}}",
+                        product.id,
+                        json_string(&product.name),
+                        quantity,
+                        json_amount(self.to_order_currency(price.unit_price)),
+                        price.rule.as_ref().map_or(String::from("null"), |r| json_string(&r.description)),
+                    ))
+                    .collect();
+                let shipments: Vec<String> = self.shipments
+                    .iter()
+                    .map(|s| {
+                        let contents: Vec<String> = s.lines
+                            .iter()
+                            .map(|(id, qty)| format!("{{\"product_id\":{},\"quantity\":{}}}", id, qty))
+                            .collect();
+                        format!(
+                            "{{\"id\":{},\"carrier\":{},\"tracking_number\":{},\"status\":\"{:?}\",\"lines\":[{}]}}",
+                            s.id,
+                            json_string(&s.carrier),
+                            json_string(&s.tracking_number),
+                            s.status,
+                            contents.join(","),
+                        )
+                    })
+                    .collect();
+                let redemptions: Vec<String> = self.redemptions
+                    .iter()
+                    .map(|r| format!(
+                        "{{\"code\":{},\"amount\":{}}}",
+                        json_string(&r.code),
+                        json_amount(self.to_order_currency(r.amount)),
+                    ))
+                    .collect();
+                writeln!(
+                    out,
+                    "{{\"id\":{},\"status\":\"{:?}\",\"created_at\":{},\"user\":{},\"currency\":{},\"lines\":[{}],\"total\":{},\"redemptions\":[{}],\"balance_due\":{},\"payment\":{},\"shipments\":[{}]}}",
+                    self.id,
+                    self.status,
+                    json_string(&self.created_at.to_rfc3339()),
+                    user_json(&self.user),
+                    json_string(&self.currency.code),
+                    lines.join(","),
+                    json_amount(self.amount_due()),
+                    redemptions.join(","),
+                    json_amount(self.balance_due()),
+                    payment_json(&self.payment),
+                    shipments.join(","),
+                )
+            }
+            Format::KeyValue => {
+                writeln!(
+                    out,
+                    "type=order id={} status={:?} user_id={} currency={} lines={} total={:.2}",
+                    self.id,
+                    self.status,
+                    self.user.id,
+                    self.currency.code,
+                    self.lines().len(),
+                    self.amount_due(),
+                )?;
+                for OrderLine { product, quantity, price } in lines {
+                    writeln!(
+                        out,
+                        "type=order_line order_id={} product_id={} quantity={} unit_price={:.2}",
+                        self.id, product.id, quantity, self.to_order_currency(price.unit_price),
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Stock levels are always listed in product id order
+impl Render for Inventory {
+    fn render(&self, out: &mut dyn Write, format: Format) -> fmt::Result {
+        let levels = self.levels();
+        match format {
+            Format::Plain => {
+                writeln!(out, "Current Inventory:")?;
+                for (product_id, quantity) in levels {
+                    writeln!(out, "Product ID: {}, Quantity: {}", product_id, quantity)?;
+                }
+                Ok(())
+            }
+            Format::Boxed => {
+                writeln!(out, "┌──────────── Inventory ─────────────┐")?;
+                for (product_id, quantity) in levels {
+                    writeln!(out, "│ Product ID: {:<8} Quantity: {:<5} │", product_id, quantity)?;
+                }
+                writeln!(out, "└────────────────────────────────────┘")
+            }
+            Format::Json => {
+                let entries: Vec<String> = levels
+                    .iter()
+                    .map(|(id, qty)| format!("{{\"product_id\":{},\"quantity\":{}}}", id, qty))
+                    .collect();
+                writeln!(out, "{{\"stock\":[{}]}}", entries.join(","))
+            }
+            Format::KeyValue => {
+                for (product_id, quantity) in levels {
+                    writeln!(out, "type=stock product_id={} quantity={}", product_id, quantity)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payment::MockGateway;
+
+    fn order() -> Order {
+        let user = User::new_unchecked(7, "Ada \"A\" L".into(), "ada@example.com".into(), "1 Main St".into());
+        let mut order = Order::new(3, user);
+        order.add_product(Product::new(1, "Lamp".into(), 10.0, String::new()), 2).unwrap();
+        order
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+        assert_eq!(kv_value("plain"), "plain");
+        assert_eq!(kv_value("two words"), "\"two words\"");
+        assert_eq!(kv_value(""), "\"\"");
+    }
+
+    #[test]
+    fn order_json_has_a_structured_payment() {
+        let mut order = order();
+        let json = order.render_to_string(Format::Json);
+        assert!(json.contains("\"payment\":{\"status\":\"Unpaid\",\"amount\":null,\"currency\":null}"), "{}", json);
+
+        let mut gateway = MockGateway::new();
+        order.authorize_payment(&mut gateway).unwrap();
+        order.capture_payment(&mut gateway).unwrap();
+        let json = order.render_to_string(Format::Json);
+        assert!(json.contains("\"payment\":{\"status\":\"Captured\",\"amount\":20.00,\"currency\":\"USD\"}"), "{}", json);
+        assert!(json.contains("\"name\":\"Ada \\\"A\\\" L\""));
+        assert!(json.starts_with("{\"id\":3,\"status\":\"Pending\""));
+    }
+
+    #[test]
+    fn key_value_orders_list_one_record_per_line() {
+        let text = order().render_to_string(Format::KeyValue);
+        assert_eq!(
+            text,
+            "type=order id=3 status=Pending user_id=7 currency=USD lines=1 total=20.00\n\
+             type=order_line order_id=3 product_id=1 quantity=2 unit_price=10.00\n"
+        );
+    }
+
+    #[test]
+    fn inventory_is_listed_in_product_order() {
+        let mut inventory = Inventory::new();
+        inventory.add_stock(2, 5).unwrap();
+        inventory.add_stock(1, 3).unwrap();
+        assert_eq!(
+            inventory.render_to_string(Format::Json),
+            "{\"stock\":[{\"product_id\":1,\"quantity\":3},{\"product_id\":2,\"quantity\":5}]}\n"
+        );
+        assert_eq!(inventory.render_to_string(Format::Plain).lines().nth(1), Some("Product ID: 1, Quantity: 3"));
+    }
+
+    #[test]
+    fn bundles_list_their_components() {
+        let bundle = Product::new(9, "Kit".into(), 30.0, "Desk kit".into()).with_components(vec![(1, 2), (4, 1)]);
+        assert!(bundle.render_to_string(Format::Plain).contains("Bundle of: 2x #1, 1x #4\n"));
+        assert!(bundle.render_to_string(Format::Json).contains("\"components\":[{\"product_id\":1,\"quantity\":2},{\"product_id\":4,\"quantity\":1}]"));
+    }
+}
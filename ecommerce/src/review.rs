@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::order::{Order, OrderStatus};
+use crate::product::Product;
+use crate::user::User;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModerationStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl fmt::Display for ModerationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModerationStatus::Pending => write!(f, "🕒 Pending"),
+            ModerationStatus::Approved => write!(f, "✅ Approved"),
+            ModerationStatus::Rejected => write!(f, "🚫 Rejected"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Review {
+    pub id: u32,
+    pub product_id: u32,
+    pub user_id: u32,
+    pub rating: u8,
+    pub text: String,
+    pub status: ModerationStatus,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReviewError {
+    InvalidRating,
+    EmptyText,
+    NotPurchased,
+    AlreadyReviewed,
+    ReviewNotFound,
+}
+
+impl fmt::Display for ReviewError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReviewError::InvalidRating => write!(f, "Rating must be between 1 and 5"),
+            ReviewError::EmptyText => write!(f, "Review text cannot be empty"),
+            ReviewError::NotPurchased => write!(f, "Only customers with a delivered order can review this product"),
+            ReviewError::AlreadyReviewed => write!(f, "User has already reviewed this product"),
+            ReviewError::ReviewNotFound => write!(f, "Review not found"),
+        }
+    }
+}
+
+impl std::error::Error for ReviewError {}
+
+/// Aggregated ratings for a single product, counting approved reviews only
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RatingStats {
+    pub count: u32,
+    pub average: f64,
+    pub histogram: [u32; 5], // index 0 -> 1 star, index 4 -> 5 stars
+}
+
+impl RatingStats {
+    fn from_ratings<'a>(ratings: impl Iterator<Item = &'a u8>) -> Self {
+        let mut stats = RatingStats::default();
+        let mut sum = 0u32;
+        for &rating in ratings {
+            stats.histogram[(rating - 1) as usize] += 1;
+            stats.count += 1;
+            sum += rating as u32;
+        }
+        if stats.count > 0 {
+            stats.average = sum as f64 / stats.count as f64;
+        }
+        stats
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReviewBoard {
+    reviews: Vec<Review>,
+    next_id: u32,
+}
+
+impl ReviewBoard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Submits a review for moderation; the user must have a delivered order containing the product
+    pub fn submit(
+        &mut self,
+        user: &User,
+        product_id: u32,
+        rating: u8,
+        text: String,
+        orders: &[Order],
+    ) -> Result<u32, ReviewError> {
+        if !(1..=5).contains(&rating) {
+            return Err(ReviewError::InvalidRating);
+        }
+        if text.trim().is_empty() {
+            return Err(ReviewError::EmptyText);
+        }
+        if !Self::has_delivered_purchase(user.id, product_id, orders) {
+            return Err(ReviewError::NotPurchased);
+        }
+        if self.reviews.iter().any(|r| {
+            r.user_id == user.id && r.product_id == product_id && r.status != ModerationStatus::Rejected
+        }) {
+            return Err(ReviewError::AlreadyReviewed);
+        }
+
+        self.next_id += 1;
+        self.reviews.push(Review {
+            id: self.next_id,
+            product_id,
+            user_id: user.id,
+            rating,
+            text: text.trim().to_string(),
+            status: ModerationStatus::Pending,
+        });
+        Ok(self.next_id)
+    }
+
+    fn has_delivered_purchase(user_id: u32, product_id: u32, orders: &[Order]) -> bool {
+        orders.iter().any(|order| {
+            order.user.id == user_id
+                && order.status == OrderStatus::Delivered
+                && order.lines().iter().any(|line| line.product.id == product_id)
+        })
+    }
+
+    pub fn approve(&mut self, review_id: u32) -> Result<(), ReviewError> {
+        self.set_status(review_id, ModerationStatus::Approved)
+    }
+
+    pub fn reject(&mut self, review_id: u32) -> Result<(), ReviewError> {
+        self.set_status(review_id, ModerationStatus::Rejected)
+    }
+
+    fn set_status(&mut self, review_id: u32, status: ModerationStatus) -> Result<(), ReviewError> {
+        let review = self
+            .reviews
+            .iter_mut()
+            .find(|r| r.id == review_id)
+            .ok_or(ReviewError::ReviewNotFound)?;
+        review.status = status;
+        Ok(())
+    }
+
+    /// Reviews waiting for a moderator
+    pub fn pending(&self) -> Vec<&Review> {
+        self.reviews.iter().filter(|r| r.status == ModerationStatus::Pending).collect()
+    }
+
+    /// Approved reviews shown on a product page
+    pub fn published_for(&self, product_id: u32) -> Vec<&Review> {
+        self.reviews
+            .iter()
+            .filter(|r| r.product_id == product_id && r.status == ModerationStatus::Approved)
+            .collect()
+    }
+
+    pub fn stats_for(&self, product_id: u32) -> RatingStats {
+        RatingStats::from_ratings(self.published_for(product_id).into_iter().map(|r| &r.rating))
+    }
+
+    /// Stats for every product with at least one approved review
+    pub fn all_stats(&self) -> HashMap<u32, RatingStats> {
+        let mut ratings: HashMap<u32, Vec<u8>> = HashMap::new();
+        for review in self.reviews.iter().filter(|r| r.status == ModerationStatus::Approved) {
+            ratings.entry(review.product_id).or_default().push(review.rating);
+        }
+        ratings
+            .into_iter()
+            .map(|(product_id, ratings)| (product_id, RatingStats::from_ratings(ratings.iter())))
+            .collect()
+    }
+
+    /// Sorts a catalog listing by average rating, best first; ties go to the product with more reviews
+    pub fn sort_by_rating(&self, products: &mut [Product]) {
+        let stats = self.all_stats();
+        let key = |product: &Product| {
+            stats
+                .get(&product.id)
+                .map(|s| (s.average, s.count))
+                .unwrap_or((0.0, 0))
+        };
+        products.sort_by(|a, b| {
+            let (a_avg, a_count) = key(a);
+            let (b_avg, b_count) = key(b);
+            b_avg.total_cmp(&a_avg).then(b_count.cmp(&a_count)).then(a.id.cmp(&b.id))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: u32) -> User {
+        User::new_unchecked(id, format!("User {}", id), format!("u{}@example.com", id), "Somewhere".into())
+    }
+
+    fn delivered(user: &User, product_ids: &[u32]) -> Order {
+        let mut order = Order::new(user.id, user.clone());
+        for &id in product_ids {
+            order.add_product(Product::new(id, format!("P{}", id), 1.0, String::new()), 1).unwrap();
+        }
+        order.status = OrderStatus::Delivered;
+        order
+    }
+
+    #[test]
+    fn only_delivered_buyers_can_review() {
+        let (alice, bob) = (user(1), user(2));
+        let mut pending = delivered(&bob, &[7]);
+        pending.status = OrderStatus::Processing;
+        let orders = vec![delivered(&alice, &[7]), pending];
+        let mut board = ReviewBoard::new();
+
+        assert_eq!(board.submit(&bob, 7, 5, "Great".into(), &orders), Err(ReviewError::NotPurchased));
+        assert_eq!(board.submit(&alice, 8, 5, "Great".into(), &orders), Err(ReviewError::NotPurchased));
+        assert_eq!(board.submit(&alice, 7, 0, "Great".into(), &orders), Err(ReviewError::InvalidRating));
+        assert_eq!(board.submit(&alice, 7, 6, "Great".into(), &orders), Err(ReviewError::InvalidRating));
+        assert_eq!(board.submit(&alice, 7, 4, "   ".into(), &orders), Err(ReviewError::EmptyText));
+        assert_eq!(board.submit(&alice, 7, 4, " Solid ".into(), &orders), Ok(1));
+        assert_eq!(board.pending()[0].text, "Solid");
+    }
+
+    #[test]
+    fn one_live_review_per_product_but_rejected_ones_can_be_redone() {
+        let alice = user(1);
+        let orders = vec![delivered(&alice, &[7])];
+        let mut board = ReviewBoard::new();
+        let first = board.submit(&alice, 7, 2, "Meh".into(), &orders).unwrap();
+        assert_eq!(board.submit(&alice, 7, 3, "Again".into(), &orders), Err(ReviewError::AlreadyReviewed));
+        board.reject(first).unwrap();
+        assert!(board.submit(&alice, 7, 3, "Again".into(), &orders).is_ok());
+        assert_eq!(board.approve(99), Err(ReviewError::ReviewNotFound));
+    }
+
+    #[test]
+    fn stats_count_approved_reviews_only() {
+        let users: Vec<User> = (1..=4).map(user).collect();
+        let orders: Vec<Order> = users.iter().map(|u| delivered(u, &[7, 8])).collect();
+        let mut board = ReviewBoard::new();
+        for (user, rating) in users.iter().zip([5, 4, 4, 1]) {
+            let id = board.submit(user, 7, rating, "Text".into(), &orders).unwrap();
+            if rating != 1 {
+                board.approve(id).unwrap();
+            }
+        }
+        let id = board.submit(&users[0], 8, 5, "Text".into(), &orders).unwrap();
+        board.approve(id).unwrap();
+
+        let stats = board.stats_for(7);
+        assert_eq!(stats.count, 3);
+        assert!((stats.average - 13.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.histogram, [0, 0, 0, 2, 1]);
+        assert_eq!(board.stats_for(9), RatingStats::default());
+
+        // Product 8 has the better average, product 9 has no reviews
+        let mut products: Vec<Product> =
+            [9, 7, 8].iter().map(|&id| Product::new(id, String::new(), 1.0, String::new())).collect();
+        board.sort_by_rating(&mut products);
+        assert_eq!(products.iter().map(|p| p.id).collect::<Vec<_>>(), vec![8, 7, 9]);
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::inventory::Inventory;
+use crate::product::Product;
+
+/// Common English words that carry no meaning for product search
+const STOP_WORDS: [&str; 24] = [
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "is",
+    "it", "of", "on", "or", "that", "the", "to", "was", "were", "will", "with", "its",
+];
+
+// BM25 tuning constants
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+/// Matches in the name count this many times more than matches in the description
+const NAME_WEIGHT: u32 = 3;
+
+/// Splits text into lowercase alphanumeric tokens, dropping stop words
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .filter(|t| !STOP_WORDS.contains(&t.as_str()))
+        .collect()
+}
+
+/// Optional constraints applied after ranking
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilter {
+    pub min_price: Option<f64>,
+    pub max_price: Option<f64>,
+    pub category: Option<String>,
+    pub in_stock_only: bool,
+}
+
+impl SearchFilter {
+    fn accepts(&self, product: &Product, inventory: Option<&Inventory>) -> bool {
+        self.min_price.is_none_or(|min| product.price() >= min)
+            && self.max_price.is_none_or(|max| product.price() <= max)
+            && self.category.as_ref().is_none_or(|c| product.category.eq_ignore_ascii_case(c))
+            && (!self.in_stock_only || inventory.is_some_and(|inv| inv.available(product) > 0))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub product_id: u32,
+    pub score: f64,
+}
+
+#[derive(Debug)]
+struct IndexedProduct {
+    product: Product,
+    term_counts: HashMap<String, u32>,
+    length: u32,
+}
+
+/// Inverted index over product names and descriptions, ranked with BM25.
+///
+/// Products are indexed one at a time, so the index can be kept current
+/// by calling `upsert` / `remove` whenever the catalog changes.
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    documents: HashMap<u32, IndexedProduct>,
+    postings: BTreeMap<String, BTreeSet<u32>>, // term -> product ids
+    total_length: u64,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_products<'a>(products: impl IntoIterator<Item = &'a Product>) -> Self {
+        let mut index = SearchIndex::new();
+        for product in products {
+            index.upsert(product.clone());
+        }
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Indexes a new product or re-indexes a changed one
+    pub fn upsert(&mut self, product: Product) {
+        self.remove(product.id);
+
+        let mut term_counts: HashMap<String, u32> = HashMap::new();
+        for token in tokenize(&product.name) {
+            *term_counts.entry(token).or_insert(0) += NAME_WEIGHT;
+        }
+        for token in tokenize(&product.description) {
+            *term_counts.entry(token).or_insert(0) += 1;
+        }
+        let length = term_counts.values().sum();
+
+        for term in term_counts.keys() {
+            self.postings.entry(term.clone()).or_default().insert(product.id);
+        }
+        self.total_length += length as u64;
+        self.documents.insert(product.id, IndexedProduct { product, term_counts, length });
+    }
+
+    pub fn remove(&mut self, product_id: u32) -> Option<Product> {
+        let document = self.documents.remove(&product_id)?;
+        for term in document.term_counts.keys() {
+            if let Some(ids) = self.postings.get_mut(term) {
+                ids.remove(&product_id);
+                if ids.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+        self.total_length -= document.length as u64;
+        Some(document.product)
+    }
+
+    pub fn get(&self, product_id: u32) -> Option<&Product> {
+        self.documents.get(&product_id).map(|d| &d.product)
+    }
+
+    fn idf(&self, document_frequency: usize) -> f64 {
+        let n = self.documents.len() as f64;
+        let df = document_frequency as f64;
+        ((n - df + 0.5) / (df + 0.5) + 1.0).ln()
+    }
+
+    /// Terms in the index starting with the prefix
+    fn expand_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a String> {
+        self.postings
+            .range(prefix.to_string()..)
+            .map(|(term, _)| term)
+            .take_while(move |term| term.starts_with(prefix))
+    }
+
+    /// Ranks products against the query, best first.
+    ///
+    /// The last query token is treated as a prefix so partially typed words
+    /// still match. `inventory` is only needed for the in-stock filter.
+    pub fn search(&self, query: &str, filter: &SearchFilter, inventory: Option<&Inventory>) -> Vec<SearchHit> {
+        let tokens = tokenize(query);
+        let average_length = if self.documents.is_empty() {
+            1.0
+        } else {
+            self.total_length as f64 / self.documents.len() as f64
+        };
+
+        let mut scores: HashMap<u32, f64> = HashMap::new();
+        for (position, token) in tokens.iter().enumerate() {
+            let terms: Vec<&String> = if position + 1 == tokens.len() {
+                self.expand_prefix(token).collect()
+            } else {
+                self.postings.get_key_value(token).map(|(term, _)| term).into_iter().collect()
+            };
+
+            for term in terms {
+                let ids = &self.postings[term];
+                let idf = self.idf(ids.len());
+                for id in ids {
+                    let document = &self.documents[id];
+                    let tf = document.term_counts[term] as f64;
+                    let norm = K1 * (1.0 - B + B * document.length as f64 / average_length);
+                    *scores.entry(*id).or_insert(0.0) += idf * tf * (K1 + 1.0) / (tf + norm);
+                }
+            }
+        }
+
+        let mut hits: Vec<SearchHit> = scores
+            .into_iter()
+            .filter(|(id, _)| filter.accepts(&self.documents[id].product, inventory))
+            .map(|(product_id, score)| SearchHit { product_id, score })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.product_id.cmp(&b.product_id)));
+        hits
+    }
+
+    /// Suggests up to `limit` product names for a partially typed query
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Vec<&str> {
+        self.search(prefix, &SearchFilter::default(), None)
+            .into_iter()
+            .take(limit)
+            .map(|hit| self.documents[&hit.product_id].product.name.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(id: u32, name: &str, description: &str, price: f64, category: &str) -> Product {
+        Product::new(id, name.into(), price, description.into()).with_category(category)
+    }
+
+    fn index() -> SearchIndex {
+        SearchIndex::from_products(&[
+            product(1, "Gaming Laptop", "A fast laptop for the road", 1200.0, "computers"),
+            product(2, "Laptop Sleeve", "Padded sleeve that fits a laptop", 30.0, "accessories"),
+            product(3, "Wireless Mouse", "Works with any laptop", 25.0, "accessories"),
+            product(4, "Keyboard", "Mechanical keys", 80.0, "accessories"),
+        ])
+    }
+
+    fn ids(hits: &[SearchHit]) -> Vec<u32> {
+        hits.iter().map(|hit| hit.product_id).collect()
+    }
+
+    #[test]
+    fn tokenize_lowercases_and_drops_stop_words() {
+        assert_eq!(tokenize("The USB-C Hub, for ITS ports"), vec!["usb", "c", "hub", "ports"]);
+        assert!(tokenize("  the and of ").is_empty());
+    }
+
+    #[test]
+    fn name_matches_outrank_description_matches() {
+        let hits = index().search("laptop", &SearchFilter::default(), None);
+        assert_eq!(ids(&hits)[2], 3);
+        assert!(hits.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        assert!(index().search("", &SearchFilter::default(), None).is_empty());
+    }
+
+    #[test]
+    fn last_token_matches_as_a_prefix() {
+        let index = index();
+        assert_eq!(ids(&index.search("wireless mou", &SearchFilter::default(), None)), vec![3]);
+        assert!(index.search("mou wireless", &SearchFilter::default(), None).iter().all(|hit| hit.product_id == 3));
+        assert_eq!(index.suggest("keyb", 5), vec!["Keyboard"]);
+    }
+
+    #[test]
+    fn filters_apply_after_ranking() {
+        let index = index();
+        let filter = SearchFilter { max_price: Some(100.0), category: Some("ACCESSORIES".into()), ..SearchFilter::default() };
+        assert_eq!(ids(&index.search("laptop", &filter, None)).len(), 2);
+
+        let mut inventory = Inventory::new();
+        inventory.add_stock(3, 1).unwrap();
+        let in_stock = SearchFilter { in_stock_only: true, ..SearchFilter::default() };
+        assert_eq!(ids(&index.search("laptop", &in_stock, Some(&inventory))), vec![3]);
+        assert!(index.search("laptop", &in_stock, None).is_empty());
+    }
+
+    #[test]
+    fn reindexing_replaces_old_terms() {
+        let mut index = index();
+        index.upsert(product(4, "Trackpad", "Glass surface", 90.0, "accessories"));
+        assert!(index.search("keyboard", &SearchFilter::default(), None).is_empty());
+        assert_eq!(ids(&index.search("trackpad", &SearchFilter::default(), None)), vec![4]);
+        assert_eq!(index.remove(4).map(|p| p.id), Some(4));
+        assert!(index.remove(4).is_none());
+        assert_eq!(index.len(), 3);
+    }
+}
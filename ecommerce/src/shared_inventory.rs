@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::inventory::InventoryError;
+
+/// Inventory handle that can be cloned across worker threads.
+///
+/// Each product has its own atomic counter, so threads buying different
+/// products never contend, and a decrement only succeeds if the stock it
+/// observed is still there.
+#[derive(Debug, Clone, Default)]
+pub struct SharedInventory {
+    stock: Arc<RwLock<HashMap<u32, AtomicU32>>>, // product_id -> quantity
+}
+
+impl SharedInventory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a handle from existing product_id -> quantity levels
+    pub fn from_levels(levels: HashMap<u32, u32>) -> Self {
+        let stock = levels
+            .into_iter()
+            .map(|(product_id, quantity)| (product_id, AtomicU32::new(quantity)))
+            .collect();
+        SharedInventory {
+            stock: Arc::new(RwLock::new(stock)),
+        }
+    }
+
+    /// Adds stock; fails without changing anything if the level would overflow
+    pub fn add_stock(&self, product_id: u32, quantity: u32) -> Result<(), InventoryError> {
+        let add = |counter: &AtomicU32| {
+            counter
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| current.checked_add(quantity))
+                .map(|_| ())
+                .map_err(|_| InventoryError::StockOverflow { product_id })
+        };
+        // Fast path: the product already has a counter
+        if let Some(counter) = self.stock.read().unwrap().get(&product_id) {
+            return add(counter);
+        }
+        add(self.stock.write().unwrap().entry(product_id).or_insert_with(|| AtomicU32::new(0)))
+    }
+
+    pub fn remove_stock(&self, product_id: u32, quantity: u32) -> Result<(), InventoryError> {
+        if quantity == 0 {
+            return Ok(());
+        }
+        let stock = self.stock.read().unwrap();
+        let insufficient = |available| InventoryError::InsufficientStock { product_id, requested: quantity, available };
+        match stock.get(&product_id) {
+            Some(counter) => counter
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| current.checked_sub(quantity))
+                .map(|_| ())
+                .map_err(insufficient),
+            None => Err(insufficient(0)),
+        }
+    }
+
+    /// Removes every line or none of them; zero-quantity lines are skipped.
+    ///
+    /// Holds the write lock while every line is checked and taken, so no other
+    /// thread can see or change stock part-way through.
+    pub fn remove_stock_all(&self, lines: &[(u32, u32)]) -> Result<(), InventoryError> {
+        // Merge repeated products so each is checked against its full quantity
+        let mut needed: Vec<(u32, u64)> = Vec::new();
+        for &(product_id, quantity) in lines.iter().filter(|(_, quantity)| *quantity > 0) {
+            match needed.iter_mut().find(|(id, _)| *id == product_id) {
+                Some((_, total)) => *total += quantity as u64,
+                None => needed.push((product_id, quantity as u64)),
+            }
+        }
+
+        let stock = self.stock.write().unwrap();
+        for &(product_id, quantity) in &needed {
+            let available = stock.get(&product_id).map_or(0, |counter| counter.load(Ordering::Acquire));
+            if quantity > available as u64 {
+                return Err(InventoryError::InsufficientStock {
+                    product_id,
+                    requested: u32::try_from(quantity).unwrap_or(u32::MAX),
+                    available,
+                });
+            }
+        }
+        for (product_id, quantity) in needed {
+            stock[&product_id].fetch_sub(quantity as u32, Ordering::AcqRel);
+        }
+        Ok(())
+    }
+
+    pub fn check_stock(&self, product_id: u32) -> u32 {
+        self.stock
+            .read()
+            .unwrap()
+            .get(&product_id)
+            .map(|counter| counter.load(Ordering::Acquire))
+            .unwrap_or(0)
+    }
+
+    /// Copies the current levels out of the shared handle
+    pub fn snapshot(&self) -> HashMap<u32, u32> {
+        self.stock
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(product_id, counter)| (*product_id, counter.load(Ordering::Acquire)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn remove_stock_all_takes_nothing_when_any_line_is_short() {
+        let shared = SharedInventory::from_levels(HashMap::from([(1, 5), (2, 1)]));
+        let err = shared.remove_stock_all(&[(1, 3), (2, 2)]).unwrap_err();
+        assert_eq!(err, InventoryError::InsufficientStock { product_id: 2, requested: 2, available: 1 });
+        assert_eq!(shared.check_stock(1), 5);
+        assert_eq!(shared.check_stock(2), 1);
+    }
+
+    #[test]
+    fn remove_stock_all_checks_repeated_products_together() {
+        let shared = SharedInventory::from_levels(HashMap::from([(1, 5)]));
+        assert!(shared.remove_stock_all(&[(1, 3), (1, 3)]).is_err());
+        assert_eq!(shared.check_stock(1), 5);
+        shared.remove_stock_all(&[(1, 3), (1, 2)]).unwrap();
+        assert_eq!(shared.check_stock(1), 0);
+    }
+
+    #[test]
+    fn add_stock_refuses_to_overflow() {
+        let shared = SharedInventory::from_levels(HashMap::from([(1, u32::MAX - 1)]));
+        assert_eq!(shared.add_stock(1, 2), Err(InventoryError::StockOverflow { product_id: 1 }));
+        assert_eq!(shared.check_stock(1), u32::MAX - 1);
+        shared.add_stock(2, 3).unwrap();
+        assert_eq!(shared.check_stock(2), 3);
+    }
+
+    #[test]
+    fn removing_nothing_is_a_no_op() {
+        let shared = SharedInventory::from_levels(HashMap::from([(1, 2)]));
+        shared.remove_stock(1, 0).unwrap();
+        shared.remove_stock(9, 0).unwrap();
+        shared.remove_stock_all(&[(1, 0), (9, 0), (1, 2)]).unwrap();
+        assert_eq!(shared.snapshot(), HashMap::from([(1, 0)]));
+    }
+
+    #[test]
+    fn concurrent_buyers_never_oversell() {
+        let shared = SharedInventory::from_levels(HashMap::from([(1, 100), (2, 100)]));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || (0..50).filter(|_| shared.remove_stock_all(&[(1, 1), (2, 2)]).is_ok()).count())
+            })
+            .collect();
+        let sold: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(sold, 50);
+        assert_eq!(shared.check_stock(1), 50);
+        assert_eq!(shared.check_stock(2), 0);
+    }
+}
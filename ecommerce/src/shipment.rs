@@ -0,0 +1,38 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShipmentStatus {
+    InTransit,
+    Delivered,
+}
+
+impl fmt::Display for ShipmentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShipmentStatus::InTransit => write!(f, "🚚 In transit"),
+            ShipmentStatus::Delivered => write!(f, "✅ Delivered"),
+        }
+    }
+}
+
+/// A parcel carrying some or all of an order's lines
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Shipment {
+    pub id: u32,
+    pub carrier: String,
+    pub tracking_number: String,
+    pub lines: Vec<(u32, u32)>, // (product_id, quantity)
+    pub status: ShipmentStatus,
+}
+
+impl Shipment {
+    pub fn quantity_of(&self, product_id: u32) -> u32 {
+        self.lines
+            .iter()
+            .filter(|(id, _)| *id == product_id)
+            .map(|(_, quantity)| quantity)
+            .sum()
+    }
+}
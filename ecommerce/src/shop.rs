@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+
+use crate::catalog::Catalog;
+use crate::error::Result;
+use crate::fraud::FraudScreen;
+use crate::inventory::{Inventory, Reservation, SYSTEM_ACTOR};
+use crate::order::{Order, OrderError, OrderStatus};
+use crate::order_query::{OrderPage, OrderQuery};
+use crate::payment::PaymentGateway;
+use crate::product::Product;
+use crate::storage::{Storage, StorageError};
+use crate::subscription::{RunReport, SubscriptionBook};
+use crate::user::User;
+
+/// Catalog, customer and order operations on top of any storage backend.
+///
+/// Every operation saves what it changed before returning, so the backend
+/// always holds the latest state.
+pub struct Shop<S: Storage> {
+    storage: S,
+    inventory: Inventory,
+    fraud_screen: Option<FraudScreen>,
+}
+
+impl<S: Storage> Shop<S> {
+    /// Opens a shop over existing storage, rebuilding stock from its ledger
+    pub fn open(storage: S) -> Result<Self> {
+        let inventory = storage.load_inventory()?;
+        Ok(Shop { storage, inventory, fraud_screen: None })
+    }
+
+    /// Screens every new order; risky ones wait under review without reserving stock
+    pub fn with_fraud_screen(mut self, screen: FraudScreen) -> Self {
+        self.fraud_screen = Some(screen);
+        self
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_storage(self) -> S {
+        self.storage
+    }
+
+    pub fn inventory(&self) -> &Inventory {
+        &self.inventory
+    }
+
+    /// Adds or replaces a product and receives its opening stock
+    pub fn add_product(&mut self, product: Product, initial_stock: u32) -> Result<()> {
+        let product_id = product.id;
+        self.storage.put_product(&product)?;
+        self.inventory.add_stock(product_id, initial_stock)?;
+        self.release_ready_orders()?;
+        self.storage.save_inventory(&self.inventory)?;
+        Ok(())
+    }
+
+    pub fn register_user(&mut self, user: User) -> Result<()> {
+        self.storage.put_user(&user)?;
+        Ok(())
+    }
+
+    pub fn receive_stock(&mut self, product_id: u32, quantity: u32) -> Result<()> {
+        if self.storage.product(product_id).is_none() {
+            return Err(StorageError::NotFound { kind: "product", id: product_id }.into());
+        }
+        self.inventory.add_stock(product_id, quantity)?;
+        self.release_ready_orders()?;
+        self.storage.save_inventory(&self.inventory)?;
+        Ok(())
+    }
+
+    /// Moves backordered orders whose stock has all arrived on to processing
+    fn release_ready_orders(&mut self) -> Result<()> {
+        for order_id in self.inventory.take_ready_orders() {
+            let mut order = self.storage.order(order_id).ok_or(StorageError::NotFound { kind: "order", id: order_id })?;
+            order.update_status(OrderStatus::Processing)?;
+            self.storage.put_order(&order)?;
+        }
+        Ok(())
+    }
+
+    /// Places an order for `(product_id, quantity)` lines and reserves its stock
+    pub fn place_order(&mut self, user_id: u32, lines: &[(u32, u32)]) -> Result<Order> {
+        let user = self.storage.user(user_id).ok_or(StorageError::NotFound { kind: "user", id: user_id })?;
+        let order_id = self.storage.next_order_id();
+
+        let mut order = Order::new(order_id, user);
+        for &(product_id, quantity) in lines {
+            let product = self.storage.product(product_id).ok_or(OrderError::ProductNotFound)?;
+            order.add_product(product, quantity)?;
+        }
+        order.place()?;
+
+        if let Some(screen) = &self.fraud_screen {
+            if screen.check(&mut order, &self.storage.orders(), Utc::now())?.needs_review {
+                self.storage.put_order(&order)?;
+                return Ok(order);
+            }
+        }
+        self.fulfil(order)
+    }
+
+    /// Releases an order held for review, reserving its stock as if it had just been placed
+    pub fn approve_order(&mut self, order_id: u32) -> Result<Order> {
+        let order = self.storage.order(order_id).ok_or(StorageError::NotFound { kind: "order", id: order_id })?;
+        if order.status != OrderStatus::UnderReview {
+            return Err(OrderError::InvalidStatus.into());
+        }
+        self.fulfil(order)
+    }
+
+    /// Finds stored orders matching a query, one page at a time
+    pub fn find_orders(&self, query: &OrderQuery) -> OrderPage {
+        query.run(&self.storage.orders())
+    }
+
+    /// Reserves stock for an order and moves it on to processing or the backorder queue
+    fn fulfil(&mut self, mut order: Order) -> Result<Order> {
+        let status = match self.inventory.reserve_order(&order)? {
+            Reservation::Reserved => OrderStatus::Processing,
+            Reservation::Waiting => OrderStatus::Backordered,
+        };
+        order.update_status(status)?;
+        self.storage.save_inventory(&self.inventory)?;
+        self.storage.put_order(&order)?;
+        Ok(order)
+    }
+
+    /// Cancels an order and puts its reserved stock back on the shelf
+    pub fn cancel_order(&mut self, order_id: u32) -> Result<Order> {
+        let mut order = self.storage.order(order_id).ok_or(StorageError::NotFound { kind: "order", id: order_id })?;
+        let previous = order.status.clone();
+        order.update_status(OrderStatus::Cancelled)?;
+
+        match previous {
+            OrderStatus::Backordered => {
+                self.inventory.cancel_backorder(order_id);
+            }
+            OrderStatus::Processing => {
+                self.inventory.return_order(&order, SYSTEM_ACTOR)?;
+            }
+            _ => {}
+        }
+        self.storage.save_inventory(&self.inventory)?;
+        self.storage.put_order(&order)?;
+        Ok(order)
+    }
+
+    /// Places every subscription order due at `now` at the stored product prices, numbering them after the stored orders
+    pub fn run_subscriptions(
+        &mut self,
+        book: &mut SubscriptionBook,
+        now: DateTime<Utc>,
+        gateway: &mut dyn PaymentGateway,
+    ) -> Result<RunReport> {
+        let mut catalog = Catalog::new();
+        for product in self.storage.products() {
+            catalog.upsert(product);
+        }
+        let report = book.run_due(now, self.storage.next_order_id(), &catalog, &mut self.inventory, gateway);
+        self.storage.save_inventory(&self.inventory)?;
+        for order in &report.created {
+            self.storage.put_order(order)?;
+        }
+        Ok(report)
+    }
+}
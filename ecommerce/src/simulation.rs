@@ -0,0 +1,591 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::inventory::{Inventory, Reservation};
+use crate::order::{Order, OrderStatus};
+use crate::payment::{MockGateway, MockResponse, PaymentStatus};
+use crate::product::{Product, StockPolicy};
+use crate::user::User;
+
+/// Small xorshift generator; the same seed always produces the same sequence
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Spread nearby seeds apart and keep the state non-zero
+        Rng(seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform value in `0..bound`
+    pub fn below(&mut self, bound: u32) -> u32 {
+        (self.next_u64() % bound as u64) as u32
+    }
+
+    /// True with probability `percent` / 100
+    pub fn chance(&mut self, percent: u32) -> bool {
+        self.below(100) < percent
+    }
+}
+
+const PRODUCTS: u32 = 8;
+/// Sold as one unit of product 1 plus two of product 2
+const BUNDLE: u32 = 7;
+const USERS: u32 = 5;
+
+/// One step of a simulated shopping session.
+///
+/// Actions only refer to ids, so any subsequence of a trace can still be
+/// replayed; steps that no longer make sense are simply rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    CreateOrder { order_id: u32, user_id: u32 },
+    AddLine { order_id: u32, product_id: u32, quantity: u32 },
+    RemoveLine { order_id: u32, product_id: u32 },
+    Checkout { order_id: u32, decline: bool },
+    Cancel { order_id: u32 },
+    Ship { order_id: u32, partial: bool },
+    Deliver { order_id: u32 },
+    Restock { product_id: u32, quantity: u32 },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::CreateOrder { order_id, user_id } => write!(f, "create order #{} for user {}", order_id, user_id),
+            Action::AddLine { order_id, product_id, quantity } => {
+                write!(f, "add {}x product {} to order #{}", quantity, product_id, order_id)
+            }
+            Action::RemoveLine { order_id, product_id } => write!(f, "remove product {} from order #{}", product_id, order_id),
+            Action::Checkout { order_id, decline } => {
+                write!(f, "check out order #{}{}", order_id, if *decline { " (card declined)" } else { "" })
+            }
+            Action::Cancel { order_id } => write!(f, "cancel order #{}", order_id),
+            Action::Ship { order_id, partial } => {
+                write!(f, "ship {} of order #{}", if *partial { "first line" } else { "everything" }, order_id)
+            }
+            Action::Deliver { order_id } => write!(f, "deliver shipments of order #{}", order_id),
+            Action::Restock { product_id, quantity } => write!(f, "restock {}x product {}", quantity, product_id),
+        }
+    }
+}
+
+impl Action {
+    /// The order this action works on, if any
+    pub fn order_id(&self) -> Option<u32> {
+        match *self {
+            Action::CreateOrder { order_id, .. }
+            | Action::AddLine { order_id, .. }
+            | Action::RemoveLine { order_id, .. }
+            | Action::Checkout { order_id, .. }
+            | Action::Cancel { order_id }
+            | Action::Ship { order_id, .. }
+            | Action::Deliver { order_id } => Some(order_id),
+            Action::Restock { .. } => None,
+        }
+    }
+}
+
+/// Generates `sessions` randomized shopping sessions from a seed
+pub fn generate(seed: u64, sessions: u32) -> Vec<Action> {
+    let mut rng = Rng::new(seed);
+    let mut actions = Vec::new();
+
+    for session in 1..=sessions {
+        let order_id = session;
+        actions.push(Action::CreateOrder { order_id, user_id: 1 + rng.below(USERS) });
+        for _ in 0..1 + rng.below(4) {
+            actions.push(Action::AddLine { order_id, product_id: 1 + rng.below(PRODUCTS), quantity: 1 + rng.below(5) });
+        }
+        if rng.chance(20) {
+            actions.push(Action::RemoveLine { order_id, product_id: 1 + rng.below(PRODUCTS) });
+        }
+        if rng.chance(85) {
+            actions.push(Action::Checkout { order_id, decline: rng.chance(10) });
+        }
+
+        // Later steps may touch this order or any earlier one
+        for _ in 0..rng.below(4) {
+            let target = 1 + rng.below(session);
+            actions.push(match rng.below(10) {
+                0..=2 => Action::Ship { order_id: target, partial: rng.chance(30) },
+                3..=4 => Action::Deliver { order_id: target },
+                5 => Action::Cancel { order_id: target },
+                6 => Action::RemoveLine { order_id: target, product_id: 1 + rng.below(PRODUCTS) },
+                7 => Action::AddLine { order_id: target, product_id: 1 + rng.below(PRODUCTS), quantity: 1 + rng.below(3) },
+                _ => Action::Restock { product_id: 1 + rng.below(PRODUCTS), quantity: 1 + rng.below(20) },
+            });
+        }
+    }
+    actions
+}
+
+/// An invariant broken after a step
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    NegativeStock { product_id: u32, level: i64 },
+    LedgerMismatch { product_id: u32, level: u32, derived: i64 },
+    StockNotConserved { product_id: u32, expected: i64, actual: i64 },
+    TotalMismatch { order_id: u32, total: f64, lines: f64 },
+    IllegalTransition { order_id: u32, from: OrderStatus, to: OrderStatus },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::NegativeStock { product_id, level } => {
+                write!(f, "stock of product {} went negative ({})", product_id, level)
+            }
+            Violation::LedgerMismatch { product_id, level, derived } => {
+                write!(f, "product {} has level {} but its ledger sums to {}", product_id, level, derived)
+            }
+            Violation::StockNotConserved { product_id, expected, actual } => write!(
+                f, "product {} is not conserved: stock plus reserved units is {}, expected {}",
+                product_id, actual, expected
+            ),
+            Violation::TotalMismatch { order_id, total, lines } => {
+                write!(f, "order #{} total {:.2} does not match its lines ({:.2})", order_id, total, lines)
+            }
+            Violation::IllegalTransition { order_id, from, to } => {
+                write!(f, "order #{} moved from {:?} to {:?}", order_id, from, to)
+            }
+        }
+    }
+}
+
+/// How far an order has got; `None` once it is cancelled
+fn stage(status: &OrderStatus) -> Option<u8> {
+    match status {
+        OrderStatus::Pending => Some(0),
+        OrderStatus::UnderReview => Some(1),
+        OrderStatus::Backordered => Some(2),
+        OrderStatus::Processing => Some(PROCESSING),
+        OrderStatus::PartiallyShipped => Some(FIRST_SHIPPED),
+        OrderStatus::Shipped => Some(5),
+        OrderStatus::Delivered => Some(6),
+        OrderStatus::Cancelled => None,
+    }
+}
+
+const PROCESSING: u8 = 3;
+const FIRST_SHIPPED: u8 = 4;
+
+/// Whether a status change keeps the order lifecycle's rules.
+///
+/// Stated as rules rather than a transition table so the simulation checks
+/// `Order::update_status` instead of repeating it: orders only move forward,
+/// cancelling is only possible before anything ships, shipping needs stock
+/// in hand (Processing) and only a fully shipped order can be delivered.
+fn is_legal(from: &OrderStatus, to: &OrderStatus) -> bool {
+    match (stage(from), stage(to)) {
+        (None, _) => false,
+        (Some(from_stage), None) => from_stage < FIRST_SHIPPED,
+        (Some(from_stage), Some(to_stage)) => {
+            to_stage > from_stage
+                && (to_stage < FIRST_SHIPPED || from_stage >= PROCESSING)
+                && (*to != OrderStatus::Delivered || *from == OrderStatus::Shipped)
+        }
+    }
+}
+
+/// Statuses in which an order's lines have left the shelf
+fn holds_stock(status: &OrderStatus) -> bool {
+    matches!(
+        status,
+        OrderStatus::Processing | OrderStatus::PartiallyShipped | OrderStatus::Shipped | OrderStatus::Delivered
+    )
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub steps: usize,
+    pub rejected: usize,
+    pub orders_by_status: BTreeMap<String, u32>,
+    pub stock_left: u64,
+}
+
+/// The shop under test plus the bookkeeping needed to check it
+pub struct Simulation {
+    catalog: HashMap<u32, Product>,
+    users: HashMap<u32, User>,
+    inventory: Inventory,
+    orders: BTreeMap<u32, Order>,
+    gateway: MockGateway,
+    received: HashMap<u32, i64>, // product_id -> units ever put on the shelf
+    ledger: HashMap<u32, i64>,   // product_id -> running sum of checked movements
+    ledger_seen: usize,
+    touched: Vec<(u32, OrderStatus)>, // orders changed since the last check, with their prior status
+    contributions: HashMap<u32, Vec<(u32, i64)>>, // order_id -> units it commits
+    committed: HashMap<u32, i64>, // product_id -> units committed by all orders
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulation {
+    /// Seeds a fixed catalog, customers and opening stock
+    pub fn new() -> Self {
+        let mut sim = Simulation {
+            catalog: HashMap::new(),
+            users: HashMap::new(),
+            inventory: Inventory::new(),
+            orders: BTreeMap::new(),
+            gateway: MockGateway::new(),
+            received: HashMap::new(),
+            ledger: HashMap::new(),
+            ledger_seen: 0,
+            touched: Vec::new(),
+            contributions: HashMap::new(),
+            committed: HashMap::new(),
+        };
+
+        for id in 1..=PRODUCTS {
+            let policy = if id % 4 == 0 { StockPolicy::Backorder } else { StockPolicy::InStockOnly };
+            let mut product = Product::new(id, format!("Product {}", id), 5.0 + id as f64 * 7.25, String::from("Simulated"))
+                .with_stock_policy(policy);
+            if id == BUNDLE {
+                product = product.with_components(vec![(1, 1), (2, 2)]);
+            } else {
+                sim.restock(id, 10 + id * 3);
+            }
+            sim.catalog.insert(id, product);
+        }
+        for id in 1..=USERS {
+            let user = User::new(id, format!("Shopper {}", id), format!("shopper{}@example.com", id), String::from("1 Test Lane"))
+                .expect("Simulated users are valid");
+            sim.users.insert(id, user);
+        }
+        sim
+    }
+
+    fn restock(&mut self, product_id: u32, quantity: u32) {
+        self.inventory.add_stock(product_id, quantity).expect("Simulated restocks stay far below the stock limit");
+        *self.received.entry(product_id).or_insert(0) += quantity as i64;
+        self.release_ready_orders();
+    }
+
+    /// Moves backordered orders whose stock has all been allocated on to processing
+    fn release_ready_orders(&mut self) {
+        for order_id in self.inventory.take_ready_orders() {
+            if let Some(order) = self.orders.get_mut(&order_id) {
+                self.touched.push((order_id, order.status.clone()));
+                let _ = order.update_status(OrderStatus::Processing);
+            }
+        }
+    }
+
+    /// Applies one action; `false` means the shop rejected it, which is not a failure
+    pub fn apply(&mut self, action: &Action) -> bool {
+        let order_id = action.order_id();
+        let before = order_id.and_then(|id| self.orders.get(&id)).map(|o| o.status.clone());
+        let accepted = self.perform(action);
+        if let Some(id) = order_id.filter(|id| self.orders.contains_key(id)) {
+            let from = before.unwrap_or(OrderStatus::Pending);
+            self.touched.push((id, from));
+        }
+        accepted
+    }
+
+    fn perform(&mut self, action: &Action) -> bool {
+        match *action {
+            Action::CreateOrder { order_id, user_id } => {
+                if self.orders.contains_key(&order_id) {
+                    return false;
+                }
+                let Some(user) = self.users.get(&user_id) else { return false };
+                self.orders.insert(order_id, Order::new(order_id, user.clone()));
+                true
+            }
+            Action::AddLine { order_id, product_id, quantity } => {
+                let (Some(order), Some(product)) = (self.orders.get_mut(&order_id), self.catalog.get(&product_id)) else {
+                    return false;
+                };
+                order.add_product(product.clone(), quantity).is_ok()
+            }
+            Action::RemoveLine { order_id, product_id } => self
+                .orders
+                .get_mut(&order_id)
+                .is_some_and(|order| order.remove_product(product_id).is_ok()),
+            Action::Checkout { order_id, decline } => self.checkout(order_id, decline),
+            Action::Cancel { order_id } => self.cancel(order_id),
+            Action::Ship { order_id, partial } => {
+                let Some(order) = self.orders.get_mut(&order_id) else { return false };
+                let unshipped: BTreeMap<u32, u32> = order
+                    .lines()
+                    .iter()
+                    .map(|line| (line.product.id, order.unshipped_quantity(line.product.id)))
+                    .filter(|(_, quantity)| *quantity > 0)
+                    .collect();
+                let mut lines: Vec<(u32, u32)> = unshipped.into_iter().collect();
+                if partial {
+                    lines.truncate(1);
+                }
+                let tracking = format!("SIM{:08}", order_id);
+                order.add_shipment("SimPost", &tracking, lines).is_ok()
+            }
+            Action::Deliver { order_id } => {
+                let Some(order) = self.orders.get_mut(&order_id) else { return false };
+                let ids: Vec<u32> = order.shipments.iter().map(|s| s.id).collect();
+                !ids.is_empty() && ids.into_iter().all(|id| order.deliver_shipment(id).is_ok())
+            }
+            Action::Restock { product_id, quantity } => {
+                if self.catalog.get(&product_id).is_none_or(Product::is_bundle) {
+                    return false;
+                }
+                self.restock(product_id, quantity);
+                true
+            }
+        }
+    }
+
+    fn checkout(&mut self, order_id: u32, decline: bool) -> bool {
+        let Some(order) = self.orders.get_mut(&order_id) else { return false };
+        if order.status != OrderStatus::Pending {
+            return false;
+        }
+        if decline {
+            self.gateway.script(MockResponse::Decline(String::from("simulated decline")));
+        }
+        if order.authorize_payment(&mut self.gateway).is_err() {
+            return false;
+        }
+
+        let status = match self.inventory.reserve_order(order) {
+            Ok(Reservation::Reserved) => OrderStatus::Processing,
+            Ok(Reservation::Waiting) => OrderStatus::Backordered,
+            Err(_) => {
+                let _ = order.void_payment(&mut self.gateway);
+                return false;
+            }
+        };
+        order.capture_payment(&mut self.gateway).expect("Mock gateway approves unscripted captures");
+        order.update_status(status).is_ok()
+    }
+
+    fn cancel(&mut self, order_id: u32) -> bool {
+        let Some(order) = self.orders.get_mut(&order_id) else { return false };
+        let previous = order.status.clone();
+        if order.update_status(OrderStatus::Cancelled).is_err() {
+            return false;
+        }
+
+        // Put back whatever the order had taken off the shelf
+        if previous == OrderStatus::Backordered {
+            self.inventory.cancel_backorder(order_id);
+        } else if holds_stock(&previous) {
+            self.inventory.return_order(order, "simulation").expect("Returned units were taken by this order");
+        }
+        if matches!(order.payment, PaymentStatus::Captured { .. }) {
+            let _ = order.refund_payment(&mut self.gateway);
+        }
+        // Returned units may complete other customers' backorders
+        self.release_ready_orders();
+        true
+    }
+
+    /// Units an order has committed, counting waiting lines in full
+    fn committed(order: &Order) -> Vec<(u32, i64)> {
+        if !holds_stock(&order.status) && order.status != OrderStatus::Backordered {
+            return Vec::new();
+        }
+        order.lines()
+            .iter()
+            .flat_map(|line| line.product.stock_lines(line.quantity).expect("Simulated quantities are small"))
+            .map(|(product_id, quantity)| (product_id, quantity as i64))
+            .collect()
+    }
+
+    /// Units each product currently has off the shelf on behalf of orders
+    fn reserved(&mut self) -> HashMap<u32, i64> {
+        // Only touched orders can have changed what they commit, so the totals are kept incrementally
+        for (order_id, _) in &self.touched {
+            for (product_id, quantity) in self.contributions.remove(order_id).unwrap_or_default() {
+                *self.committed.entry(product_id).or_insert(0) -= quantity;
+            }
+            let contribution = Self::committed(&self.orders[order_id]);
+            for &(product_id, quantity) in &contribution {
+                *self.committed.entry(product_id).or_insert(0) += quantity;
+            }
+            self.contributions.insert(*order_id, contribution);
+        }
+
+        let mut reserved = self.committed.clone();
+        for line in self.inventory.backorders() {
+            if self.orders.get(&line.order_id).is_some_and(|o| o.status == OrderStatus::Backordered) {
+                *reserved.entry(line.product_id).or_insert(0) -= line.outstanding as i64;
+            }
+        }
+        reserved
+    }
+
+    /// Checks every invariant after a step
+    pub fn check(&mut self) -> Result<(), Violation> {
+        // Replay only the movements recorded since the last check
+        for movement in &self.inventory.movements()[self.ledger_seen..] {
+            let running = self.ledger.entry(movement.product_id).or_insert(0);
+            *running += movement.delta;
+            if *running < 0 {
+                return Err(Violation::NegativeStock { product_id: movement.product_id, level: *running });
+            }
+        }
+        self.ledger_seen = self.inventory.movements().len();
+
+        let reserved = self.reserved();
+        for &product_id in self.catalog.keys() {
+            let level = self.inventory.check_stock(product_id);
+            let derived = self.ledger.get(&product_id).copied().unwrap_or(0);
+            if derived != level as i64 {
+                return Err(Violation::LedgerMismatch { product_id, level, derived });
+            }
+
+            let actual = level as i64 + reserved.get(&product_id).copied().unwrap_or(0);
+            let expected = self.received.get(&product_id).copied().unwrap_or(0);
+            if actual != expected {
+                return Err(Violation::StockNotConserved { product_id, expected, actual });
+            }
+        }
+
+        // Only orders touched by the step can have changed
+        for (order_id, from) in std::mem::take(&mut self.touched) {
+            let order = &self.orders[&order_id];
+            let lines: f64 = order
+                .lines()
+                .iter()
+                .map(|line| line.price.unit_price * line.quantity as f64)
+                .sum();
+            if (order.total - lines).abs() > 1e-6 * lines.max(1.0) {
+                return Err(Violation::TotalMismatch { order_id, total: order.total, lines });
+            }
+            if from != order.status && !is_legal(&from, &order.status) {
+                return Err(Violation::IllegalTransition { order_id, from, to: order.status.clone() });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn summary(&self, steps: usize, rejected: usize) -> Summary {
+        let mut orders_by_status = BTreeMap::new();
+        for order in self.orders.values() {
+            *orders_by_status.entry(format!("{:?}", order.status)).or_insert(0) += 1;
+        }
+        Summary {
+            steps,
+            rejected,
+            orders_by_status,
+            stock_left: self.inventory.levels().iter().map(|(_, q)| *q as u64).sum(),
+        }
+    }
+}
+
+/// Replays a trace on a fresh shop, stopping at the first broken invariant
+pub fn run(actions: &[Action]) -> Result<Summary, (usize, Violation)> {
+    let mut sim = Simulation::new();
+    let mut rejected = 0;
+    for (step, action) in actions.iter().enumerate() {
+        if !sim.apply(action) {
+            rejected += 1;
+        }
+        sim.check().map_err(|violation| (step, violation))?;
+    }
+    Ok(sim.summary(actions.len(), rejected))
+}
+
+/// Shrinks a failing trace to a small one that still breaks the same kind of invariant.
+///
+/// Cuts the trace after the failing step, then repeatedly tries dropping
+/// chunks of actions, halving the chunk size whenever nothing can be removed.
+pub fn shrink(actions: &[Action]) -> Vec<Action> {
+    let Err((step, violation)) = run(actions) else {
+        return actions.to_vec();
+    };
+    let kind = std::mem::discriminant(&violation);
+    let still_fails = |candidate: &[Action]| {
+        matches!(run(candidate), Err((_, v)) if std::mem::discriminant(&v) == kind)
+    };
+
+    let mut trace = actions[..=step].to_vec();
+    let mut chunk = trace.len().div_ceil(2);
+    while chunk > 0 {
+        let mut start = 0;
+        let mut removed_any = false;
+        while start < trace.len() {
+            let end = (start + chunk).min(trace.len());
+            let candidate: Vec<Action> = trace[..start].iter().chain(&trace[end..]).cloned().collect();
+            if still_fails(&candidate) {
+                trace = candidate;
+                removed_any = true;
+            } else {
+                start = end;
+            }
+        }
+        if !removed_any {
+            chunk /= 2;
+        }
+    }
+    trace
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUSES: [OrderStatus; 8] = [
+        OrderStatus::Pending,
+        OrderStatus::UnderReview,
+        OrderStatus::Backordered,
+        OrderStatus::Processing,
+        OrderStatus::PartiallyShipped,
+        OrderStatus::Shipped,
+        OrderStatus::Delivered,
+        OrderStatus::Cancelled,
+    ];
+
+    #[test]
+    fn lifecycle_rules_agree_with_the_order_state_machine() {
+        for from in &STATUSES {
+            for to in &STATUSES {
+                assert_eq!(is_legal(from, to), from.can_transition_to(to), "{:?} -> {:?}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn traces_are_reproducible_from_the_seed() {
+        assert_eq!(generate(7, 50), generate(7, 50));
+        assert_ne!(generate(7, 50), generate(8, 50));
+        assert!(generate(7, 0).is_empty());
+    }
+
+    #[test]
+    fn generated_sessions_keep_every_invariant() {
+        for seed in [1, 2, 3] {
+            let actions = generate(seed, 300);
+            let summary = run(&actions).unwrap_or_else(|(step, v)| panic!("seed {} step {}: {}", seed, step, v));
+            assert_eq!(summary.steps, actions.len());
+            assert_eq!(summary.orders_by_status.values().sum::<u32>(), 300);
+        }
+    }
+
+    #[test]
+    fn steps_that_make_no_sense_are_rejected_not_fatal() {
+        let actions = [
+            Action::Ship { order_id: 1, partial: false },
+            Action::CreateOrder { order_id: 1, user_id: 1 },
+            Action::Checkout { order_id: 1, decline: false },
+            Action::AddLine { order_id: 1, product_id: 1, quantity: 1 },
+        ];
+        let summary = run(&actions).unwrap();
+        assert_eq!(summary.rejected, 2);
+        assert_eq!(shrink(&actions), actions.to_vec());
+    }
+}
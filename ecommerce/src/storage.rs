@@ -0,0 +1,604 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::inventory::{BackorderState, Inventory, InventoryError, StockMovement};
+use crate::order::Order;
+use crate::product::Product;
+use crate::user::User;
+
+/// Appended records between automatic compactions of a `FileStorage`
+pub const DEFAULT_COMPACT_AFTER: usize = 1_000;
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    Corrupt { line: usize, message: String },
+    NotFound { kind: &'static str, id: u32 },
+    Ledger(InventoryError),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::Io(_) => write!(f, "Storage I/O failed"),
+            StorageError::Corrupt { line, message } => write!(f, "Corrupt storage record on line {}: {}", line, message),
+            StorageError::NotFound { kind, id } => write!(f, "No {} with id {}", kind, id),
+            StorageError::Ledger(_) => write!(f, "Stored stock ledger cannot be replayed"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StorageError::Io(err) => Some(err),
+            StorageError::Ledger(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(err: io::Error) -> Self {
+        StorageError::Io(err)
+    }
+}
+
+/// Where products, users, orders and the stock ledger are kept.
+///
+/// Reads hand out copies so callers never hold a borrow into the backend.
+/// Order notifiers are runtime wiring and are not stored.
+pub trait Storage {
+    fn put_product(&mut self, product: &Product) -> Result<(), StorageError>;
+    fn delete_product(&mut self, id: u32) -> Result<bool, StorageError>;
+    fn product(&self, id: u32) -> Option<Product>;
+    /// Every product, sorted by id
+    fn products(&self) -> Vec<Product>;
+
+    fn put_user(&mut self, user: &User) -> Result<(), StorageError>;
+    fn user(&self, id: u32) -> Option<User>;
+    fn users(&self) -> Vec<User>;
+
+    fn put_order(&mut self, order: &Order) -> Result<(), StorageError>;
+    fn order(&self, id: u32) -> Option<Order>;
+    fn orders(&self) -> Vec<Order>;
+
+    /// The id after the highest stored order's
+    fn next_order_id(&self) -> u32 {
+        self.orders().iter().map(|o| o.id).max().unwrap_or(0) + 1
+    }
+
+    /// Appends ledger movements; the ledger is never rewritten
+    fn append_movements(&mut self, movements: &[StockMovement]) -> Result<(), StorageError>;
+    fn movements(&self) -> Vec<StockMovement>;
+
+    /// Replaces the stored backorder queue
+    fn put_backorders(&mut self, state: &BackorderState) -> Result<(), StorageError>;
+    fn backorders(&self) -> BackorderState;
+
+    /// Posts any movements the inventory has made since it was last saved, and its backorder queue if it changed
+    fn save_inventory(&mut self, inventory: &Inventory) -> Result<(), StorageError> {
+        let last = self.movements().last().map_or(0, |m| m.id);
+        let new: Vec<StockMovement> = inventory.movements().iter().filter(|m| m.id > last).cloned().collect();
+        if !new.is_empty() {
+            self.append_movements(&new)?;
+        }
+        let state = inventory.backorder_state();
+        if state != self.backorders() {
+            self.put_backorders(&state)?;
+        }
+        Ok(())
+    }
+
+    /// Rebuilds stock levels from the stored ledger and restores the backorder queue
+    fn load_inventory(&self) -> Result<Inventory, StorageError> {
+        let mut inventory = Inventory::from_movements(self.movements()).map_err(StorageError::Ledger)?;
+        inventory.restore_backorders(self.backorders());
+        Ok(inventory)
+    }
+}
+
+/// Keeps everything in memory; the backend for tests and the simulation
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    products: BTreeMap<u32, Product>,
+    users: BTreeMap<u32, User>,
+    orders: BTreeMap<u32, Order>,
+    movements: Vec<StockMovement>,
+    backorders: BackorderState,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record_count(&self) -> usize {
+        let backorders = usize::from(self.backorders != BackorderState::default());
+        self.products.len() + self.users.len() + self.orders.len() + self.movements.len() + backorders
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn put_product(&mut self, product: &Product) -> Result<(), StorageError> {
+        self.products.insert(product.id, product.clone());
+        Ok(())
+    }
+
+    fn delete_product(&mut self, id: u32) -> Result<bool, StorageError> {
+        Ok(self.products.remove(&id).is_some())
+    }
+
+    fn product(&self, id: u32) -> Option<Product> {
+        self.products.get(&id).cloned()
+    }
+
+    fn products(&self) -> Vec<Product> {
+        self.products.values().cloned().collect()
+    }
+
+    fn put_user(&mut self, user: &User) -> Result<(), StorageError> {
+        self.users.insert(user.id, user.clone());
+        Ok(())
+    }
+
+    fn user(&self, id: u32) -> Option<User> {
+        self.users.get(&id).cloned()
+    }
+
+    fn users(&self) -> Vec<User> {
+        self.users.values().cloned().collect()
+    }
+
+    fn put_order(&mut self, order: &Order) -> Result<(), StorageError> {
+        self.orders.insert(order.id, order.clone());
+        Ok(())
+    }
+
+    fn order(&self, id: u32) -> Option<Order> {
+        self.orders.get(&id).cloned()
+    }
+
+    fn orders(&self) -> Vec<Order> {
+        self.orders.values().cloned().collect()
+    }
+
+    fn append_movements(&mut self, movements: &[StockMovement]) -> Result<(), StorageError> {
+        self.movements.extend_from_slice(movements);
+        Ok(())
+    }
+
+    fn movements(&self) -> Vec<StockMovement> {
+        self.movements.clone()
+    }
+
+    fn put_backorders(&mut self, state: &BackorderState) -> Result<(), StorageError> {
+        self.backorders = state.clone();
+        Ok(())
+    }
+
+    fn backorders(&self) -> BackorderState {
+        self.backorders.clone()
+    }
+}
+
+/// Durable storage in a single append-only JSON-lines file.
+///
+/// Every write appends one record and syncs it before returning, so a crash
+/// loses at most the record being written; a torn final line is dropped on
+/// the next open. Superseded records are squeezed out by compaction, which
+/// writes a fresh snapshot beside the log and atomically renames it over.
+pub struct FileStorage {
+    path: PathBuf,
+    log: File,
+    state: MemoryStorage,
+    appended: usize, // records appended since the last compaction
+    compact_after: usize,
+}
+
+impl FileStorage {
+    /// Opens or creates the log at `path` and replays it
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let path = path.as_ref().to_path_buf();
+        // A snapshot left behind by a crash mid-compaction was never renamed into place
+        let _ = fs::remove_file(Self::snapshot_path(&path));
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut state = MemoryStorage::new();
+        let mut valid_len = 0;
+        let mut records = 0usize;
+        for (index, line) in text.split_inclusive('\n').enumerate() {
+            // A line without its newline is a write that was interrupted; it was never acknowledged
+            if !line.ends_with('\n') {
+                break;
+            }
+            if !line.trim().is_empty() {
+                let record = decode_record(line.trim())
+                    .map_err(|message| StorageError::Corrupt { line: index + 1, message })?;
+                apply(&mut state, record);
+                records += 1;
+            }
+            valid_len += line.len();
+        }
+
+        let log = OpenOptions::new().create(true).append(true).open(&path)?;
+        if valid_len < text.len() {
+            log.set_len(valid_len as u64)?;
+        }
+
+        let mut storage = FileStorage { path, log, appended: 0, compact_after: DEFAULT_COMPACT_AFTER, state };
+        storage.appended = records.saturating_sub(storage.state.record_count());
+        Ok(storage)
+    }
+
+    /// Compacts automatically once this many records have been appended
+    pub fn with_compaction_threshold(mut self, records: usize) -> Self {
+        self.compact_after = records.max(1);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn snapshot_path(path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".compact");
+        PathBuf::from(name)
+    }
+
+    fn append(&mut self, records: Vec<Record>) -> Result<(), StorageError> {
+        let mut lines = String::new();
+        for record in &records {
+            lines.push_str(&encode_record(record));
+            lines.push('\n');
+        }
+        self.log.write_all(lines.as_bytes())?;
+        self.log.sync_data()?;
+
+        self.appended += records.len();
+        for record in records {
+            apply(&mut self.state, record);
+        }
+        if self.appended >= self.compact_after {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the log as one record per live object.
+    ///
+    /// The snapshot is synced before the rename and the directory after it,
+    /// so at every instant the file at `path` is either the old log or the
+    /// complete new one.
+    pub fn compact(&mut self) -> Result<(), StorageError> {
+        let snapshot = Self::snapshot_path(&self.path);
+        {
+            let mut file = File::create(&snapshot)?;
+            let mut out = String::new();
+            for record in snapshot_records(&self.state) {
+                out.push_str(&encode_record(&record));
+                out.push('\n');
+            }
+            file.write_all(out.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&snapshot, &self.path)?;
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            File::open(dir)?.sync_all()?;
+        }
+
+        self.log = OpenOptions::new().append(true).open(&self.path)?;
+        self.appended = 0;
+        Ok(())
+    }
+}
+
+impl Storage for FileStorage {
+    fn put_product(&mut self, product: &Product) -> Result<(), StorageError> {
+        self.append(vec![Record::Product(product.clone())])
+    }
+
+    fn delete_product(&mut self, id: u32) -> Result<bool, StorageError> {
+        if self.state.product(id).is_none() {
+            return Ok(false);
+        }
+        self.append(vec![Record::ProductDeleted(id)])?;
+        Ok(true)
+    }
+
+    fn product(&self, id: u32) -> Option<Product> {
+        self.state.product(id)
+    }
+
+    fn products(&self) -> Vec<Product> {
+        self.state.products()
+    }
+
+    fn put_user(&mut self, user: &User) -> Result<(), StorageError> {
+        self.append(vec![Record::User(user.clone())])
+    }
+
+    fn user(&self, id: u32) -> Option<User> {
+        self.state.user(id)
+    }
+
+    fn users(&self) -> Vec<User> {
+        self.state.users()
+    }
+
+    fn put_order(&mut self, order: &Order) -> Result<(), StorageError> {
+        self.append(vec![Record::Order(Box::new(order.clone()))])
+    }
+
+    fn order(&self, id: u32) -> Option<Order> {
+        self.state.order(id)
+    }
+
+    fn orders(&self) -> Vec<Order> {
+        self.state.orders()
+    }
+
+    fn append_movements(&mut self, movements: &[StockMovement]) -> Result<(), StorageError> {
+        self.append(movements.iter().cloned().map(Record::Movement).collect())
+    }
+
+    fn movements(&self) -> Vec<StockMovement> {
+        self.state.movements()
+    }
+
+    fn put_backorders(&mut self, state: &BackorderState) -> Result<(), StorageError> {
+        self.append(vec![Record::Backorders(state.clone())])
+    }
+
+    fn backorders(&self) -> BackorderState {
+        self.state.backorders()
+    }
+}
+
+/// One line of the log, written as `{"type": .., "data": ..}`
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+enum Record {
+    Product(Product),
+    ProductDeleted(u32),
+    User(User),
+    Order(Box<Order>),
+    Movement(StockMovement),
+    Backorders(BackorderState),
+}
+
+fn apply(state: &mut MemoryStorage, record: Record) {
+    match record {
+        Record::Product(product) => {
+            state.products.insert(product.id, product);
+        }
+        Record::ProductDeleted(id) => {
+            state.products.remove(&id);
+        }
+        Record::User(user) => {
+            state.users.insert(user.id, user);
+        }
+        Record::Order(order) => {
+            state.orders.insert(order.id, *order);
+        }
+        Record::Movement(movement) => state.movements.push(movement),
+        Record::Backorders(backorders) => state.backorders = backorders,
+    }
+}
+
+fn snapshot_records(state: &MemoryStorage) -> Vec<Record> {
+    let products = state.products.values().cloned().map(Record::Product);
+    let users = state.users.values().cloned().map(Record::User);
+    let orders = state.orders.values().cloned().map(|o| Record::Order(Box::new(o)));
+    let movements = state.movements.iter().cloned().map(Record::Movement);
+    let backorders = Some(state.backorders.clone())
+        .filter(|backorders| *backorders != BackorderState::default())
+        .map(Record::Backorders);
+    products.chain(users).chain(orders).chain(movements).chain(backorders).collect()
+}
+
+fn encode_record(record: &Record) -> String {
+    serde_json::to_string(record).expect("Records always serialize")
+}
+
+fn decode_record(line: &str) -> Result<Record, String> {
+    serde_json::from_str(line).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::currency::{Currency, RateTable, Rounding};
+    use crate::gift_card::Redemption;
+    use crate::inventory::Reservation;
+    use crate::order::OrderStatus;
+    use crate::payment::MockGateway;
+    use crate::pricing::{PricingEngine, RuleCondition};
+    use crate::product::StockPolicy;
+    use crate::shop::Shop;
+    use chrono::{DateTime, TimeZone, Utc};
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("storage-{}-{}.jsonl", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn user(id: u32) -> User {
+        User::new_unchecked(id, format!("User {}", id), format!("u{}@example.com", id), "1 Main St".into())
+    }
+
+    fn backordered(id: u32) -> Product {
+        Product::new(id, format!("P{}", id), 5.0, String::new()).with_stock_policy(StockPolicy::Backorder)
+    }
+
+    #[test]
+    fn every_order_field_survives_a_reopen() {
+        let at = Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap();
+        let mut pricing = PricingEngine::new();
+        pricing.add_rule(1, RuleCondition::MinQuantity(2), 8.0).unwrap();
+        let mut rates = RateTable::new();
+        rates.add("USD", "EUR", 0.5, at);
+
+        let mut order = Order::new(7, user(1));
+        let lamp = Product::new(1, "Lamp".into(), 10.0, "Desk \"lamp\"".into())
+            .with_stock_policy(StockPolicy::PreOrder { release: at });
+        order.add_product_priced(lamp, 2, &pricing, at).unwrap();
+        order.add_product(Product::new(2, "Kit".into(), 3.0, String::new()).with_components(vec![(1, 2)]), 1).unwrap();
+        order.place().unwrap();
+        order.checkout(Currency::from_code("EUR"), &rates, Rounding::default(), at).unwrap();
+        order.redemptions.push(Redemption { code: "GIFT-1".into(), amount: 4.0 });
+        let mut gateway = MockGateway::new();
+        order.authorize_payment(&mut gateway).unwrap();
+        order.capture_payment(&mut gateway).unwrap();
+        order.update_status(OrderStatus::Processing).unwrap();
+        order.add_shipment("UPS", "1Z", vec![(1, 1)]).unwrap();
+        order.billing_country = Some("DE".into());
+
+        let path = temp_path("order");
+        FileStorage::open(&path).unwrap().put_order(&order).unwrap();
+        let reopened = FileStorage::open(&path).unwrap().order(7).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(serde_json::to_value(&reopened).unwrap(), serde_json::to_value(&order).unwrap());
+        assert!(reopened.is_placed());
+        assert_eq!(reopened.charged_price(1).unwrap().unit_price, 8.0);
+        assert_eq!(reopened.status, OrderStatus::PartiallyShipped);
+    }
+
+    #[test]
+    fn the_backorder_queue_survives_a_reopen_and_compaction() {
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 1).unwrap();
+        let mut order = Order::new(1, user(1));
+        order.add_product(backordered(1), 1).unwrap();
+        order.add_product(backordered(2), 3).unwrap();
+        assert_eq!(inventory.reserve_order(&order).unwrap(), Reservation::Waiting);
+        inventory.expect_receipt(2, Utc.with_ymd_and_hms(2026, 5, 1, 0, 0, 0).unwrap());
+
+        let path = temp_path("backorders");
+        let mut storage = FileStorage::open(&path).unwrap();
+        storage.save_inventory(&inventory).unwrap();
+        storage.compact().unwrap();
+        let reopened = FileStorage::open(&path).unwrap().load_inventory().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(reopened.backorder_state(), inventory.backorder_state());
+        assert_eq!(reopened.backorder_state().held[&1], vec![(1, 1)]);
+        assert_eq!(reopened.check_stock(1), 0);
+    }
+
+    #[test]
+    fn a_reopened_shop_still_allocates_and_releases_backorders() {
+        let path = temp_path("shop-allocate");
+        let mut shop = Shop::open(FileStorage::open(&path).unwrap()).unwrap();
+        shop.add_product(backordered(1), 0).unwrap();
+        shop.register_user(user(1)).unwrap();
+        let order = shop.place_order(1, &[(1, 2)]).unwrap();
+        assert_eq!(order.status, OrderStatus::Backordered);
+        drop(shop);
+
+        let mut shop = Shop::open(FileStorage::open(&path).unwrap()).unwrap();
+        shop.receive_stock(1, 5).unwrap();
+        assert_eq!(shop.storage().order(order.id).unwrap().status, OrderStatus::Processing);
+        assert_eq!(shop.inventory().check_stock(1), 3);
+        drop(shop);
+
+        let shop = Shop::open(FileStorage::open(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(shop.inventory().backorders().count(), 0);
+        assert_eq!(shop.inventory().check_stock(1), 3);
+    }
+
+    #[test]
+    fn cancelling_after_a_reopen_returns_held_units() {
+        let path = temp_path("shop-cancel");
+        let mut shop = Shop::open(FileStorage::open(&path).unwrap()).unwrap();
+        shop.add_product(backordered(1), 2).unwrap();
+        shop.add_product(backordered(2), 0).unwrap();
+        shop.register_user(user(1)).unwrap();
+        let order = shop.place_order(1, &[(1, 2), (2, 1)]).unwrap();
+        assert_eq!(shop.inventory().check_stock(1), 0);
+        drop(shop);
+
+        let mut shop = Shop::open(FileStorage::open(&path).unwrap()).unwrap();
+        shop.cancel_order(order.id).unwrap();
+        assert_eq!(shop.inventory().check_stock(1), 2);
+        drop(shop);
+
+        let shop = Shop::open(FileStorage::open(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(shop.inventory().check_stock(1), 2);
+        assert_eq!(shop.inventory().backorder_state(), BackorderState::default());
+    }
+
+    #[test]
+    fn a_torn_last_line_is_dropped_but_corruption_is_reported() {
+        let path = temp_path("torn");
+        let mut storage = FileStorage::open(&path).unwrap();
+        storage.put_user(&user(1)).unwrap();
+        storage.put_user(&user(2)).unwrap();
+        drop(storage);
+        let text = fs::read_to_string(&path).unwrap();
+        fs::write(&path, &text[..text.len() - 5]).unwrap();
+
+        let mut storage = FileStorage::open(&path).unwrap();
+        assert_eq!(storage.users().len(), 1);
+        storage.put_user(&user(3)).unwrap();
+        assert_eq!(FileStorage::open(&path).unwrap().users().len(), 2);
+
+        fs::write(&path, "{\"type\":\"user\",\"data\":{\"id\":1}}\n").unwrap();
+        let err = FileStorage::open(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(err, StorageError::Corrupt { line: 1, .. }), "{}", err);
+    }
+
+    #[test]
+    fn compaction_keeps_only_live_records() {
+        let path = temp_path("compact");
+        let mut storage = FileStorage::open(&path).unwrap().with_compaction_threshold(3);
+        storage.put_product(&Product::new(1, "A".into(), 1.0, String::new())).unwrap();
+        storage.put_product(&Product::new(1, "B".into(), 1.0, String::new())).unwrap();
+        storage.put_product(&Product::new(2, "C".into(), 1.0, String::new())).unwrap();
+        assert!(storage.delete_product(2).unwrap());
+        assert!(!storage.delete_product(2).unwrap());
+        drop(storage);
+
+        let text = fs::read_to_string(&path).unwrap();
+        let storage = FileStorage::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(text.lines().count(), 3);
+        assert_eq!(storage.products().iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), vec!["B"]);
+    }
+
+    #[test]
+    fn records_from_before_fraud_screening_load_as_long_established() {
+        let mut order = Order::new(4, user(1));
+        order.add_product(backordered(1), 1).unwrap();
+        let mut value: serde_json::Value = serde_json::from_str(&encode_record(&Record::Order(Box::new(order)))).unwrap();
+        let data = value["data"].as_object_mut().unwrap();
+        for field in ["created_at", "billing_country", "shipping_country"] {
+            data.remove(field);
+        }
+        let user = data["user"].as_object_mut().unwrap();
+        for field in ["registered_at", "loyalty_points", "tier"] {
+            user.remove(field);
+        }
+
+        let Ok(Record::Order(order)) = decode_record(&value.to_string()) else { panic!("Old order record did not decode") };
+        assert_eq!(order.created_at, DateTime::<Utc>::UNIX_EPOCH);
+        assert_eq!(order.user.registered_at, DateTime::<Utc>::UNIX_EPOCH);
+        assert_eq!((order.user.loyalty_points, order.billing_country), (0, None));
+    }
+}
@@ -0,0 +1,538 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use chrono::{DateTime, Duration, Months, Utc};
+
+use crate::catalog::Catalog;
+use crate::error::Error;
+use crate::inventory::{Inventory, Reservation, SYSTEM_ACTOR};
+use crate::order::{Order, OrderError, OrderStatus};
+use crate::payment::PaymentGateway;
+use crate::user::User;
+
+/// How often a subscription reorders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cadence {
+    Weekly,
+    /// Same day each month, clamped to the month's last day
+    Monthly,
+    EveryDays(u32),
+}
+
+impl Cadence {
+    /// The run `n` periods after `anchor`, or `None` past the supported date range.
+    ///
+    /// Counting from the anchor keeps monthly runs on the anchor's day: Jan 31
+    /// is followed by Feb 29 and then Mar 31, not Mar 29.
+    pub fn nth_run(&self, anchor: DateTime<Utc>, n: u32) -> Option<DateTime<Utc>> {
+        match self {
+            Cadence::Weekly => Duration::try_weeks(n as i64).and_then(|step| anchor.checked_add_signed(step)),
+            Cadence::Monthly => anchor.checked_add_months(Months::new(n)),
+            Cadence::EveryDays(days) => ((*days).max(1) as i64)
+                .checked_mul(n as i64)
+                .and_then(Duration::try_days)
+                .and_then(|step| anchor.checked_add_signed(step)),
+        }
+    }
+}
+
+impl fmt::Display for Cadence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cadence::Weekly => write!(f, "weekly"),
+            Cadence::Monthly => write!(f, "monthly"),
+            Cadence::EveryDays(days) => write!(f, "every {} days", days),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    Active,
+    Paused,
+    Cancelled,
+}
+
+impl fmt::Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubscriptionStatus::Active => write!(f, "▶️ Active"),
+            SubscriptionStatus::Paused => write!(f, "⏸️ Paused"),
+            SubscriptionStatus::Cancelled => write!(f, "⏹️ Cancelled"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SubscriptionError {
+    EmptySubscription,
+    InvalidQuantity,
+    InvalidStatus,
+    NotFound,
+    DateOutOfRange,
+}
+
+impl fmt::Display for SubscriptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubscriptionError::EmptySubscription => write!(f, "Subscription must contain at least one product"),
+            SubscriptionError::InvalidQuantity => write!(f, "Subscription quantities must be positive"),
+            SubscriptionError::InvalidStatus => write!(f, "Invalid subscription status transition"),
+            SubscriptionError::NotFound => write!(f, "Subscription not found"),
+            SubscriptionError::DateOutOfRange => write!(f, "Next run falls outside the supported date range"),
+        }
+    }
+}
+
+impl std::error::Error for SubscriptionError {}
+
+/// A standing order that repeats on a cadence
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    pub id: u32,
+    pub user: User,
+    pub lines: Vec<(u32, u32)>, // (product_id, quantity); priced from the catalog at each run
+    pub cadence: Cadence,
+    pub first_run: DateTime<Utc>, // runs are counted from here
+    pub next_run: DateTime<Utc>,
+    pub runs_scheduled: u32, // periods between first_run and next_run
+    pub status: SubscriptionStatus,
+    pub order_ids: Vec<u32>, // orders generated so far
+}
+
+impl Subscription {
+    /// The run after `next_run`, without moving the schedule
+    fn following_run(&self) -> Result<(u32, DateTime<Utc>), SubscriptionError> {
+        let n = self.runs_scheduled.checked_add(1).ok_or(SubscriptionError::DateOutOfRange)?;
+        let at = self.cadence.nth_run(self.first_run, n).ok_or(SubscriptionError::DateOutOfRange)?;
+        Ok((n, at))
+    }
+
+    /// Moves `next_run` forward while `before(next_run)` holds
+    fn skip_while(&mut self, before: impl Fn(DateTime<Utc>) -> bool) -> Result<(), SubscriptionError> {
+        while before(self.next_run) {
+            (self.runs_scheduled, self.next_run) = self.following_run()?;
+        }
+        Ok(())
+    }
+
+    pub fn pause(&mut self) -> Result<(), SubscriptionError> {
+        if self.status != SubscriptionStatus::Active {
+            return Err(SubscriptionError::InvalidStatus);
+        }
+        self.status = SubscriptionStatus::Paused;
+        Ok(())
+    }
+
+    /// Resumes a paused subscription; runs missed while paused are skipped, not made up
+    pub fn resume(&mut self, now: DateTime<Utc>) -> Result<(), SubscriptionError> {
+        if self.status != SubscriptionStatus::Paused {
+            return Err(SubscriptionError::InvalidStatus);
+        }
+        self.skip_while(|run| run < now)?;
+        self.status = SubscriptionStatus::Active;
+        Ok(())
+    }
+
+    pub fn cancel(&mut self) -> Result<(), SubscriptionError> {
+        if self.status == SubscriptionStatus::Cancelled {
+            return Err(SubscriptionError::InvalidStatus);
+        }
+        self.status = SubscriptionStatus::Cancelled;
+        Ok(())
+    }
+
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.status == SubscriptionStatus::Active && self.next_run <= now
+    }
+}
+
+/// A due subscription that could not be turned into an order
+#[derive(Debug)]
+pub struct RunFailure {
+    pub subscription_id: u32,
+    pub error: Error,
+}
+
+/// What one scheduler run did
+#[derive(Debug, Default)]
+pub struct RunReport {
+    pub created: Vec<Order>,
+    pub failures: Vec<RunFailure>,
+}
+
+impl RunReport {
+    pub fn display(&self) {
+        println!("{} order(s) created, {} failure(s)", self.created.len(), self.failures.len());
+        for order in &self.created {
+            println!("  ✅ {}", order.get_order_summary());
+        }
+        for failure in &self.failures {
+            println!("  ⚠️ Subscription #{} [{}]: {}",
+                failure.subscription_id, failure.error.code(), crate::error::report(&failure.error));
+        }
+    }
+}
+
+/// Every subscription, plus the scheduler that turns due ones into orders
+#[derive(Debug, Default)]
+pub struct SubscriptionBook {
+    subscriptions: BTreeMap<u32, Subscription>,
+}
+
+impl SubscriptionBook {
+    pub fn new() -> Self {
+        SubscriptionBook::default()
+    }
+
+    pub fn subscribe(
+        &mut self,
+        user: User,
+        lines: Vec<(u32, u32)>,
+        cadence: Cadence,
+        first_run: DateTime<Utc>,
+    ) -> Result<u32, SubscriptionError> {
+        if lines.is_empty() {
+            return Err(SubscriptionError::EmptySubscription);
+        }
+        if lines.iter().any(|(_, quantity)| *quantity == 0) {
+            return Err(SubscriptionError::InvalidQuantity);
+        }
+        let id = self.subscriptions.keys().next_back().map_or(1, |last| last + 1);
+        self.subscriptions.insert(id, Subscription {
+            id,
+            user,
+            lines,
+            cadence,
+            first_run,
+            next_run: first_run,
+            runs_scheduled: 0,
+            status: SubscriptionStatus::Active,
+            order_ids: Vec::new(),
+        });
+        Ok(id)
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Subscription> {
+        self.subscriptions.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Result<&mut Subscription, SubscriptionError> {
+        self.subscriptions.get_mut(&id).ok_or(SubscriptionError::NotFound)
+    }
+
+    pub fn subscriptions(&self) -> impl Iterator<Item = &Subscription> {
+        self.subscriptions.values()
+    }
+
+    /// Places an order for every active subscription due at `now`.
+    ///
+    /// Each line is charged the catalog product's current price, so price
+    /// changes and sales applied with `PriceHistory::refresh` carry over to
+    /// later runs; a product missing from the catalog fails the run.
+    /// Payment is authorized before stock is reserved, so a declined card
+    /// never holds stock; if reservation fails the authorization is voided.
+    /// A failed subscription keeps its run date and is retried next time.
+    /// Orders are numbered up from `first_order_id`, which should be the next
+    /// id free in storage; `Shop::run_subscriptions` takes care of that.
+    pub fn run_due(
+        &mut self,
+        now: DateTime<Utc>,
+        first_order_id: u32,
+        catalog: &Catalog,
+        inventory: &mut Inventory,
+        gateway: &mut dyn PaymentGateway,
+    ) -> RunReport {
+        let mut report = RunReport::default();
+        let due: Vec<u32> = self.subscriptions.values().filter(|s| s.is_due(now)).map(|s| s.id).collect();
+
+        let mut order_id = first_order_id;
+        for id in due {
+            let Some(subscription) = self.subscriptions.get_mut(&id) else { continue };
+            // Work out the next run first so an order is never placed without one
+            let mut rescheduled = subscription.clone();
+            if let Err(err) = rescheduled.skip_while(|run| run <= now) {
+                report.failures.push(RunFailure { subscription_id: id, error: err.into() });
+                continue;
+            }
+            match Self::place(subscription, order_id, catalog, inventory, gateway) {
+                Ok(order) => {
+                    order_id += 1;
+                    subscription.next_run = rescheduled.next_run;
+                    subscription.runs_scheduled = rescheduled.runs_scheduled;
+                    subscription.order_ids.push(order.id);
+                    report.created.push(order);
+                }
+                Err(error) => report.failures.push(RunFailure { subscription_id: id, error }),
+            }
+        }
+        report
+    }
+
+    fn place(
+        subscription: &Subscription,
+        order_id: u32,
+        catalog: &Catalog,
+        inventory: &mut Inventory,
+        gateway: &mut dyn PaymentGateway,
+    ) -> Result<Order, Error> {
+        let mut order = Order::new(order_id, subscription.user.clone());
+        for &(product_id, quantity) in &subscription.lines {
+            let product = catalog.get(product_id).ok_or(OrderError::ProductNotFound)?;
+            order.add_product(product.clone(), quantity)?;
+        }
+        order.place()?;
+        order.authorize_payment(gateway)?;
+
+        let reservation = match inventory.reserve_order(&order) {
+            Ok(reservation) => reservation,
+            Err(err) => {
+                order.void_payment(gateway)?;
+                return Err(err.into());
+            }
+        };
+        if let Err(err) = order.capture_payment(gateway) {
+            let _ = order.void_payment(gateway);
+            Self::release(&order, &reservation, inventory)?;
+            return Err(err.into());
+        }
+
+        let status = match reservation {
+            Reservation::Reserved => OrderStatus::Processing,
+            Reservation::Waiting => OrderStatus::Backordered,
+        };
+        order.update_status(status)?;
+        Ok(order)
+    }
+
+    /// Gives back stock taken for an order that could not be paid for
+    fn release(order: &Order, reservation: &Reservation, inventory: &mut Inventory) -> Result<(), Error> {
+        match reservation {
+            Reservation::Waiting => {
+                inventory.cancel_backorder(order.id);
+            }
+            Reservation::Reserved => {
+                inventory.return_order(order, SYSTEM_ACTOR)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    use crate::payment::{MockGateway, MockResponse};
+    use crate::price_history::PriceHistory;
+    use crate::product::Product;
+    use crate::shop::Shop;
+    use crate::storage::{MemoryStorage, Storage};
+
+    fn at(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, 9, 0, 0).unwrap()
+    }
+
+    fn user() -> User {
+        User::new_unchecked(1, "Ada".into(), "ada@example.com".into(), "1 Main St".into())
+    }
+
+    fn widget() -> Product {
+        Product::new(1, "Widget".into(), 10.0, String::new())
+    }
+
+    fn catalog() -> Catalog {
+        let mut catalog = Catalog::new();
+        catalog.upsert(widget());
+        catalog
+    }
+
+    #[test]
+    fn monthly_runs_keep_the_anchor_day() {
+        let anchor = at(2024, 1, 31);
+        let runs: Vec<_> = (1..=3).map(|n| Cadence::Monthly.nth_run(anchor, n).unwrap()).collect();
+        assert_eq!(runs, vec![at(2024, 2, 29), at(2024, 3, 31), at(2024, 4, 30)]);
+    }
+
+    #[test]
+    fn day_cadences_count_whole_periods() {
+        assert_eq!(Cadence::Weekly.nth_run(at(2024, 1, 1), 2), Some(at(2024, 1, 15)));
+        assert_eq!(Cadence::EveryDays(10).nth_run(at(2024, 1, 1), 3), Some(at(2024, 1, 31)));
+        // Zero days is treated as daily rather than looping forever
+        assert_eq!(Cadence::EveryDays(0).nth_run(at(2024, 1, 1), 1), Some(at(2024, 1, 2)));
+    }
+
+    #[test]
+    fn runs_past_the_date_range_are_errors_not_panics() {
+        assert_eq!(Cadence::EveryDays(u32::MAX).nth_run(at(2024, 1, 1), u32::MAX), None);
+        assert_eq!(Cadence::Monthly.nth_run(DateTime::<Utc>::MAX_UTC, 1), None);
+
+        let mut book = SubscriptionBook::new();
+        let id = book.subscribe(user(), vec![(1, 1)], Cadence::Monthly, DateTime::<Utc>::MAX_UTC).unwrap();
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 5).unwrap();
+        let report = book.run_due(DateTime::<Utc>::MAX_UTC, 1, &catalog(), &mut inventory, &mut MockGateway::new());
+        assert!(report.created.is_empty());
+        assert!(matches!(report.failures[0].error, Error::Subscription(SubscriptionError::DateOutOfRange)));
+        // Nothing was taken for an order that was never placed
+        assert_eq!(inventory.check_stock(1), 5);
+        assert!(book.get(id).unwrap().order_ids.is_empty());
+    }
+
+    #[test]
+    fn subscribing_validates_lines() {
+        let mut book = SubscriptionBook::new();
+        assert_eq!(book.subscribe(user(), vec![], Cadence::Weekly, at(2024, 1, 1)), Err(SubscriptionError::EmptySubscription));
+        assert_eq!(
+            book.subscribe(user(), vec![(1, 0)], Cadence::Weekly, at(2024, 1, 1)),
+            Err(SubscriptionError::InvalidQuantity)
+        );
+        assert!(matches!(book.get_mut(1), Err(SubscriptionError::NotFound)));
+    }
+
+    #[test]
+    fn due_subscriptions_become_orders_once_per_run() {
+        let mut book = SubscriptionBook::new();
+        let id = book.subscribe(user(), vec![(1, 2)], Cadence::Monthly, at(2024, 1, 31)).unwrap();
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 10).unwrap();
+        let mut gateway = MockGateway::new();
+
+        let report = book.run_due(at(2024, 1, 31), 7, &catalog(), &mut inventory, &mut gateway);
+        assert_eq!(report.created.len(), 1);
+        assert_eq!(report.created[0].id, 7);
+        assert_eq!(report.created[0].status, OrderStatus::Processing);
+        assert!(report.created[0].is_paid());
+        assert_eq!(inventory.check_stock(1), 8);
+        assert_eq!(book.get(id).unwrap().next_run, at(2024, 2, 29));
+
+        assert!(book.run_due(at(2024, 2, 1), 8, &catalog(), &mut inventory, &mut gateway).created.is_empty());
+        book.run_due(at(2024, 2, 29), 8, &catalog(), &mut inventory, &mut gateway);
+        assert_eq!(book.get(id).unwrap().next_run, at(2024, 3, 31));
+        assert_eq!(book.get(id).unwrap().order_ids, vec![7, 8]);
+    }
+
+    #[test]
+    fn declined_cards_hold_no_stock_and_retry_next_run() {
+        let mut book = SubscriptionBook::new();
+        let id = book.subscribe(user(), vec![(1, 1)], Cadence::Weekly, at(2024, 1, 1)).unwrap();
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 3).unwrap();
+        let mut gateway = MockGateway::new();
+        gateway.script(MockResponse::Decline("card expired".into()));
+
+        let report = book.run_due(at(2024, 1, 1), 1, &catalog(), &mut inventory, &mut gateway);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].subscription_id, id);
+        assert_eq!(inventory.check_stock(1), 3);
+        assert_eq!(book.get(id).unwrap().next_run, at(2024, 1, 1));
+
+        assert_eq!(book.run_due(at(2024, 1, 2), 1, &catalog(), &mut inventory, &mut gateway).created.len(), 1);
+    }
+
+    #[test]
+    fn missing_stock_voids_the_authorization() {
+        let mut book = SubscriptionBook::new();
+        book.subscribe(user(), vec![(1, 5)], Cadence::Weekly, at(2024, 1, 1)).unwrap();
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 1).unwrap();
+        let mut gateway = MockGateway::new();
+
+        let report = book.run_due(at(2024, 1, 1), 1, &catalog(), &mut inventory, &mut gateway);
+        assert!(matches!(report.failures[0].error, Error::Inventory(_)));
+        assert!(gateway.calls().last().unwrap().starts_with("void"));
+        assert_eq!(inventory.check_stock(1), 1);
+    }
+
+    #[test]
+    fn failed_capture_gives_the_stock_back() {
+        let mut book = SubscriptionBook::new();
+        book.subscribe(user(), vec![(1, 2)], Cadence::Weekly, at(2024, 1, 1)).unwrap();
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 2).unwrap();
+        let mut gateway = MockGateway::new();
+        gateway.script(MockResponse::Approve).script(MockResponse::Timeout);
+
+        let report = book.run_due(at(2024, 1, 1), 1, &catalog(), &mut inventory, &mut gateway);
+        assert!(matches!(report.failures[0].error, Error::Order(_)));
+        assert_eq!(inventory.check_stock(1), 2);
+    }
+
+    #[test]
+    fn paused_subscriptions_skip_missed_runs() {
+        let mut book = SubscriptionBook::new();
+        let id = book.subscribe(user(), vec![(1, 1)], Cadence::Monthly, at(2024, 1, 31)).unwrap();
+        let subscription = book.get_mut(id).unwrap();
+        subscription.pause().unwrap();
+        assert_eq!(subscription.pause(), Err(SubscriptionError::InvalidStatus));
+        assert!(!subscription.is_due(at(2024, 2, 1)));
+
+        subscription.resume(at(2024, 4, 1)).unwrap();
+        assert_eq!(subscription.next_run, at(2024, 4, 30));
+        assert_eq!(subscription.resume(at(2024, 4, 1)), Err(SubscriptionError::InvalidStatus));
+
+        subscription.cancel().unwrap();
+        assert_eq!(subscription.cancel(), Err(SubscriptionError::InvalidStatus));
+        assert!(!subscription.is_due(at(2024, 5, 1)));
+    }
+
+    #[test]
+    fn shop_numbers_subscription_orders_after_stored_ones() {
+        let mut shop = Shop::open(MemoryStorage::new()).unwrap();
+        shop.add_product(widget(), 10).unwrap();
+        shop.register_user(user()).unwrap();
+        shop.place_order(1, &[(1, 1)]).unwrap();
+
+        let mut book = SubscriptionBook::new();
+        book.subscribe(user(), vec![(1, 1)], Cadence::Weekly, at(2024, 1, 1)).unwrap();
+        book.subscribe(user(), vec![(1, 2)], Cadence::Weekly, at(2024, 1, 1)).unwrap();
+        let report = shop.run_subscriptions(&mut book, at(2024, 1, 1), &mut MockGateway::new()).unwrap();
+
+        let ids: Vec<u32> = report.created.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert_eq!(shop.storage().orders().len(), 3);
+        assert_eq!(shop.storage().next_order_id(), 4);
+        assert_eq!(shop.inventory().check_stock(1), 6);
+    }
+
+    #[test]
+    fn each_run_charges_the_current_catalog_price() {
+        let mut book = SubscriptionBook::new();
+        book.subscribe(user(), vec![(1, 2)], Cadence::Weekly, at(2024, 1, 1)).unwrap();
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 10).unwrap();
+        let mut gateway = MockGateway::new();
+        let mut catalog = catalog();
+
+        let first = book.run_due(at(2024, 1, 1), 1, &catalog, &mut inventory, &mut gateway);
+        assert_eq!(first.created[0].total, 20.0);
+
+        // A sale applied to the catalog between runs is charged on the next order
+        let mut history = PriceHistory::new();
+        history.track(&widget(), at(2024, 1, 1));
+        history.schedule_sale(1, 7.5, at(2024, 1, 5), at(2024, 1, 10)).unwrap();
+        history.refresh(catalog.products_mut(), at(2024, 1, 8));
+        let second = book.run_due(at(2024, 1, 8), 2, &catalog, &mut inventory, &mut gateway);
+        assert_eq!(second.created[0].total, 15.0);
+
+        history.refresh(catalog.products_mut(), at(2024, 1, 15));
+        let third = book.run_due(at(2024, 1, 15), 3, &catalog, &mut inventory, &mut gateway);
+        assert_eq!(third.created[0].total, 20.0);
+    }
+
+    #[test]
+    fn products_missing_from_the_catalog_fail_the_run() {
+        let mut book = SubscriptionBook::new();
+        let id = book.subscribe(user(), vec![(1, 1), (2, 1)], Cadence::Weekly, at(2024, 1, 1)).unwrap();
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 5).unwrap();
+        let mut gateway = MockGateway::new();
+
+        let report = book.run_due(at(2024, 1, 1), 1, &catalog(), &mut inventory, &mut gateway);
+        assert!(matches!(report.failures[0].error, Error::Order(OrderError::ProductNotFound)));
+        assert!(gateway.calls().is_empty());
+        assert_eq!(inventory.check_stock(1), 5);
+        assert_eq!(book.get(id).unwrap().next_run, at(2024, 1, 1));
+    }
+}
@@ -1,11 +1,28 @@
 use std::fmt;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::loyalty::Tier;
+use crate::render::{Format, Render};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: u32,
     pub name: String,
     pub email: String,
     pub address: String,
+    #[serde(default)]
+    pub loyalty_points: u32, // as of the last `LoyaltyProgram::refresh`
+    #[serde(default)]
+    pub tier: Tier,
+    #[serde(default = "unix_epoch")]
+    pub registered_at: DateTime<Utc>,
+}
+
+/// Stand-in for timestamps missing from records written before they were kept.
+///
+/// Long past rather than now, so old accounts and orders never look new to the fraud rules.
+pub(crate) fn unix_epoch() -> DateTime<Utc> {
+    DateTime::UNIX_EPOCH
 }
 
 #[derive(Debug)]
@@ -25,6 +42,8 @@ impl fmt::Display for UserError {
     }
 }
 
+impl std::error::Error for UserError {}
+
 impl User {
     /// Creates a new User with validation
     pub fn new(id: u32, name: String, email: String, address: String) -> Result<Self, UserError> {
@@ -44,6 +63,9 @@ impl User {
             name: name.trim().to_string(),
             email: email.trim().to_lowercase(),
             address: address.trim().to_string(),
+            loyalty_points: 0,
+            tier: Tier::default(),
+            registered_at: Utc::now(),
         })
     }
 
@@ -54,9 +76,18 @@ impl User {
             name,
             email: email.to_lowercase(),
             address,
+            loyalty_points: 0,
+            tier: Tier::default(),
+            registered_at: Utc::now(),
         }
     }
 
+    /// Sets when the account was opened, e.g. for accounts imported from another system
+    pub fn with_registered_at(mut self, registered_at: DateTime<Utc>) -> Self {
+        self.registered_at = registered_at;
+        self
+    }
+
     /// Validates email format (basic check)
     fn is_valid_email(email: &str) -> bool {
         let email = email.trim();
@@ -84,19 +115,25 @@ impl User {
         Ok(())
     }
 
+    /// Spendable loyalty points
+    pub fn points_balance(&self) -> u32 {
+        self.loyalty_points
+    }
+
+    pub fn tier(&self) -> Tier {
+        self.tier
+    }
+
     /// Displays user information in a formatted way
     pub fn display(&self) {
-        println!("┌─────────── User Details ───────────┐");
-        println!("│ ID: {:<29} │", self.id);
-        println!("│ Name: {:<27} │", self.name);
-        println!("│ Email: {:<26} │", self.email);
-        println!("│ Address: {:<24} │", self.address);
-        println!("└────────────────────────────────────┘");
+        print!("{}", self.render_to_string(Format::Boxed));
     }
+}
 
-    /// Returns user information as a formatted string
-    pub fn to_string(&self) -> String {
-        format!("User {} (ID: {}) - Email: {}, Address: {}", 
+impl fmt::Display for User {
+    /// Formats user information as a single line
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "User {} (ID: {}) - Email: {}, Address: {}",
             self.name, self.id, self.email, self.address)
     }
 }
@@ -0,0 +1,846 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use crate::inventory::Inventory;
+use crate::notification::{Notifier, NotifyError, OrderEvent};
+use crate::order::OrderStatus;
+
+/// Header carrying `t=<unix seconds>,v1=<hex HMAC-SHA256 of "<t>.<body>">`
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+/// Kinds of event a subscription can ask for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    OrderStatusChanged,
+    StockThreshold,
+}
+
+impl EventKind {
+    /// Name sent in the payload's `type` field and the event header
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::OrderStatusChanged => "order.status_changed",
+            EventKind::StockThreshold => "inventory.stock_threshold",
+        }
+    }
+}
+
+/// Something that happened in the shop that subscribers may want pushed to them
+#[derive(Debug, Clone, PartialEq)]
+pub enum WebhookEvent {
+    OrderStatusChanged { order_id: u32, user_id: u32, from: OrderStatus, to: OrderStatus, total: f64 },
+    /// Stock fell below a watched threshold (`below`) or recovered to it
+    StockThreshold { product_id: u32, level: u32, threshold: u32, below: bool },
+}
+
+impl WebhookEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            WebhookEvent::OrderStatusChanged { .. } => EventKind::OrderStatusChanged,
+            WebhookEvent::StockThreshold { .. } => EventKind::StockThreshold,
+        }
+    }
+
+    fn data(&self) -> Value {
+        match self {
+            WebhookEvent::OrderStatusChanged { order_id, user_id, from, to, total } => json!({
+                "order_id": order_id,
+                "user_id": user_id,
+                "from": from,
+                "to": to,
+                "total": (total * 100.0).round() / 100.0,
+            }),
+            WebhookEvent::StockThreshold { product_id, level, threshold, below } => json!({
+                "product_id": product_id,
+                "level": level,
+                "threshold": threshold,
+                "below": below,
+            }),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WebhookError {
+    InvalidUrl(String),
+    EmptySecret,
+    UnknownSubscription(u32),
+    UnknownDelivery(u64),
+    /// The subscription was unsubscribed, so nothing more is sent to it
+    InactiveSubscription(u32),
+    Io(io::Error),
+    /// The receiver answered with a non-2xx status
+    Status(u16),
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WebhookError::InvalidUrl(url) => write!(f, "Webhook URL '{}' is not a valid http:// URL", url),
+            WebhookError::EmptySecret => write!(f, "Webhook signing secret cannot be empty"),
+            WebhookError::UnknownSubscription(id) => write!(f, "Unknown webhook subscription {}", id),
+            WebhookError::UnknownDelivery(id) => write!(f, "Unknown webhook delivery {}", id),
+            WebhookError::InactiveSubscription(id) => write!(f, "Webhook subscription {} is no longer active", id),
+            WebhookError::Io(_) => write!(f, "Webhook request failed"),
+            WebhookError::Status(status) => write!(f, "Webhook receiver answered HTTP {}", status),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WebhookError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for WebhookError {
+    fn from(err: io::Error) -> Self {
+        WebhookError::Io(err)
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// HMAC-SHA256 of `"<timestamp>.<body>"`, the value a signature header carries
+fn signature_mac(secret: &str, timestamp: i64, body: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body.as_bytes());
+    mac
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+/// The signature header value for a body sent at `timestamp` (unix seconds)
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    format!("t={},v1={}", timestamp, hex(&signature_mac(secret, timestamp, body).finalize().into_bytes()))
+}
+
+/// Checks a signature header against the body, as a receiver would
+pub fn verify(secret: &str, header: &str, body: &str) -> bool {
+    let field = |name: &str| header.split(',').find_map(|part| part.trim().strip_prefix(name));
+    let Some(timestamp) = field("t=").and_then(|t| t.parse::<i64>().ok()) else { return false };
+    let Some(signature) = field("v1=").and_then(unhex) else { return false };
+    // verify_slice compares in constant time
+    signature_mac(secret, timestamp, body).verify_slice(&signature).is_ok()
+}
+
+/// Splits `http://host[:port][/path]` into the address to connect to, the Host header and the path.
+///
+/// Whitespace and control characters are rejected, as they would end up in the request line and headers.
+fn parse_url(url: &str) -> Result<(String, String, String), WebhookError> {
+    let invalid = || WebhookError::InvalidUrl(url.to_string());
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(invalid());
+    }
+    let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+    let (host, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    let address = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+    Ok((address, host.to_string(), path.to_string()))
+}
+
+/// Sends one HTTP POST and returns the response status
+pub trait Transport {
+    fn post(&mut self, url: &str, headers: &[(&str, String)], body: &str) -> Result<u16, WebhookError>;
+}
+
+/// Plain HTTP/1.1 over TCP; put a TLS-terminating proxy in front of https receivers
+#[derive(Debug)]
+pub struct HttpTransport {
+    timeout: std::time::Duration,
+}
+
+impl Default for HttpTransport {
+    fn default() -> Self {
+        HttpTransport { timeout: std::time::Duration::from_secs(5) }
+    }
+}
+
+impl HttpTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies to connecting as well as to each read and write
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Tries each address the host resolves to, giving each `timeout` to connect
+    fn connect(address: &str, timeout: std::time::Duration) -> io::Result<TcpStream> {
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "host did not resolve to any address");
+        for candidate in address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&candidate, timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_error = err,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+impl Transport for HttpTransport {
+    fn post(&mut self, url: &str, headers: &[(&str, String)], body: &str) -> Result<u16, WebhookError> {
+        let (address, host, path) = parse_url(url)?;
+        let mut stream = Self::connect(&address, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            path, host, body.len()
+        );
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        request.push_str(body);
+        stream.write_all(request.as_bytes())?;
+
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line)?;
+        status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP status line").into())
+    }
+}
+
+/// A receiver URL and the events it wants
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookSubscription {
+    pub id: u32,
+    pub url: String,
+    secret: String,
+    pub events: Vec<EventKind>,
+    pub active: bool,
+}
+
+/// How often and how far apart failed deliveries are retried
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { max_attempts: 5, base_delay: Duration::seconds(30), max_delay: Duration::hours(1) }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait after the given number of failed attempts: base, 2×base, 4×base… capped at `max_delay`
+    pub fn delay_after(&self, attempts: u32) -> Duration {
+        let factor = 2i32.saturating_pow(attempts.saturating_sub(1));
+        self.base_delay.checked_mul(factor).map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+/// One payload on its way to one subscription
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    pub id: u64,
+    pub subscription_id: u32,
+    pub kind: EventKind,
+    pub body: String,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+/// What one `deliver_due` run did, by delivery id
+#[derive(Debug, Default)]
+pub struct DeliveryReport {
+    pub delivered: Vec<u64>,
+    pub retrying: Vec<u64>,
+    pub dead_lettered: Vec<u64>,
+}
+
+impl DeliveryReport {
+    pub fn display(&self) {
+        println!("{} delivered, {} to retry, {} dead-lettered",
+            self.delivered.len(), self.retrying.len(), self.dead_lettered.len());
+    }
+}
+
+/// Webhook subscriptions and the queue of signed payloads waiting to reach them.
+///
+/// Nothing is sent when an event is published; `deliver_due` sends whatever
+/// is due, so callers decide when network calls happen.
+#[derive(Debug, Default)]
+pub struct WebhookDispatcher {
+    subscriptions: BTreeMap<u32, WebhookSubscription>,
+    queue: Vec<Delivery>,
+    dead_letters: Vec<Delivery>,
+    retry: RetryPolicy,
+    thresholds: HashMap<u32, u32>, // product_id -> stock level to warn below
+    below: HashMap<u32, bool>,     // product_id -> whether the last check was below its threshold
+    next_event: u64,
+    next_delivery: u64,
+}
+
+impl WebhookDispatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Registers a receiver and returns its subscription id
+    pub fn subscribe(&mut self, url: &str, secret: &str, events: &[EventKind]) -> Result<u32, WebhookError> {
+        parse_url(url)?;
+        if secret.is_empty() {
+            return Err(WebhookError::EmptySecret);
+        }
+        let id = self.subscriptions.keys().next_back().map_or(1, |last| last + 1);
+        self.subscriptions.insert(id, WebhookSubscription {
+            id,
+            url: url.to_string(),
+            secret: secret.to_string(),
+            events: events.to_vec(),
+            active: true,
+        });
+        Ok(id)
+    }
+
+    /// Stops new deliveries to a receiver; queued ones are dropped
+    pub fn unsubscribe(&mut self, id: u32) -> Result<(), WebhookError> {
+        let subscription = self.subscriptions.get_mut(&id).ok_or(WebhookError::UnknownSubscription(id))?;
+        subscription.active = false;
+        self.queue.retain(|delivery| delivery.subscription_id != id);
+        Ok(())
+    }
+
+    pub fn subscription(&self, id: u32) -> Option<&WebhookSubscription> {
+        self.subscriptions.get(&id)
+    }
+
+    /// Queues the event for every active subscription that wants it; returns the delivery ids
+    pub fn publish(&mut self, event: &WebhookEvent, now: DateTime<Utc>) -> Vec<u64> {
+        self.next_event += 1;
+        let body = json!({
+            "id": format!("evt_{}", self.next_event),
+            "type": event.kind().name(),
+            "created_at": now.to_rfc3339(),
+            "data": event.data(),
+        })
+        .to_string();
+
+        let mut ids = Vec::new();
+        for subscription in self.subscriptions.values().filter(|s| s.active && s.events.contains(&event.kind())) {
+            self.next_delivery += 1;
+            self.queue.push(Delivery {
+                id: self.next_delivery,
+                subscription_id: subscription.id,
+                kind: event.kind(),
+                body: body.clone(),
+                attempts: 0,
+                next_attempt_at: now,
+                last_error: None,
+            });
+            ids.push(self.next_delivery);
+        }
+        ids
+    }
+
+    /// Sends a stock threshold event when a product first drops below `threshold` and again when it recovers
+    pub fn watch_stock(&mut self, product_id: u32, threshold: u32) {
+        self.thresholds.insert(product_id, threshold);
+    }
+
+    /// Compares watched products against their thresholds and publishes any crossings
+    pub fn check_stock(&mut self, inventory: &Inventory, now: DateTime<Utc>) -> Vec<u64> {
+        let mut watched: Vec<(u32, u32)> = self.thresholds.iter().map(|(id, t)| (*id, *t)).collect();
+        watched.sort_unstable();
+        let mut ids = Vec::new();
+        for (product_id, threshold) in watched {
+            let level = inventory.check_stock(product_id);
+            let below = level < threshold;
+            let was_below = self.below.insert(product_id, below).unwrap_or(false);
+            if below != was_below {
+                ids.extend(self.publish(&WebhookEvent::StockThreshold { product_id, level, threshold, below }, now));
+            }
+        }
+        ids
+    }
+
+    /// Attempts every delivery that is due; failures back off and eventually move to the dead-letter list
+    pub fn deliver_due(&mut self, now: DateTime<Utc>, transport: &mut dyn Transport) -> DeliveryReport {
+        let mut report = DeliveryReport::default();
+        let (due, waiting): (Vec<Delivery>, Vec<Delivery>) =
+            std::mem::take(&mut self.queue).into_iter().partition(|d| d.next_attempt_at <= now);
+        self.queue = waiting;
+
+        for mut delivery in due {
+            let subscription = &self.subscriptions[&delivery.subscription_id];
+            let headers = [
+                (SIGNATURE_HEADER, sign(&subscription.secret, now.timestamp(), &delivery.body)),
+                (EVENT_HEADER, delivery.kind.name().to_string()),
+                (DELIVERY_HEADER, delivery.id.to_string()),
+            ];
+            delivery.attempts += 1;
+            let result = match transport.post(&subscription.url, &headers, &delivery.body) {
+                Ok(status) if (200..300).contains(&status) => Ok(()),
+                Ok(status) => Err(WebhookError::Status(status)),
+                Err(err) => Err(err),
+            };
+
+            match result {
+                Ok(()) => report.delivered.push(delivery.id),
+                Err(err) => {
+                    delivery.last_error = Some(crate::error::report(&err));
+                    if delivery.attempts >= self.retry.max_attempts {
+                        report.dead_lettered.push(delivery.id);
+                        self.dead_letters.push(delivery);
+                    } else {
+                        delivery.next_attempt_at = now + self.retry.delay_after(delivery.attempts);
+                        report.retrying.push(delivery.id);
+                        self.queue.push(delivery);
+                    }
+                }
+            }
+        }
+        report
+    }
+
+    /// Deliveries still waiting to be sent or retried
+    pub fn pending(&self) -> &[Delivery] {
+        &self.queue
+    }
+
+    /// Deliveries that ran out of attempts
+    pub fn dead_letters(&self) -> &[Delivery] {
+        &self.dead_letters
+    }
+
+    /// Puts a dead-lettered delivery back in the queue with a fresh set of attempts
+    pub fn redeliver(&mut self, delivery_id: u64, now: DateTime<Utc>) -> Result<(), WebhookError> {
+        let index = self.dead_letters
+            .iter()
+            .position(|d| d.id == delivery_id)
+            .ok_or(WebhookError::UnknownDelivery(delivery_id))?;
+        let subscription_id = self.dead_letters[index].subscription_id;
+        if !self.subscriptions.get(&subscription_id).is_some_and(|s| s.active) {
+            return Err(WebhookError::InactiveSubscription(subscription_id));
+        }
+        let mut delivery = self.dead_letters.remove(index);
+        delivery.attempts = 0;
+        delivery.next_attempt_at = now;
+        self.queue.push(delivery);
+        Ok(())
+    }
+}
+
+/// Publishes order status changes to a shared dispatcher
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    dispatcher: Arc<Mutex<WebhookDispatcher>>,
+}
+
+impl WebhookNotifier {
+    pub fn new(dispatcher: Arc<Mutex<WebhookDispatcher>>) -> Self {
+        WebhookNotifier { dispatcher }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&self, event: &OrderEvent) -> Result<(), NotifyError> {
+        if let OrderEvent::StatusChanged { order, from, to } = event {
+            let event = WebhookEvent::OrderStatusChanged {
+                order_id: order.id,
+                user_id: order.user.id,
+                from: from.clone(),
+                to: to.clone(),
+                total: order.total,
+            };
+            let mut dispatcher = self.dispatcher.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            dispatcher.publish(&event, Utc::now());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::io::Read;
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread::{self, JoinHandle};
+
+    use chrono::TimeZone;
+
+    /// A request the stub receiver accepted
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ReceivedRequest {
+        pub path: String,
+        pub event: Option<String>,
+        pub body: String,
+        pub signature_valid: bool,
+    }
+
+    #[derive(Debug, Default)]
+    struct StubState {
+        responses: VecDeque<u16>,
+        received: Vec<ReceivedRequest>,
+    }
+
+    /// A local HTTP server that records webhook requests and checks their signatures.
+    ///
+    /// Answers 200 unless statuses were scripted with `respond_with`. Stops when dropped.
+    #[derive(Debug)]
+    pub struct StubReceiver {
+        address: SocketAddr,
+        state: Arc<Mutex<StubState>>,
+        stop: Arc<AtomicBool>,
+        handle: Option<JoinHandle<()>>,
+    }
+
+    impl StubReceiver {
+        /// Listens on a free port on 127.0.0.1
+        pub fn start(secret: &str) -> io::Result<Self> {
+            let listener = TcpListener::bind("127.0.0.1:0")?;
+            let address = listener.local_addr()?;
+            let state = Arc::new(Mutex::new(StubState::default()));
+            let stop = Arc::new(AtomicBool::new(false));
+
+            let secret = secret.to_string();
+            let (thread_state, thread_stop) = (Arc::clone(&state), Arc::clone(&stop));
+            let handle = thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if thread_stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        // A malformed request is simply dropped, as a real receiver would
+                        let _ = Self::handle(stream, &secret, &thread_state);
+                    }
+                }
+            });
+            Ok(StubReceiver { address, state, stop, handle: Some(handle) })
+        }
+
+        fn handle(stream: TcpStream, secret: &str, state: &Mutex<StubState>) -> io::Result<()> {
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line)?;
+            let path = request_line.split_whitespace().nth(1).unwrap_or("/").to_string();
+
+            let mut headers = HashMap::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line)?;
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+                }
+            }
+            let length = headers.get("content-length").and_then(|l| l.parse().ok()).unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            let body = String::from_utf8_lossy(&body).into_owned();
+
+            let signature = headers.get(&SIGNATURE_HEADER.to_lowercase());
+            let request = ReceivedRequest {
+                path,
+                event: headers.get(&EVENT_HEADER.to_lowercase()).cloned(),
+                signature_valid: signature.is_some_and(|header| verify(secret, header, &body)),
+                body,
+            };
+            let status = {
+                let mut state = state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                state.received.push(request);
+                state.responses.pop_front().unwrap_or(200)
+            };
+            let mut stream = reader.into_inner();
+            write!(stream, "HTTP/1.1 {} Stub\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)
+        }
+
+        /// URL of a path on this receiver, e.g. `url("/hooks")`
+        pub fn url(&self, path: &str) -> String {
+            format!("http://{}{}", self.address, path)
+        }
+
+        /// Answers the next requests with these statuses, in order, then 200 again
+        pub fn respond_with(&self, statuses: &[u16]) {
+            let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            state.responses.extend(statuses);
+        }
+
+        /// Every request received so far, oldest first
+        pub fn received(&self) -> Vec<ReceivedRequest> {
+            self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).received.clone()
+        }
+    }
+
+    impl Drop for StubReceiver {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::SeqCst);
+            // Wake the accept loop so it sees the flag
+            let _ = TcpStream::connect(self.address);
+            if let Some(handle) = self.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+
+    /// A request as the transport saw it: URL, headers and body
+    type SentRequest = (String, Vec<(String, String)>, String);
+
+    /// Answers from a script without touching the network; `None` is a connection failure
+    #[derive(Default)]
+    struct ScriptedTransport {
+        responses: VecDeque<Option<u16>>,
+        sent: Vec<SentRequest>,
+    }
+
+    impl Transport for ScriptedTransport {
+        fn post(&mut self, url: &str, headers: &[(&str, String)], body: &str) -> Result<u16, WebhookError> {
+            let headers = headers.iter().map(|(name, value)| (name.to_string(), value.clone())).collect();
+            self.sent.push((url.to_string(), headers, body.to_string()));
+            match self.responses.pop_front().unwrap_or(Some(200)) {
+                Some(status) => Ok(status),
+                None => Err(io::Error::new(io::ErrorKind::ConnectionRefused, "refused").into()),
+            }
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 9, 0, 0).unwrap()
+    }
+
+    fn order_event() -> WebhookEvent {
+        WebhookEvent::OrderStatusChanged {
+            order_id: 7,
+            user_id: 1,
+            from: OrderStatus::Pending,
+            to: OrderStatus::Processing,
+            total: 10.005,
+        }
+    }
+
+    fn dispatcher(max_attempts: u32) -> WebhookDispatcher {
+        WebhookDispatcher::new().with_retry_policy(RetryPolicy {
+            max_attempts,
+            base_delay: Duration::seconds(30),
+            max_delay: Duration::minutes(5),
+        })
+    }
+
+    #[test]
+    fn signatures_match_a_reference_hmac() {
+        let body = r#"{"id":"evt_1"}"#;
+        assert_eq!(
+            sign("whsec_test", 1_700_000_000, body),
+            "t=1700000000,v1=c89214b5b5da833daed6f0b8c5bb6bd58cea9022bd80ccc78230f3942d632925"
+        );
+    }
+
+    #[test]
+    fn verify_rejects_anything_but_the_signed_body() {
+        let header = sign("whsec_test", 1_700_000_000, "body");
+        assert!(verify("whsec_test", &header, "body"));
+        assert!(!verify("whsec_test", &header, "body!"));
+        assert!(!verify("whsec_other", &header, "body"));
+        assert!(!verify("whsec_test", &header.replace("t=1700000000", "t=1700000001"), "body"));
+        assert!(!verify("whsec_test", "t=1700000000", "body"));
+        assert!(!verify("whsec_test", "v1=00", "body"));
+        assert!(!verify("whsec_test", "t=1700000000,v1=abc", "body"));
+        assert!(!verify("whsec_test", "t=1700000000,v1=zz", "body"));
+    }
+
+    #[test]
+    fn subscriptions_need_an_http_url_and_a_secret() {
+        let mut dispatcher = WebhookDispatcher::new();
+        assert!(matches!(dispatcher.subscribe("https://example.com", "s", &[]), Err(WebhookError::InvalidUrl(_))));
+        assert!(matches!(dispatcher.subscribe("http:///hooks", "s", &[]), Err(WebhookError::InvalidUrl(_))));
+        for url in [
+            "http://example.com/hooks HTTP/1.1\r\nX-Injected: 1\r\n\r\nPOST /admin",
+            "http://example.com\r\nX-Injected: 1",
+            "http://example.com/a b",
+            "http://example.com/\n",
+            "http://exa\tmple.com/",
+            "http://example.com/\u{0}",
+            "http://example.com/\u{85}",
+        ] {
+            assert!(matches!(dispatcher.subscribe(url, "s", &[]), Err(WebhookError::InvalidUrl(_))), "{:?}", url);
+        }
+        assert!(matches!(dispatcher.subscribe("http://example.com", "", &[]), Err(WebhookError::EmptySecret)));
+        assert_eq!(dispatcher.subscribe("http://example.com", "s", &[]).unwrap(), 1);
+        assert!(matches!(dispatcher.unsubscribe(9), Err(WebhookError::UnknownSubscription(9))));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy { max_attempts: 10, base_delay: Duration::seconds(30), max_delay: Duration::minutes(5) };
+        let delays: Vec<i64> = (1..=6).map(|attempts| policy.delay_after(attempts).num_seconds()).collect();
+        assert_eq!(delays, vec![30, 60, 120, 240, 300, 300]);
+        assert_eq!(policy.delay_after(u32::MAX), Duration::minutes(5));
+
+        let huge = RetryPolicy { max_attempts: 10, base_delay: Duration::days(365_000), max_delay: Duration::MAX };
+        assert_eq!(huge.delay_after(40), Duration::MAX);
+    }
+
+    #[test]
+    fn events_go_only_to_active_subscribers_that_want_them() {
+        let mut dispatcher = dispatcher(3);
+        let orders = dispatcher.subscribe("http://a.test/hooks", "a", &[EventKind::OrderStatusChanged]).unwrap();
+        dispatcher.subscribe("http://b.test/hooks", "b", &[EventKind::StockThreshold]).unwrap();
+        let gone = dispatcher.subscribe("http://c.test/hooks", "c", &[EventKind::OrderStatusChanged]).unwrap();
+        dispatcher.unsubscribe(gone).unwrap();
+
+        assert_eq!(dispatcher.publish(&order_event(), now()).len(), 1);
+        let delivery = &dispatcher.pending()[0];
+        assert_eq!(delivery.subscription_id, orders);
+        let body: Value = serde_json::from_str(&delivery.body).unwrap();
+        assert_eq!(body["type"], "order.status_changed");
+        assert_eq!(body["data"]["to"], "Processing");
+        assert_eq!(body["data"]["total"], 10.01);
+    }
+
+    #[test]
+    fn deliveries_are_signed_with_the_subscribers_secret() {
+        let mut dispatcher = dispatcher(3);
+        dispatcher.subscribe("http://a.test/hooks", "whsec_a", &[EventKind::OrderStatusChanged]).unwrap();
+        dispatcher.publish(&order_event(), now());
+        let mut transport = ScriptedTransport::default();
+
+        let report = dispatcher.deliver_due(now(), &mut transport);
+        assert_eq!(report.delivered, vec![1]);
+        assert!(dispatcher.pending().is_empty());
+        let (url, headers, body) = &transport.sent[0];
+        assert_eq!(url, "http://a.test/hooks");
+        let header = |name: &str| headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str()).unwrap();
+        assert!(verify("whsec_a", header(SIGNATURE_HEADER), body));
+        assert_eq!(header(EVENT_HEADER), "order.status_changed");
+        assert_eq!(header(DELIVERY_HEADER), "1");
+    }
+
+    #[test]
+    fn failures_back_off_then_dead_letter() {
+        let mut dispatcher = dispatcher(2);
+        dispatcher.subscribe("http://a.test/hooks", "a", &[EventKind::OrderStatusChanged]).unwrap();
+        dispatcher.publish(&order_event(), now());
+        let mut transport = ScriptedTransport::default();
+        transport.responses.extend([Some(503), None]);
+
+        let report = dispatcher.deliver_due(now(), &mut transport);
+        assert_eq!(report.retrying, vec![1]);
+        assert_eq!(dispatcher.pending()[0].next_attempt_at, now() + Duration::seconds(30));
+        assert_eq!(dispatcher.pending()[0].last_error.as_deref(), Some("Webhook receiver answered HTTP 503"));
+
+        // Not due yet, so nothing is sent
+        assert!(dispatcher.deliver_due(now() + Duration::seconds(29), &mut transport).retrying.is_empty());
+        assert_eq!(transport.sent.len(), 1);
+
+        let report = dispatcher.deliver_due(now() + Duration::seconds(30), &mut transport);
+        assert_eq!(report.dead_lettered, vec![1]);
+        assert!(dispatcher.pending().is_empty());
+        let dead = &dispatcher.dead_letters()[0];
+        assert_eq!(dead.attempts, 2);
+        assert_eq!(dead.last_error.as_deref(), Some("Webhook request failed: refused"));
+    }
+
+    #[test]
+    fn redelivery_needs_an_active_subscription() {
+        let mut dispatcher = dispatcher(1);
+        let id = dispatcher.subscribe("http://a.test/hooks", "a", &[EventKind::OrderStatusChanged]).unwrap();
+        dispatcher.publish(&order_event(), now());
+        dispatcher.publish(&order_event(), now());
+        let mut transport = ScriptedTransport::default();
+        transport.responses.extend([Some(500), Some(500)]);
+        dispatcher.deliver_due(now(), &mut transport);
+        assert_eq!(dispatcher.dead_letters().len(), 2);
+
+        assert!(matches!(dispatcher.redeliver(99, now()), Err(WebhookError::UnknownDelivery(99))));
+        dispatcher.redeliver(1, now()).unwrap();
+        assert_eq!((dispatcher.pending()[0].id, dispatcher.pending()[0].attempts), (1, 0));
+        assert_eq!(dispatcher.deliver_due(now(), &mut transport).delivered, vec![1]);
+
+        dispatcher.unsubscribe(id).unwrap();
+        assert!(matches!(dispatcher.redeliver(2, now()), Err(WebhookError::InactiveSubscription(1))));
+        assert_eq!(dispatcher.dead_letters().len(), 1);
+    }
+
+    #[test]
+    fn stock_events_fire_on_crossings_only() {
+        let mut dispatcher = dispatcher(3);
+        dispatcher.subscribe("http://a.test/hooks", "a", &[EventKind::StockThreshold]).unwrap();
+        dispatcher.watch_stock(1, 5);
+        let mut inventory = Inventory::new();
+        inventory.add_stock(1, 3).unwrap();
+
+        assert_eq!(dispatcher.check_stock(&inventory, now()).len(), 1);
+        assert!(dispatcher.check_stock(&inventory, now()).is_empty());
+        inventory.add_stock(1, 2).unwrap();
+        assert_eq!(dispatcher.check_stock(&inventory, now()).len(), 1);
+        let recovered: Value = serde_json::from_str(&dispatcher.pending()[1].body).unwrap();
+        assert_eq!((recovered["data"]["level"].as_u64(), recovered["data"]["below"].as_bool()), (Some(5), Some(false)));
+    }
+
+    #[test]
+    fn http_transport_posts_signed_requests() {
+        let receiver = StubReceiver::start("whsec_stub").unwrap();
+        let mut dispatcher = dispatcher(3);
+        dispatcher.subscribe(&receiver.url("/hooks"), "whsec_stub", &[EventKind::OrderStatusChanged]).unwrap();
+        dispatcher.publish(&order_event(), now());
+        let mut transport = HttpTransport::new().with_timeout(std::time::Duration::from_secs(2));
+
+        receiver.respond_with(&[503]);
+        assert_eq!(dispatcher.deliver_due(now(), &mut transport).retrying, vec![1]);
+        assert_eq!(dispatcher.deliver_due(now() + Duration::minutes(1), &mut transport).delivered, vec![1]);
+        let received = receiver.received();
+        assert_eq!(received.len(), 2);
+        assert!(received.iter().all(|r| r.signature_valid && r.path == "/hooks"));
+        assert_eq!(received[0].event.as_deref(), Some("order.status_changed"));
+    }
+
+    #[test]
+    fn http_transport_reports_refused_connections() {
+        // Bind then drop a listener to find a port nothing is listening on
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut transport = HttpTransport::new().with_timeout(std::time::Duration::from_millis(500));
+        let result = transport.post(&format!("http://127.0.0.1:{}/", port), &[], "{}");
+        assert!(matches!(result, Err(WebhookError::Io(_))));
+    }
+}